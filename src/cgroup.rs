@@ -0,0 +1,288 @@
+// Capping: move a matched process into its own cgroup v2 child of
+// `cgroup_root`, with `cpu.max` and `memory.max` taken from the rule, so a
+// runaway keeps running within limits instead of being killed.
+//
+// Each capped process gets a `cap-<pid>-<start ticks>` cgroup. The daemon
+// removes those whose processes have all exited.
+//
+// Also reads a process's cgroup membership from /proc/<pid>/cgroup, along
+// with the systemd unit and container ID its path names.
+
+use crate::{verify_identity, ActionResult, Process};
+
+// cpu.max quota and period are in microseconds; the kernel default period.
+const CPU_PERIOD_US: i64 = 100_000;
+const CAP_PREFIX: &str = "cap-";
+// Scope names container runtimes give their cgroups, before the ID.
+const CONTAINER_PREFIXES: [&str; 4] = ["docker-", "cri-containerd-", "crio-", "libpod-"];
+
+pub(crate) fn cap_cgroup_path(root: &str, proc: &Process) -> String {
+    format!("{}/{}{}-{}", root, CAP_PREFIX, proc.pid, proc.start_ticks)
+}
+
+pub(crate) fn is_capped(root: &str, proc: &Process) -> bool {
+    std::path::Path::new(&cap_cgroup_path(root, proc)).is_dir()
+}
+
+// `cpu.max` for a share of one CPU; 250% allows two and a half CPUs.
+pub(crate) fn cpu_max(cpu_percent: f64) -> String {
+    let quota = (cpu_percent * CPU_PERIOD_US as f64 / 100f64).round() as i64;
+    // The kernel refuses quotas under 1ms.
+    format!("{} {}", quota.max(1000), CPU_PERIOD_US)
+}
+
+// Creates the process's cgroup with the given limits (0 leaves a limit
+// unset) and moves the process into it.
+pub(crate) fn cap_process(
+    root: &str,
+    proc: &Process,
+    cpu_percent: f64,
+    memory_mb: i64,
+) -> ActionResult {
+    if let Some(refusal) = verify_identity(proc) {
+        return refusal;
+    }
+    let mut controllers: Vec<&str> = Vec::new();
+    let mut limits: Vec<(&str, String)> = Vec::new();
+    if cpu_percent > 0f64 {
+        controllers.push("+cpu");
+        limits.push(("cpu.max", cpu_max(cpu_percent)));
+    }
+    if memory_mb > 0 {
+        controllers.push("+memory");
+        limits.push(("memory.max", (memory_mb * 1024 * 1024).to_string()));
+    }
+    // Controllers have to be enabled in the parent before a child can
+    // use them.
+    let enabled = std::fs::create_dir_all(root).and_then(|()| {
+        let subtree_control = format!("{}/cgroup.subtree_control", root);
+        std::fs::write(subtree_control, controllers.join(" "))
+    });
+    if let Err(err) = enabled {
+        return io_result(&err);
+    }
+    let path = cap_cgroup_path(root, proc);
+    if let Err(err) = std::fs::create_dir(&path) {
+        return io_result(&err);
+    }
+    let mut moved = Ok(());
+    for (file, value) in &limits {
+        moved = moved.and_then(|()| std::fs::write(format!("{}/{}", path, file), value));
+    }
+    let procs_file = format!("{}/cgroup.procs", path);
+    let moved = moved.and_then(|()| std::fs::write(procs_file, proc.pid.to_string()));
+    match moved {
+        Ok(()) => ActionResult::Capped,
+        Err(err) => {
+            // An empty cgroup left behind would hide the process from the
+            // next attempt.
+            let _ = std::fs::remove_dir(&path);
+            io_result(&err)
+        }
+    }
+}
+
+// The cap cgroups under `root` whose processes have all exited.
+pub(crate) fn empty_cgroups(root: &str) -> Vec<String> {
+    let entries = match std::fs::read_dir(root) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut empty: Vec<String> = Vec::new();
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !name.starts_with(CAP_PREFIX) || !entry.path().is_dir() {
+            continue;
+        }
+        let path = format!("{}/{}", root, name);
+        let members = std::fs::read_to_string(format!("{}/cgroup.procs", path));
+        // Unreadable means not ours to judge; leave it.
+        if members.is_ok_and(|members| members.trim().is_empty()) {
+            empty.push(path);
+        }
+    }
+    empty.sort();
+    empty
+}
+
+// Removes `paths` and returns those that went. Only ever rmdir: on cgroupfs
+// that removes an empty cgroup, and a `cgroup_root` pointing anywhere else
+// keeps every directory that still holds files.
+pub(crate) fn remove_cgroups(paths: &[String]) -> Vec<String> {
+    paths
+        .iter()
+        .filter(|path| std::fs::remove_dir(path).is_ok())
+        .cloned()
+        .collect()
+}
+
+// The cgroup v2 path (`0::/...`). On a v1 or hybrid host that has none, the
+// systemd hierarchy's path, since that is where units are visible.
+pub(crate) fn parse_proc_cgroup(text: &str) -> String {
+    let mut systemd = None;
+    for line in text.lines() {
+        let mut fields = line.splitn(3, ':');
+        let (id, controllers, path) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(id), Some(controllers), Some(path)) => (id, controllers, path),
+            _ => continue,
+        };
+        if id == "0" && controllers.is_empty() {
+            return path.to_string();
+        }
+        if controllers == "name=systemd" {
+            systemd = Some(path.to_string());
+        }
+    }
+    systemd.unwrap_or_default()
+}
+
+// The innermost service or scope in `path`, which is the unit the process
+// runs in, e.g. `docker-<id>.scope` or `sshd.service`.
+pub(crate) fn systemd_unit(path: &str) -> String {
+    path.rsplit('/')
+        .find(|part| part.ends_with(".service") || part.ends_with(".scope"))
+        .unwrap_or("")
+        .to_string()
+}
+
+// The 64-hex-digit container ID named by the innermost component of `path`
+// that carries one, either bare (cgroupfs driver, Kubernetes) or wrapped in a
+// runtime's scope name (systemd driver).
+pub(crate) fn container_id(path: &str) -> String {
+    for part in path.rsplit('/') {
+        let part = part.strip_suffix(".scope").unwrap_or(part);
+        let id = CONTAINER_PREFIXES
+            .iter()
+            .find_map(|prefix| part.strip_prefix(prefix))
+            .unwrap_or(part);
+        if id.len() == 64 && id.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')) {
+            return id.to_string();
+        }
+    }
+    String::new()
+}
+
+fn io_result(err: &std::io::Error) -> ActionResult {
+    match err.raw_os_error() {
+        Some(errno) => crate::action_result_from_errno(errno),
+        None => ActionResult::Failed,
+    }
+}
+
+#[cfg(test)]
+fn fake_cgroup_root(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("reaper-cgroup-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&path);
+    path.to_string_lossy().to_string()
+}
+
+#[test]
+fn test_cpu_max_quota() {
+    assert!(cpu_max(50f64) == "50000 100000");
+    assert!(cpu_max(250f64) == "250000 100000");
+    assert!(
+        cpu_max(0.1) == "1000 100000",
+        "Clamped to the kernel minimum"
+    );
+}
+
+#[test]
+fn test_cap_process_in_fake_cgroupfs() {
+    let root = fake_cgroup_root("cap");
+    let mut child = std::process::Command::new("sleep")
+        .arg("30")
+        .spawn()
+        .expect("spawn sleep");
+    let proc = crate::parse_proc_status(child.id() as i32).expect("spawned child");
+    let result = cap_process(&root, &proc, 50f64, 512);
+    let path = cap_cgroup_path(&root, &proc);
+    let read = |file: &str| std::fs::read_to_string(format!("{}/{}", path, file));
+    let cpu = read("cpu.max");
+    let memory = read("memory.max");
+    let members = read("cgroup.procs");
+    let controllers = std::fs::read_to_string(format!("{}/cgroup.subtree_control", root));
+    let mut recycled = proc.clone();
+    recycled.start_ticks += 1;
+    let refused = cap_process(&root, &recycled, 50f64, 0);
+    let _ = child.kill();
+    let _ = child.wait();
+    assert!(result == ActionResult::Capped);
+    assert!(is_capped(&root, &proc));
+    assert!(cpu.expect("cpu.max") == "50000 100000");
+    assert!(memory.expect("memory.max") == "536870912");
+    assert!(members.expect("cgroup.procs") == proc.pid.to_string());
+    assert!(controllers.expect("subtree_control") == "+cpu +memory");
+    assert!(refused == ActionResult::IdentityMismatch);
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_remove_empty_cgroups_keeps_occupied_ones() {
+    let root = fake_cgroup_root("cleanup");
+    for (name, members) in [
+        ("cap-4242-10", ""),
+        ("cap-4343-11", "4343\n"),
+        ("other", ""),
+    ] {
+        let path = format!("{}/{}", root, name);
+        std::fs::create_dir_all(&path).expect("create cgroup");
+        std::fs::write(format!("{}/cgroup.procs", path), members).expect("write procs");
+        std::fs::write(format!("{}/cpu.max", path), "50000 100000").expect("write cpu.max");
+    }
+    let empty = empty_cgroups(&root);
+    assert!(empty == vec![format!("{}/cap-4242-10", root)]);
+    assert!(
+        remove_cgroups(&empty).is_empty(),
+        "A plain directory holding files is never deleted"
+    );
+    // cgroupfs interface files vanish with the cgroup; the fixture's do not.
+    for file in ["cgroup.procs", "cpu.max"] {
+        std::fs::remove_file(format!("{}/{}", empty[0], file)).expect("remove fixture file");
+    }
+    assert!(remove_cgroups(&empty) == empty);
+    assert!(std::path::Path::new(&format!("{}/cap-4343-11", root)).is_dir());
+    assert!(
+        std::path::Path::new(&format!("{}/other", root)).is_dir(),
+        "Only cap cgroups are reaper's to remove"
+    );
+    assert!(empty_cgroups(&format!("{}/missing", root)).is_empty());
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_parse_proc_cgroup_v2_and_hybrid() {
+    let unified = "0::/system.slice/sshd.service\n";
+    assert!(parse_proc_cgroup(unified) == "/system.slice/sshd.service");
+    let hybrid = "12:cpu,cpuacct:/user.slice\n\
+                  1:name=systemd:/user.slice/user-1000.slice/session-3.scope\n0::/\n";
+    assert!(
+        parse_proc_cgroup(hybrid) == "/",
+        "The v2 line wins when present"
+    );
+    let legacy = "12:cpu,cpuacct:/\n1:name=systemd:/system.slice/cron.service\n";
+    assert!(parse_proc_cgroup(legacy) == "/system.slice/cron.service");
+    assert!(parse_proc_cgroup("").is_empty());
+}
+
+#[test]
+fn test_systemd_unit_and_container_id() {
+    let id = "4f1c2b3a5d6e7f8091a2b3c4d5e6f708192a3b4c5d6e7f8091a2b3c4d5e6f708";
+    let docker = format!("/system.slice/docker-{}.scope", id);
+    assert!(systemd_unit(&docker) == format!("docker-{}.scope", id));
+    assert!(container_id(&docker) == id);
+    let kubepods = format!(
+        "/kubepods/burstable/pod0a1b2c3d-1111-2222-3333-444455556666/{}",
+        id
+    );
+    assert!(container_id(&kubepods) == id && systemd_unit(&kubepods).is_empty());
+    let podman = format!(
+        "/user.slice/user-1000.slice/user@1000.service/user.slice/libpod-{}.scope/container",
+        id
+    );
+    assert!(container_id(&podman) == id);
+    assert!(systemd_unit(&podman) == format!("libpod-{}.scope", id));
+    let session = "/user.slice/user-1000.slice/user@1000.service/app.slice/app-term.scope";
+    assert!(systemd_unit(session) == "app-term.scope" && container_id(session).is_empty());
+    assert!(systemd_unit("/system.slice/sshd.service") == "sshd.service");
+    assert!(container_id(&format!("/system.slice/crio-conmon-{}.scope", id)).is_empty());
+}
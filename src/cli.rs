@@ -1 +1,675 @@
-pub fn parse_args () -> Command { Command :: Scan } pub fn execute (command : Command) -> bool { println ! ("Reaper v0.1.0 - Module structure initialized") ; println ! ("All modules compiled successfully!") ; true } pub fn display_results (critical : u32 , high : u32 , low : u32) { println ! ("Rogue processes detected:") ; println ! ("  Critical: {}" , critical) ; println ! ("  High: {}" , high) ; println ! ("  Low: {}" , low) ; } fn main () { # [derive (Debug , Clone , PartialEq)] enum Command { Scan , Status , } }
\ No newline at end of file
+// Command-line interface: argv parsing and subcommand dispatch.
+
+use crate::detector::{format_match_table, format_rule_match, RuleMatch};
+use crate::frozen::resume_verified;
+use crate::frozen::{entry_process, format_frozen_table, load_frozen, remove_frozen};
+use crate::logger::{log_event, new_audit_logger, AuditEvent};
+use crate::output::OutputFormat;
+use crate::output::{matches_csv, matches_json, parse_output_format, rules_csv, rules_json};
+use crate::safety::{new_safety_gate, protection_reason};
+use crate::terminate_verified;
+use crate::{apply_rules, daemon_loop, dry_run_verdict, format_process, format_rule};
+use crate::{is_valid_config, is_valid_rule, parse_proc_status, pending_rules, scan_processes};
+use crate::{ActionResult, Config, DetectionRule, PROC_ROOT};
+
+pub(crate) const DEFAULT_CONFIG_PATH: &str = "/etc/reaper.toml";
+
+pub(crate) const USAGE: &str = "\
+Usage: ruchy-reaper <COMMAND> [OPTIONS]
+
+Commands:
+  scan                 List running processes that match the configured rules;
+                       sustained rules show as pending, since a scan is one sample
+  daemon               Scan and terminate matches every check interval
+  kill <PID>           Terminate one process (SIGTERM, grace period, SIGKILL)
+  frozen               List processes halted by stop rules
+  resume <PID>         Continue a frozen process (SIGCONT)
+  rules                List the configured detection rules
+  check-config [FILE]  Validate a config file and its rules
+  help                 Show this message
+
+Options:
+  --config <FILE>      Config file (default: /etc/reaper.toml)
+  --dry-run            Report what would be terminated without signalling
+  --interval <SECS>    Override check_interval_secs
+  --output <FORMAT>    scan/rules output: table (default), json or csv
+  -h, --help           Show this message
+  -V, --version        Show the version";
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Command {
+    Scan,
+    Daemon,
+    Kill(i32),
+    Frozen,
+    Resume(i32),
+    Rules,
+    CheckConfig,
+    Help,
+    Version,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct CliArgs {
+    pub(crate) command: Command,
+    // None means the default path, which is allowed to be absent.
+    pub(crate) config_path: Option<String>,
+    pub(crate) dry_run: bool,
+    pub(crate) interval: Option<i64>,
+    pub(crate) output: OutputFormat,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Invocation {
+    pub(crate) command: Command,
+    pub(crate) config: Config,
+    pub(crate) config_path: String,
+    pub(crate) output: OutputFormat,
+}
+
+// Parses argv (including the program name) without touching the filesystem.
+pub(crate) fn parse_cli(args: &[String]) -> Result<CliArgs, String> {
+    let mut command: Option<Command> = None;
+    let mut config_path: Option<String> = None;
+    let mut dry_run = false;
+    let mut interval: Option<i64> = None;
+    let mut output: Option<OutputFormat> = None;
+    let mut positionals: Vec<String> = Vec::new();
+    let mut rest = args.iter().skip(1);
+    while let Some(arg) = rest.next() {
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if arg.starts_with("--") => (flag, Some(value.to_string())),
+            _ => (arg.as_str(), None),
+        };
+        match flag {
+            "--config" | "--interval" | "--output" => {
+                let value = match inline_value {
+                    Some(value) => value,
+                    None => rest
+                        .next()
+                        .cloned()
+                        .ok_or(format!("{} needs a value", flag))?,
+                };
+                match flag {
+                    "--config" => config_path = Some(value),
+                    "--interval" => interval = Some(parse_interval(&value)?),
+                    _ => match parse_output_format(&value) {
+                        Some(format) => output = Some(format),
+                        None => {
+                            return Err(format!(
+                                "--output must be table, json or csv, got `{}`",
+                                value
+                            ))
+                        }
+                    },
+                }
+            }
+            "--dry-run" => dry_run = true,
+            "-h" | "--help" => command = Some(Command::Help),
+            "-V" | "--version" => command = Some(Command::Version),
+            _ if flag.starts_with('-') && flag.len() > 1 => {
+                return Err(format!("unknown option `{}`", arg));
+            }
+            _ => positionals.push(arg.clone()),
+        }
+    }
+    if command.is_none() {
+        command = Some(parse_command(&positionals, &mut config_path)?);
+    }
+    let command = command.unwrap_or(Command::Help);
+    if output.is_some() && !matches!(command, Command::Scan | Command::Rules) {
+        return Err("--output only applies to `scan` and `rules`".to_string());
+    }
+    Ok(CliArgs {
+        command,
+        config_path,
+        dry_run,
+        interval,
+        output: output.unwrap_or(OutputFormat::Table),
+    })
+}
+
+fn parse_command(
+    positionals: &[String],
+    config_path: &mut Option<String>,
+) -> Result<Command, String> {
+    let name = match positionals.first() {
+        Some(name) => name.as_str(),
+        None => return Err("missing command".to_string()),
+    };
+    let operands = &positionals[1..];
+    let expected_operands = match name {
+        "kill" | "resume" => 1,
+        "check-config" if config_path.is_none() => operands.len().min(1),
+        _ => 0,
+    };
+    if operands.len() != expected_operands {
+        return Err(format!("unexpected arguments for `{}`", name));
+    }
+    match name {
+        "scan" => Ok(Command::Scan),
+        "daemon" => Ok(Command::Daemon),
+        "rules" => Ok(Command::Rules),
+        "frozen" => Ok(Command::Frozen),
+        "help" => Ok(Command::Help),
+        "check-config" => {
+            if let Some(file) = operands.first() {
+                *config_path = Some(file.clone());
+            }
+            Ok(Command::CheckConfig)
+        }
+        "kill" | "resume" => match operands[0].parse::<i32>() {
+            Ok(pid) if pid > 0 && name == "kill" => Ok(Command::Kill(pid)),
+            Ok(pid) if pid > 0 => Ok(Command::Resume(pid)),
+            _ => Err(format!("invalid PID `{}`", operands[0])),
+        },
+        _ => Err(format!("unknown command `{}`", name)),
+    }
+}
+
+fn parse_interval(value: &str) -> Result<i64, String> {
+    match value.parse::<i64>() {
+        Ok(secs) if secs > 0 => Ok(secs),
+        _ => Err(format!(
+            "--interval must be a positive number of seconds, got `{}`",
+            value
+        )),
+    }
+}
+
+// Loads the config named on the command line and applies flag overrides.
+pub(crate) fn resolve(args: CliArgs) -> Result<Invocation, String> {
+    let explicit = args.config_path.is_some();
+    let config_path = args
+        .config_path
+        .unwrap_or_else(|| DEFAULT_CONFIG_PATH.to_string());
+    if explicit && !std::path::Path::new(&config_path).exists() {
+        return Err(format!("{}: config file not found", config_path));
+    }
+    let mut config = match args.command {
+        Command::Help | Command::Version => crate::default_config(),
+        _ => crate::load_config(config_path.clone()).map_err(|err| err.to_string())?,
+    };
+    if args.dry_run {
+        config.dry_run = true;
+    }
+    if let Some(interval) = args.interval {
+        config.check_interval_secs = interval;
+    }
+    Ok(Invocation {
+        command: args.command,
+        config,
+        config_path,
+        output: args.output,
+    })
+}
+
+// Runs the command and returns the process exit code.
+pub(crate) fn execute(invocation: Invocation) -> i32 {
+    let config = invocation.config;
+    match invocation.command {
+        Command::Help => {
+            println!("{}", USAGE);
+            0
+        }
+        Command::Version => {
+            println!("ruchy-reaper {}", env!("CARGO_PKG_VERSION"));
+            0
+        }
+        Command::Scan => {
+            let processes = scan_processes(config.cpu_normalization);
+            let scanned = processes.len();
+            let matched = apply_rules(processes.clone(), config.rules.clone());
+            let pending = pending_rules(processes, &config.rules, &matched);
+            let rows = if config.dry_run {
+                dry_run_rows(&matched, &config)
+            } else {
+                matched
+                    .into_iter()
+                    .map(|rule_match| (rule_match, None))
+                    .collect()
+            };
+            print!(
+                "{}",
+                render_scan(&rows, &pending, &config, invocation.output, scanned)
+            );
+            0
+        }
+        Command::Daemon => {
+            daemon_loop(config);
+            0
+        }
+        Command::Kill(pid) => kill_command(pid, config),
+        Command::Frozen => {
+            print!("{}", format_frozen_table(&load_frozen(&config.frozen_file)));
+            0
+        }
+        Command::Resume(pid) => resume_command(pid, config),
+        Command::Rules => {
+            print!("{}", render_rules(&config.rules, invocation.output));
+            0
+        }
+        Command::CheckConfig => check_config_command(&invocation.config_path, config),
+    }
+}
+
+// Runs the safety gate over every match, logs each verdict and pairs each
+// match with its dry-run action. Nothing is signalled.
+fn dry_run_rows(matched: &[RuleMatch], config: &Config) -> Vec<(RuleMatch, Option<String>)> {
+    let gate = new_safety_gate(config);
+    let audit = new_audit_logger(config.log_file.clone());
+    matched
+        .iter()
+        .map(|rule_match| {
+            let (action, _) = dry_run_verdict(rule_match, &gate, &audit, PROC_ROOT);
+            (rule_match.clone(), Some(action))
+        })
+        .collect()
+}
+
+// Rows carry a dry-run action only in dry run. Pending rows, sustained rules
+// a single scan cannot confirm, follow with their progress as the action.
+fn render_scan(
+    rows: &[(RuleMatch, Option<String>)],
+    pending: &[(RuleMatch, String)],
+    config: &Config,
+    output: OutputFormat,
+    scanned: usize,
+) -> String {
+    let mut all = rows.to_vec();
+    all.extend(
+        pending
+            .iter()
+            .map(|(rule_match, label)| (rule_match.clone(), Some(label.clone()))),
+    );
+    match output {
+        OutputFormat::Json => matches_json(&all),
+        OutputFormat::Csv => matches_csv(&all),
+        OutputFormat::Table => {
+            let mut text = if config.dry_run {
+                let report: Vec<(RuleMatch, String)> = all
+                    .iter()
+                    .map(|(rule_match, action)| {
+                        (rule_match.clone(), action.clone().unwrap_or_default())
+                    })
+                    .collect();
+                format_match_table(&report)
+            } else {
+                all.iter()
+                    .map(|(rule_match, action)| match action {
+                        Some(label) => format!("{} [{}]\n", format_rule_match(rule_match), label),
+                        None => format!("{}\n", format_rule_match(rule_match)),
+                    })
+                    .collect()
+            };
+            text.push_str(&format!(
+                "{} processes scanned, {} matched",
+                scanned,
+                rows.len()
+            ));
+            if !pending.is_empty() {
+                text.push_str(&format!(", {} pending sustain", pending.len()));
+            }
+            text.push('\n');
+            text
+        }
+    }
+}
+
+fn render_rules(rules: &[DetectionRule], output: OutputFormat) -> String {
+    match output {
+        OutputFormat::Json => rules_json(rules),
+        OutputFormat::Csv => rules_csv(rules),
+        OutputFormat::Table if rules.is_empty() => "No rules configured\n".to_string(),
+        OutputFormat::Table => rules
+            .iter()
+            .map(|rule| format!("{}\n", format_rule(rule.clone())))
+            .collect(),
+    }
+}
+
+fn kill_command(pid: i32, config: Config) -> i32 {
+    let proc = match parse_proc_status(pid) {
+        Some(proc) => proc,
+        None => {
+            eprintln!("ruchy-reaper: no process with PID {}", pid);
+            return 1;
+        }
+    };
+    let audit = new_audit_logger(config.log_file.clone());
+    if let Some(reason) = protection_reason(&new_safety_gate(&config), &proc) {
+        let refused = Some(ActionResult::Protected);
+        log_event(
+            &audit,
+            AuditEvent::TerminateResult,
+            &proc,
+            None,
+            config.dry_run,
+            refused,
+        );
+        eprintln!(
+            "ruchy-reaper: refusing to terminate {}: {}",
+            format_process(proc),
+            reason
+        );
+        return 1;
+    }
+    if config.dry_run {
+        log_event(&audit, AuditEvent::WouldTerminate, &proc, None, true, None);
+        println!("dry run: would terminate {}", format_process(proc));
+        return 0;
+    }
+    log_event(
+        &audit,
+        AuditEvent::TerminateAttempt,
+        &proc,
+        None,
+        false,
+        None,
+    );
+    let result = terminate_verified(proc.clone(), config.grace_period_secs);
+    log_event(
+        &audit,
+        AuditEvent::TerminateResult,
+        &proc,
+        None,
+        false,
+        Some(result),
+    );
+    println!("terminate {}: {:?}", format_process(proc), result);
+    match result {
+        ActionResult::Success => 0,
+        _ => 1,
+    }
+}
+
+fn resume_command(pid: i32, config: Config) -> i32 {
+    let entry = match load_frozen(&config.frozen_file)
+        .into_iter()
+        .find(|entry| entry.pid == pid)
+    {
+        Some(entry) => entry,
+        None => {
+            eprintln!("ruchy-reaper: PID {} was not frozen by reaper", pid);
+            return 1;
+        }
+    };
+    let proc = entry_process(&entry);
+    if config.dry_run {
+        println!("dry run: would resume {}", format_process(proc));
+        return 0;
+    }
+    let result = resume_verified(&entry);
+    // Keep the record only while the process may still be resumed.
+    if !matches!(
+        result,
+        ActionResult::PermissionDenied | ActionResult::Failed
+    ) {
+        if let Err(err) = remove_frozen(&config.frozen_file, pid) {
+            eprintln!(
+                "ruchy-reaper: cannot update {}: {}",
+                config.frozen_file, err
+            );
+        }
+    }
+    let audit = new_audit_logger(config.log_file.clone());
+    log_event(&audit, AuditEvent::Resume, &proc, None, false, Some(result));
+    println!("resume {}: {:?}", format_process(proc), result);
+    match result {
+        ActionResult::Resumed => 0,
+        _ => 1,
+    }
+}
+
+fn check_config_command(path: &str, config: Config) -> i32 {
+    let mut valid = is_valid_config(config.clone());
+    for rule in &config.rules {
+        let rule_ok = is_valid_rule(rule.clone());
+        valid = valid && rule_ok;
+        let verdict = if rule_ok { "ok" } else { "INVALID" };
+        println!("{}: {}", verdict, format_rule(rule.clone()));
+    }
+    if valid {
+        println!("{}: ok ({} rules)", path, config.rules.len());
+        0
+    } else {
+        println!("{}: invalid configuration", path);
+        1
+    }
+}
+
+#[cfg(test)]
+fn argv(args: &[&str]) -> Vec<String> {
+    let mut all = vec!["ruchy-reaper".to_string()];
+    all.extend(args.iter().map(|arg| arg.to_string()));
+    all
+}
+
+#[test]
+fn test_parse_cli_subcommands() {
+    assert!(parse_cli(&argv(&["scan"])).expect("scan").command == Command::Scan);
+    assert!(parse_cli(&argv(&["daemon"])).expect("daemon").command == Command::Daemon);
+    assert!(parse_cli(&argv(&["rules"])).expect("rules").command == Command::Rules);
+    assert!(parse_cli(&argv(&["kill", "4242"])).expect("kill").command == Command::Kill(4242));
+    assert!(parse_cli(&argv(&["frozen"])).expect("frozen").command == Command::Frozen);
+    let resume = parse_cli(&argv(&["resume", "4242"])).expect("resume");
+    assert!(resume.command == Command::Resume(4242));
+    assert!(parse_cli(&argv(&["resume"])).is_err());
+    let args = parse_cli(&argv(&["check-config", "/tmp/r.toml"])).expect("check-config");
+    assert!(args.command == Command::CheckConfig);
+    assert!(args.config_path == Some("/tmp/r.toml".to_string()));
+}
+
+#[test]
+fn test_parse_cli_flags() {
+    let args = parse_cli(&argv(&[
+        "--config",
+        "r.toml",
+        "daemon",
+        "--dry-run",
+        "--interval=5",
+    ]))
+    .expect("flags");
+    assert!(args.command == Command::Daemon && args.dry_run);
+    assert!(args.config_path == Some("r.toml".to_string()));
+    assert!(args.interval == Some(5));
+    assert!(parse_cli(&argv(&["scan", "--help"])).expect("help").command == Command::Help);
+}
+
+#[test]
+fn test_parse_cli_errors() {
+    assert!(parse_cli(&argv(&[])).is_err(), "A command is required");
+    assert!(parse_cli(&argv(&["explode"])).is_err());
+    assert!(parse_cli(&argv(&["kill"])).is_err(), "kill needs a PID");
+    assert!(parse_cli(&argv(&["kill", "-3"])).is_err());
+    assert!(parse_cli(&argv(&["kill", "abc"])).is_err());
+    assert!(parse_cli(&argv(&["scan", "extra"])).is_err());
+    assert!(parse_cli(&argv(&["scan", "--interval", "0"])).is_err());
+    assert!(parse_cli(&argv(&["scan", "--config"])).is_err());
+    assert!(parse_cli(&argv(&["scan", "--frobnicate"])).is_err());
+}
+
+#[test]
+fn test_resolve_overrides_file_values() {
+    let path = std::env::temp_dir().join(format!("reaper-cli-{}.toml", std::process::id()));
+    std::fs::write(&path, "check_interval_secs = 30\ndry_run = false\n").expect("write config");
+    let path = path.to_string_lossy().to_string();
+    let args = parse_cli(&argv(&["scan", "--config", &path])).expect("args");
+    let invocation = resolve(args).expect("resolve");
+    assert!(invocation.config.check_interval_secs == 30 && !invocation.config.dry_run);
+    let args = parse_cli(&argv(&[
+        "scan",
+        "--config",
+        &path,
+        "--dry-run",
+        "--interval",
+        "7",
+    ]));
+    let invocation = resolve(args.expect("args")).expect("resolve");
+    assert!(invocation.config.check_interval_secs == 7 && invocation.config.dry_run);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_resolve_explicit_missing_config() {
+    let args = parse_cli(&argv(&["rules", "--config", "/nonexistent/reaper.toml"]));
+    let error = resolve(args.expect("args")).expect_err("missing file");
+    assert!(error.contains("/nonexistent/reaper.toml"));
+}
+
+#[test]
+fn test_check_config_command_exit_codes() {
+    let mut config = crate::default_config();
+    assert!(check_config_command("ok.toml", config.clone()) == 0);
+    config.rules.push(crate::new_detection_rule(
+        String::new(),
+        crate::Priority::Low,
+        0f64,
+        0,
+        String::new(),
+        String::new(),
+        true,
+    ));
+    assert!(
+        check_config_command("bad.toml", config) == 1,
+        "Unnamed rule is invalid"
+    );
+}
+
+#[test]
+fn test_kill_command_missing_pid() {
+    assert!(kill_command(i32::MAX, crate::default_config()) == 1);
+}
+
+#[test]
+fn test_kill_command_refuses_protected_pid() {
+    let mut config = crate::default_config();
+    config.log_file = std::env::temp_dir()
+        .join(format!("reaper-kill-protected-{}.log", std::process::id()))
+        .to_string_lossy()
+        .to_string();
+    assert!(kill_command(std::process::id() as i32, config.clone()) == 1);
+    let audit = std::fs::read_to_string(&config.log_file).expect("audit log");
+    assert!(audit.contains("\"result\":\"protected\""));
+    let _ = std::fs::remove_file(&config.log_file);
+}
+
+#[test]
+fn test_resume_command_continues_frozen_child() {
+    let mut child = std::process::Command::new("sleep")
+        .arg("30")
+        .spawn()
+        .expect("spawn sleep");
+    let proc = parse_proc_status(child.id() as i32).expect("spawned child");
+    let dir = std::env::temp_dir().join(format!("reaper-resume-{}", std::process::id()));
+    let mut config = crate::default_config();
+    config.frozen_file = dir.join("frozen.tsv").to_string_lossy().to_string();
+    config.log_file = dir.join("audit.log").to_string_lossy().to_string();
+    assert!(
+        resume_command(proc.pid, config.clone()) == 1,
+        "Not frozen by reaper"
+    );
+    assert!(crate::frozen::freeze_verified(&proc) == ActionResult::Frozen);
+    let entry = crate::frozen::new_frozen_entry(&proc, "Spin", 1761919387);
+    crate::frozen::record_frozen(&config.frozen_file, entry).expect("record");
+    let resumed = resume_command(proc.pid, config.clone());
+    let audit = std::fs::read_to_string(&config.log_file).expect("audit log");
+    let _ = child.kill();
+    let _ = child.wait();
+    assert!(resumed == 0);
+    assert!(
+        load_frozen(&config.frozen_file).is_empty(),
+        "The record is dropped"
+    );
+    assert!(audit.contains("\"event\":\"resume\"") && audit.contains("\"result\":\"resumed\""));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_dry_run_report_marks_protected_matches() {
+    let mut config = crate::default_config();
+    config.rules = vec![crate::new_detection_rule(
+        "Spin".to_string(),
+        crate::Priority::High,
+        90f64,
+        0,
+        String::new(),
+        String::new(),
+        true,
+    )];
+    config.log_file = std::env::temp_dir()
+        .join(format!("reaper-dry-report-{}.log", std::process::id()))
+        .to_string_lossy()
+        .to_string();
+    let mut me = parse_proc_status(std::process::id() as i32).expect("own process");
+    me.cpu_usage = 99.9;
+    let mut other = me.clone();
+    other.pid = i32::MAX;
+    other.name = "spin".to_string();
+    let matched = apply_rules(vec![me, other], config.rules.clone());
+    config.dry_run = true;
+    let rows = dry_run_rows(&matched, &config);
+    let table = render_scan(&rows, &[], &config, OutputFormat::Table, 2);
+    assert!(table.lines().count() == 4 && table.contains("protected: reaper itself"));
+    assert!(table.ends_with("2 processes scanned, 2 matched\n"));
+    assert!(table.contains("would terminate"));
+    let audit = std::fs::read_to_string(&config.log_file).expect("audit log");
+    assert!(audit.contains("\"event\":\"would_terminate\""));
+    assert!(audit.contains("\"result\":\"protected\""));
+    let _ = std::fs::remove_file(&config.log_file);
+}
+
+#[test]
+fn test_scan_lists_pending_sustained_rules() {
+    let mut config = crate::default_config();
+    config.rules = vec![crate::detector::preset_rule("infinite-loop").expect("preset")];
+    let mut spin = crate::new_process(
+        4242,
+        "spin".to_string(),
+        "./spin".to_string(),
+        99.9,
+        64,
+        crate::ProcessStatus::Running,
+    );
+    spin.age_seconds = 600;
+    let mut idle = spin.clone();
+    idle.pid = 4343;
+    idle.cpu_usage = 0.1;
+    let processes = vec![spin, idle];
+    let matched = apply_rules(processes.clone(), config.rules.clone());
+    let pending = pending_rules(processes, &config.rules, &matched);
+    assert!(matched.is_empty(), "One sample cannot sustain a rule");
+    assert!(pending.len() == 1 && pending[0].0.process.pid == 4242);
+    assert!(pending[0].1 == "pending (0/300s)");
+    let table = render_scan(&[], &pending, &config, OutputFormat::Table, 2);
+    assert!(table.contains("Infinite loop") && table.contains("[pending (0/300s)]"));
+    assert!(table.ends_with("2 processes scanned, 0 matched, 1 pending sustain\n"));
+    config.dry_run = true;
+    let table = render_scan(&[], &pending, &config, OutputFormat::Table, 2);
+    assert!(table.contains("pending (0/300s)"));
+    let json = render_scan(&[], &pending, &config, OutputFormat::Json, 2);
+    assert!(json.contains("\"action\":\"pending (0/300s)\""));
+}
+
+#[test]
+fn test_parse_cli_output_format() {
+    let args = parse_cli(&argv(&["scan", "--output", "json"])).expect("json");
+    assert!(args.output == OutputFormat::Json);
+    let args = parse_cli(&argv(&["rules", "--output=csv"])).expect("csv");
+    assert!(args.output == OutputFormat::Csv);
+    assert!(parse_cli(&argv(&["scan"])).expect("default").output == OutputFormat::Table);
+    assert!(parse_cli(&argv(&["scan", "--output", "xml"])).is_err());
+    assert!(parse_cli(&argv(&["daemon", "--output", "json"])).is_err());
+}
+
+#[test]
+fn test_render_rules_formats() {
+    assert!(render_rules(&[], OutputFormat::Table) == "No rules configured\n");
+    assert!(render_rules(&[], OutputFormat::Json) == "[]\n");
+    let preset = crate::detector::preset_rule("hung-test").expect("preset");
+    let csv = render_rules(std::slice::from_ref(&preset), OutputFormat::Csv);
+    assert!(csv.lines().count() == 2);
+    assert!(csv.contains(",86400,false,0,0,hung-test,terminate-tree,leaves-first,19,0,0,"));
+    let table = render_rules(&[preset], OutputFormat::Table);
+    assert!(table.starts_with("Rule: Hung test [medium]"));
+}
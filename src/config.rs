@@ -1 +1,884 @@
-pub struct Config { pub scan_interval_seconds : u64 , pub dry_run : bool , pub min_pid : u32 , } pub fn default_config () -> Config { Config { scan_interval_seconds : 60 , dry_run : false , min_pid : 1000 , } } pub fn load_config (path : & str) -> Config { default_config () } pub fn validate_config (config : & Config) -> bool { config . scan_interval_seconds > 0 && config . min_pid > 0 } fn main () { }
\ No newline at end of file
+// TOML configuration loading.
+//
+// The toml crate parses the file; reaper then reads top-level settings and
+// an array of `[[rules]]` tables from it. Anything it does not know is
+// rejected with the file, line and key, taken from the crate's spans, so a
+// bad config never silently turns into defaults.
+
+use crate::detector::{compile_rule_patterns, preset_rule, PRESET_NAMES};
+use crate::{default_config, is_valid_config, is_valid_rule, new_detection_rule};
+use crate::{parse_cpu_normalization, parse_match_mode, parse_rule_action, parse_tree_signal};
+use crate::{Config, CpuNormalization, DetectionRule, MatchMode, Priority, RuleAction, TreeSignal};
+use toml::de::{DeString, DeTable, DeValue};
+use toml::Spanned;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ConfigError {
+    pub(crate) file: String,
+    pub(crate) line: usize,
+    pub(crate) key: String,
+    pub(crate) message: String,
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.file)?;
+        if self.line > 0 {
+            write!(f, ":{}", self.line)?;
+        }
+        if !self.key.is_empty() {
+            write!(f, ": {}", self.key)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum TomlValue {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Datetime(String),
+    Array(Vec<TomlValue>),
+    Table(Vec<Entry>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Entry {
+    pub(crate) line: usize,
+    pub(crate) key: String,
+    pub(crate) value: TomlValue,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct RuleTable {
+    pub(crate) line: usize,
+    pub(crate) entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Document {
+    pub(crate) top: Vec<Entry>,
+    pub(crate) rules: Vec<RuleTable>,
+}
+
+// A parse failure before the file name is known: (line, key, message).
+type ParseFailure = (usize, String, String);
+
+pub(crate) fn parse_config(text: &str, file: &str) -> Result<Config, ConfigError> {
+    let error = |(line, key, message): ParseFailure| ConfigError {
+        file: file.to_string(),
+        line,
+        key,
+        message,
+    };
+    let document = parse_document(text).map_err(error)?;
+    config_from_document(&document).map_err(error)
+}
+
+pub(crate) fn parse_document(text: &str) -> Result<Document, ParseFailure> {
+    let table = DeTable::parse(text).map_err(|err| syntax_failure(text, &err))?;
+    let mut document = Document::default();
+    for (key, value) in table.get_ref() {
+        let line = line_of(text, key.span().start);
+        if key.get_ref() != "rules" {
+            document.top.push(entry(text, key, value)?);
+            continue;
+        }
+        let items = match value.get_ref() {
+            DeValue::Array(items) => items,
+            _ => return Err((line, "rules".to_string(), RULES_EXPECTED.to_string())),
+        };
+        for item in items.iter() {
+            match item.get_ref() {
+                DeValue::Table(table) => document.rules.push(RuleTable {
+                    line: line_of(text, item.span().start),
+                    entries: entries(text, table)?,
+                }),
+                _ => return Err((line, "rules".to_string(), RULES_EXPECTED.to_string())),
+            }
+        }
+    }
+    Ok(document)
+}
+
+const RULES_EXPECTED: &str = "expected [[rules]] tables";
+
+fn entries(text: &str, table: &DeTable) -> Result<Vec<Entry>, ParseFailure> {
+    table
+        .iter()
+        .map(|(key, value)| entry(text, key, value))
+        .collect()
+}
+
+fn entry(
+    text: &str,
+    key: &Spanned<DeString>,
+    value: &Spanned<DeValue>,
+) -> Result<Entry, ParseFailure> {
+    let line = line_of(text, key.span().start);
+    let key = key.get_ref().to_string();
+    match toml_value(text, value.get_ref()) {
+        Ok(value) => Ok(Entry { line, key, value }),
+        Err(message) => Err((line, key, message)),
+    }
+}
+
+fn toml_value(text: &str, value: &DeValue) -> Result<TomlValue, String> {
+    match value {
+        DeValue::String(value) => Ok(TomlValue::String(value.to_string())),
+        DeValue::Integer(value) => match i64::from_str_radix(value.as_str(), value.radix()) {
+            Ok(integer) => Ok(TomlValue::Integer(integer)),
+            Err(_) => Err(format!("integer `{}` out of range", value)),
+        },
+        DeValue::Float(value) => match value.as_str().parse::<f64>() {
+            Ok(float) => Ok(TomlValue::Float(float)),
+            Err(_) => Err(format!("invalid float `{}`", value)),
+        },
+        DeValue::Boolean(value) => Ok(TomlValue::Boolean(*value)),
+        DeValue::Datetime(value) => Ok(TomlValue::Datetime(value.to_string())),
+        DeValue::Array(items) => items
+            .iter()
+            .map(|item| toml_value(text, item.get_ref()))
+            .collect::<Result<Vec<_>, _>>()
+            .map(TomlValue::Array),
+        DeValue::Table(table) => entries(text, table)
+            .map(TomlValue::Table)
+            .map_err(|(_, _, message)| message),
+    }
+}
+
+// The toml crate reports a byte span; report its line, and the key written
+// on that line when the error comes after one.
+fn syntax_failure(text: &str, err: &toml::de::Error) -> ParseFailure {
+    let offset = err.span().map_or(0, |span| span.start).min(text.len());
+    let line_start = text[..offset].rfind('\n').map_or(0, |newline| newline + 1);
+    let key = match text[line_start..offset].split_once('=') {
+        Some((key, _)) => key.trim().to_string(),
+        None => String::new(),
+    };
+    (line_of(text, offset), key, err.message().to_string())
+}
+
+fn line_of(text: &str, offset: usize) -> usize {
+    text[..offset.min(text.len())].matches('\n').count() + 1
+}
+
+fn config_from_document(document: &Document) -> Result<Config, ParseFailure> {
+    let mut config = default_config();
+    let mut presets: Vec<DetectionRule> = Vec::new();
+    for entry in &document.top {
+        match entry.key.as_str() {
+            "presets" => {
+                for name in expect_string_array(entry)? {
+                    let rule = expect_preset(entry, &name)?;
+                    if presets.iter().any(|other| other.name == rule.name) {
+                        return Err(failure(entry, &duplicate_rule(&rule)));
+                    }
+                    presets.push(rule);
+                }
+            }
+            "check_interval_secs" => config.check_interval_secs = expect_integer(entry)?,
+            "dry_run" => config.dry_run = expect_bool(entry)?,
+            "log_file" => config.log_file = expect_string(entry)?,
+            "grace_period_secs" => config.grace_period_secs = expect_integer(entry)?,
+            "min_pid" => config.min_pid = expect_pid(entry)?,
+            "protected_names" => config.protected_names = expect_string_array(entry)?,
+            "protected_exes" => config.protected_exes = expect_string_array(entry)?,
+            "metrics_address" => config.metrics_address = expect_address(entry)?,
+            "frozen_file" => config.frozen_file = expect_string(entry)?,
+            "throttle_file" => config.throttle_file = expect_string(entry)?,
+            "cgroup_root" => config.cgroup_root = expect_string(entry)?,
+            "cpu_normalization" => config.cpu_normalization = expect_cpu_normalization(entry)?,
+            _ => return Err(failure(entry, "unknown key")),
+        }
+    }
+    if config.check_interval_secs <= 0 {
+        return Err(top_failure(
+            document,
+            "check_interval_secs",
+            "must be positive",
+        ));
+    }
+    if config.grace_period_secs < 0 {
+        return Err(top_failure(
+            document,
+            "grace_period_secs",
+            "must not be negative",
+        ));
+    }
+    if config.log_file.is_empty() {
+        return Err(top_failure(document, "log_file", "must not be empty"));
+    }
+    if config.frozen_file.is_empty() {
+        return Err(top_failure(document, "frozen_file", "must not be empty"));
+    }
+    if config.throttle_file.is_empty() {
+        return Err(top_failure(document, "throttle_file", "must not be empty"));
+    }
+    if config.cgroup_root.is_empty() {
+        return Err(top_failure(document, "cgroup_root", "must not be empty"));
+    }
+    // Presets come first, so they win priority ties against [[rules]].
+    config.rules = presets;
+    for table in &document.rules {
+        let rule = rule_from_table(table)?;
+        // Match history and actions are keyed by rule name.
+        if config.rules.iter().any(|other| other.name == rule.name) {
+            return Err(
+                match table.entries.iter().find(|entry| entry.key == "name") {
+                    Some(entry) => failure(entry, &duplicate_rule(&rule)),
+                    None => (table.line, "name".to_string(), duplicate_rule(&rule)),
+                },
+            );
+        }
+        config.rules.push(rule);
+    }
+    if !is_valid_config(config.clone()) {
+        return Err((0, String::new(), "invalid configuration".to_string()));
+    }
+    Ok(config)
+}
+
+fn rule_from_table(table: &RuleTable) -> Result<DetectionRule, ParseFailure> {
+    // A `preset` key makes the preset the base; the table's other keys
+    // override it wherever they appear.
+    let mut rule = match table.entries.iter().find(|entry| entry.key == "preset") {
+        Some(entry) => expect_preset(entry, &expect_string(entry)?)?,
+        None => new_detection_rule(
+            String::new(),
+            Priority::Medium,
+            0f64,
+            0,
+            String::new(),
+            String::new(),
+            true,
+        ),
+    };
+    for entry in &table.entries {
+        match entry.key.as_str() {
+            "preset" => {}
+            "name" => rule.name = expect_string(entry)?,
+            "priority" => rule.priority = expect_priority(entry)?,
+            "max_cpu_percent" => {
+                rule.max_cpu_percent = expect_number(entry)?;
+                if !(0f64..=100f64).contains(&rule.max_cpu_percent) {
+                    return Err(failure(entry, "must be between 0 and 100"));
+                }
+            }
+            "max_memory_mb" => rule.max_memory_mb = expect_non_negative(entry)?,
+            "name_pattern" => rule.name_pattern = expect_string(entry)?,
+            "cmdline_pattern" => rule.cmdline_pattern = expect_string(entry)?,
+            "enabled" => rule.enabled = expect_bool(entry)?,
+            "min_age_secs" => rule.min_age_secs = expect_non_negative(entry)?,
+            "parent_is_init" => rule.parent_is_init = expect_bool(entry)?,
+            "name_match" => rule.name_match = expect_match_mode(entry)?,
+            "cmdline_match" => rule.cmdline_match = expect_match_mode(entry)?,
+            "case_sensitive" => rule.case_sensitive = expect_bool(entry)?,
+            "cgroup_pattern" => rule.cgroup_pattern = expect_string(entry)?,
+            "cgroup_match" => rule.cgroup_match = expect_match_mode(entry)?,
+            "unit_pattern" => rule.unit_pattern = expect_string(entry)?,
+            "unit_match" => rule.unit_match = expect_match_mode(entry)?,
+            "exclude_unit_pattern" => rule.exclude_unit_pattern = expect_string(entry)?,
+            "container_pattern" => rule.container_pattern = expect_string(entry)?,
+            "container_match" => rule.container_match = expect_match_mode(entry)?,
+            "sustained_samples" => rule.sustained_samples = expect_non_negative(entry)?,
+            "sustained_secs" => rule.sustained_secs = expect_non_negative(entry)?,
+            "action" => rule.action = expect_rule_action(entry)?,
+            "tree_signal" => rule.tree_signal = expect_tree_signal(entry)?,
+            "throttle_nice" => {
+                rule.throttle_nice = expect_integer(entry)?;
+                if !(1..=19).contains(&rule.throttle_nice) {
+                    return Err(failure(entry, "must be between 1 and 19"));
+                }
+            }
+            "cap_cpu_percent" => {
+                rule.cap_cpu_percent = expect_number(entry)?;
+                if rule.cap_cpu_percent < 0f64 {
+                    return Err(failure(entry, "must not be negative"));
+                }
+            }
+            "cap_memory_mb" => rule.cap_memory_mb = expect_non_negative(entry)?,
+            _ => return Err(failure(entry, "unknown key")),
+        }
+    }
+    if rule.name.is_empty() {
+        return Err((
+            table.line,
+            "name".to_string(),
+            "rule needs a name".to_string(),
+        ));
+    }
+    // Without a CPU threshold a throttled process could never be restored.
+    if rule.action == RuleAction::Throttle && rule.max_cpu_percent <= 0f64 {
+        return Err(action_failure(
+            table,
+            "throttle needs max_cpu_percent to revert against",
+        ));
+    }
+    if rule.action == RuleAction::Cap && rule.cap_cpu_percent <= 0f64 && rule.cap_memory_mb <= 0 {
+        return Err(action_failure(
+            table,
+            "cap needs cap_cpu_percent or cap_memory_mb",
+        ));
+    }
+    // Patterns are compiled once here, not on every scan.
+    let rule = compile_rule_patterns(rule).map_err(|(key, message)| {
+        match table.entries.iter().find(|entry| entry.key == key) {
+            Some(entry) => failure(entry, &message),
+            None => (table.line, key, message),
+        }
+    })?;
+    if !is_valid_rule(rule.clone()) {
+        return Err((
+            table.line,
+            "rules".to_string(),
+            format!("invalid rule `{}`", rule.name),
+        ));
+    }
+    Ok(rule)
+}
+
+fn duplicate_rule(rule: &DetectionRule) -> String {
+    format!("duplicate rule name `{}`", rule.name)
+}
+
+// Reported at the rule's `action` key, or the table header if the action
+// came from a preset.
+fn action_failure(table: &RuleTable, message: &str) -> ParseFailure {
+    match table.entries.iter().find(|entry| entry.key == "action") {
+        Some(entry) => failure(entry, message),
+        None => (table.line, "action".to_string(), message.to_string()),
+    }
+}
+
+pub(crate) fn failure(entry: &Entry, message: &str) -> ParseFailure {
+    (entry.line, entry.key.clone(), message.to_string())
+}
+
+fn top_failure(document: &Document, key: &str, message: &str) -> ParseFailure {
+    let line = match document.top.iter().find(|entry| entry.key == key) {
+        Some(entry) => entry.line,
+        None => 0,
+    };
+    (line, key.to_string(), message.to_string())
+}
+
+pub(crate) fn expect_string(entry: &Entry) -> Result<String, ParseFailure> {
+    match &entry.value {
+        TomlValue::String(value) => Ok(value.clone()),
+        _ => Err(failure(entry, "expected a string")),
+    }
+}
+
+pub(crate) fn expect_integer(entry: &Entry) -> Result<i64, ParseFailure> {
+    match entry.value {
+        TomlValue::Integer(value) => Ok(value),
+        _ => Err(failure(entry, "expected an integer")),
+    }
+}
+
+pub(crate) fn expect_non_negative(entry: &Entry) -> Result<i64, ParseFailure> {
+    let value = expect_integer(entry)?;
+    if value < 0 {
+        return Err(failure(entry, "must not be negative"));
+    }
+    Ok(value)
+}
+
+pub(crate) fn expect_number(entry: &Entry) -> Result<f64, ParseFailure> {
+    match entry.value {
+        TomlValue::Integer(value) => Ok(value as f64),
+        TomlValue::Float(value) => Ok(value),
+        _ => Err(failure(entry, "expected a number")),
+    }
+}
+
+pub(crate) fn expect_bool(entry: &Entry) -> Result<bool, ParseFailure> {
+    match entry.value {
+        TomlValue::Boolean(value) => Ok(value),
+        _ => Err(failure(entry, "expected true or false")),
+    }
+}
+
+pub(crate) fn expect_string_array(entry: &Entry) -> Result<Vec<String>, ParseFailure> {
+    match &entry.value {
+        TomlValue::Array(items) => items
+            .iter()
+            .map(|item| match item {
+                TomlValue::String(value) => Ok(value.clone()),
+                _ => Err(failure(entry, "expected an array of strings")),
+            })
+            .collect(),
+        _ => Err(failure(entry, "expected an array of strings")),
+    }
+}
+
+fn expect_pid(entry: &Entry) -> Result<i32, ParseFailure> {
+    match i32::try_from(expect_non_negative(entry)?) {
+        Ok(pid) => Ok(pid),
+        Err(_) => Err(failure(entry, "is not a valid PID")),
+    }
+}
+
+// Empty disables the endpoint; otherwise host:port, as for bind(2).
+fn expect_address(entry: &Entry) -> Result<String, ParseFailure> {
+    let address = expect_string(entry)?;
+    if address.is_empty() {
+        return Ok(address);
+    }
+    match address.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => Ok(address),
+        _ => Err(failure(entry, "must be host:port")),
+    }
+}
+
+fn expect_preset(entry: &Entry, name: &str) -> Result<DetectionRule, ParseFailure> {
+    match preset_rule(name) {
+        Some(rule) => Ok(rule),
+        None => Err(failure(
+            entry,
+            &format!(
+                "unknown preset `{}` (expected one of {})",
+                name,
+                PRESET_NAMES.join(", ")
+            ),
+        )),
+    }
+}
+
+fn expect_match_mode(entry: &Entry) -> Result<MatchMode, ParseFailure> {
+    match parse_match_mode(&expect_string(entry)?) {
+        Some(mode) => Ok(mode),
+        None => Err(failure(
+            entry,
+            "expected \"substring\", \"glob\", \"regex\" or \"exact\"",
+        )),
+    }
+}
+
+fn expect_rule_action(entry: &Entry) -> Result<RuleAction, ParseFailure> {
+    match parse_rule_action(&expect_string(entry)?) {
+        Some(action) => Ok(action),
+        None => Err(failure(
+            entry,
+            "expected \"terminate\", \"terminate-tree\", \"stop\", \"throttle\", \
+             \"cap\" or \"log-only\"",
+        )),
+    }
+}
+
+fn expect_tree_signal(entry: &Entry) -> Result<TreeSignal, ParseFailure> {
+    match parse_tree_signal(&expect_string(entry)?) {
+        Some(signal) => Ok(signal),
+        None => Err(failure(
+            entry,
+            "expected \"leaves-first\" or \"process-group\"",
+        )),
+    }
+}
+
+fn expect_cpu_normalization(entry: &Entry) -> Result<CpuNormalization, ParseFailure> {
+    match parse_cpu_normalization(&expect_string(entry)?) {
+        Some(normalization) => Ok(normalization),
+        None => Err(failure(entry, "expected \"per-core\" or \"whole-machine\"")),
+    }
+}
+
+fn expect_priority(entry: &Entry) -> Result<Priority, ParseFailure> {
+    match expect_string(entry)?.to_lowercase().as_str() {
+        "high" => Ok(Priority::High),
+        "medium" => Ok(Priority::Medium),
+        "low" => Ok(Priority::Low),
+        _ => Err(failure(entry, "expected \"high\", \"medium\" or \"low\"")),
+    }
+}
+
+#[test]
+fn test_parse_config_full_file() {
+    let text = r#"
+# reaper.toml
+check_interval_secs = 30
+dry_run = true
+log_file = "/var/log/reaper/audit.log"  # JSON lines
+grace_period_secs = 10
+
+[[rules]]
+name = "Infinite loop"
+priority = "high"
+max_cpu_percent = 90
+min_age_secs = 300
+
+[[rules]]
+name = "Hung nextest"
+priority = "Medium"
+cmdline_pattern = "cargo-nextest"
+max_memory_mb = 4_096
+enabled = false
+"#;
+    let config = parse_config(text, "reaper.toml").expect("config should parse");
+    assert!(config.check_interval_secs == 30 && config.dry_run);
+    assert!(config.log_file == "/var/log/reaper/audit.log");
+    assert!(config.grace_period_secs == 10);
+    assert!(config.rules.len() == 2);
+    assert!(config.rules[0].priority == Priority::High);
+    assert!(config.rules[0].max_cpu_percent == 90f64 && config.rules[0].min_age_secs == 300);
+    assert!(config.rules[1].cmdline_pattern == "cargo-nextest");
+    assert!(config.rules[1].max_memory_mb == 4096 && !config.rules[1].enabled);
+}
+
+#[test]
+fn test_parse_config_empty_file_is_default() {
+    let config = parse_config("# nothing here\n", "empty.toml").expect("empty config");
+    assert!(config.check_interval_secs == default_config().check_interval_secs);
+    assert!(config.rules.is_empty());
+}
+
+#[test]
+fn test_parse_config_reports_line_and_key() {
+    let text = "check_interval_secs = 30\n\n[[rules]]\nname = \"Spin\"\npriority = \"urgent\"\n";
+    let error = parse_config(text, "/etc/reaper.toml").expect_err("bad priority");
+    assert!(error.line == 5 && error.key == "priority");
+    assert!(error
+        .to_string()
+        .starts_with("/etc/reaper.toml:5: priority:"));
+}
+
+#[test]
+fn test_parse_config_type_and_range_errors() {
+    let error = parse_config("dry_run = \"yes\"\n", "r.toml").expect_err("wrong type");
+    assert!(error.line == 1 && error.key == "dry_run");
+    let error = parse_config("check_interval_secs = 0\n", "r.toml").expect_err("zero interval");
+    assert!(error.key == "check_interval_secs");
+    let text = "[[rules]]\nname = \"x\"\nmax_cpu_percent = 150.0\n";
+    let error = parse_config(text, "r.toml").expect_err("cpu over 100");
+    assert!(error.line == 3 && error.key == "max_cpu_percent");
+    let error = parse_config("[[rules]]\npriority = \"low\"\n", "r.toml").expect_err("no name");
+    assert!(error.line == 1 && error.key == "name");
+}
+
+#[test]
+fn test_parse_config_syntax_errors() {
+    let error = parse_config("log_file = \"/tmp/x\n", "r.toml").expect_err("unterminated");
+    assert!(error.line == 1 && error.key == "log_file" && error.message.contains("string"));
+    let error = parse_config("[server]\n", "r.toml").expect_err("unknown table");
+    assert!(error.key == "server");
+    let error = parse_config("dry_run = true\nserver.port = 80\n", "r.toml").expect_err("dotted");
+    assert!(error.line == 2 && error.key == "server" && error.message == "unknown key");
+    let error = parse_config("[rules]\nname = \"x\"\n", "r.toml").expect_err("single table");
+    assert!(error.line == 1 && error.key == "rules");
+    let error = parse_config("min_pid = 1e99999\n", "r.toml").expect_err("bad float");
+    assert!(error.line == 1 && error.key == "min_pid");
+    let error = parse_config("mystery = 1\n", "r.toml").expect_err("unknown key");
+    assert!(error.key == "mystery" && error.message == "unknown key");
+    let error = parse_config("dry_run = true\ndry_run = false\n", "r.toml").expect_err("dup");
+    assert!(error.line == 2 && error.message == "duplicate key");
+    let error = parse_config("just some words\n", "r.toml").expect_err("not key = value");
+    assert!(error.line == 1);
+}
+
+#[test]
+fn test_parse_document_value_kinds() {
+    let text = r#"
+raw = 'C:\raw'
+escaped = "a\"b\u0041"
+negative = -12
+hex = 0x1_F
+float = 2.5e1
+when = 1979-05-27T07:32:00Z
+array = ["a", 'b',]
+"#;
+    let document = parse_document(text).expect("document");
+    let values: Vec<&TomlValue> = document.top.iter().map(|entry| &entry.value).collect();
+    assert!(*values[0] == TomlValue::String("C:\\raw".to_string()));
+    assert!(*values[1] == TomlValue::String("a\"bA".to_string()));
+    assert!(*values[2] == TomlValue::Integer(-12));
+    assert!(*values[3] == TomlValue::Integer(31));
+    assert!(*values[4] == TomlValue::Float(25f64));
+    assert!(*values[5] == TomlValue::Datetime("1979-05-27T07:32:00Z".to_string()));
+    let expected = vec![
+        TomlValue::String("a".to_string()),
+        TomlValue::String("b".to_string()),
+    ];
+    assert!(*values[6] == TomlValue::Array(expected));
+    assert!(document.top.iter().map(|entry| entry.line).eq(2..=8));
+    assert!(parse_document("a = [1, 2").is_err() && parse_document("a = nope").is_err());
+}
+
+#[test]
+fn test_parse_config_full_toml_syntax() {
+    let text = r#"
+"log_file" = """
+/var/log/reaper.log"""
+min_pid = 0x100
+rules = [
+    { name = "Inline", max_cpu_percent = 95 },
+]
+"#;
+    let config = parse_config(text, "r.toml").expect("inline tables");
+    assert!(config.log_file == "/var/log/reaper.log" && config.min_pid == 256);
+    assert!(config.rules.len() == 1 && config.rules[0].name == "Inline");
+    assert!(config.rules[0].max_cpu_percent == 95f64);
+    let text = r#"
+[[rules]]
+'name' = "Multi-line"
+cmdline_pattern = '''
+cargo test'''
+"#;
+    let config = parse_config(text, "r.toml").expect("quoted keys and multi-line strings");
+    assert!(
+        config.rules[0].name == "Multi-line" && config.rules[0].cmdline_pattern == "cargo test"
+    );
+    let text = "[[rules]]\nname = \"x\"\nsustained = { samples = 3 }\n";
+    let error = parse_config(text, "r.toml").expect_err("inline table is not a rule key");
+    assert!(error.line == 3 && error.key == "sustained" && error.message == "unknown key");
+}
+
+#[test]
+fn test_parse_document_multiline_array_and_comments() {
+    let text = "names = [\n  \"sshd\", # keep\n  \"a#b\",\n]\n";
+    let document = parse_document(text).expect("document");
+    let expected = vec![
+        TomlValue::String("sshd".to_string()),
+        TomlValue::String("a#b".to_string()),
+    ];
+    assert!(document.top[0].value == TomlValue::Array(expected));
+}
+
+#[test]
+fn test_parse_config_pattern_modes() {
+    let text = r#"
+[[rules]]
+name = "Threaded nextest"
+cmdline_pattern = "cargo-nextest * --test-threads *"
+cmdline_match = "glob"
+name_pattern = "cargo-.+"
+name_match = "Regex"
+case_sensitive = true
+sustained_samples = 3
+sustained_secs = 120
+"#;
+    let config = parse_config(text, "modes.toml").expect("valid config");
+    let rule = &config.rules[0];
+    assert!(rule.cmdline_match == MatchMode::Glob && rule.name_match == MatchMode::Regex);
+    assert!(rule.case_sensitive);
+    assert!(rule.sustained_samples == 3 && rule.sustained_secs == 120);
+    assert!(
+        rule.name_matcher.is_some() && rule.cmdline_matcher.is_some(),
+        "Compiled at load"
+    );
+    let error = parse_config(
+        "[[rules]]\nname = \"Bad\"\nname_match = \"regex\"\nname_pattern = \"(\"\n",
+        "bad.toml",
+    )
+    .expect_err("invalid regex");
+    assert!(error.line == 4 && error.key == "name_pattern");
+    let error = parse_config(
+        "[[rules]]\nname = \"Bad\"\ncmdline_match = \"fuzzy\"\n",
+        "bad.toml",
+    )
+    .expect_err("unknown mode");
+    assert!(error.line == 3 && error.key == "cmdline_match");
+}
+
+#[test]
+fn test_parse_config_presets() {
+    let text = r#"
+presets = ["infinite-loop", "orphaned-monitor"]
+
+[[rules]]
+min_age_secs = 172800
+preset = "hung-test"
+priority = "high"
+"#;
+    let config = parse_config(text, "presets.toml").expect("valid config");
+    let names: Vec<&str> = config.rules.iter().map(|rule| rule.name.as_str()).collect();
+    assert!(names == vec!["Infinite loop", "Orphaned monitor", "Hung test"]);
+    let hung = &config.rules[2];
+    assert!(hung.preset == "hung-test" && hung.priority == Priority::High);
+    assert!(
+        hung.min_age_secs == 172800,
+        "Table keys override the preset"
+    );
+    assert!(hung.cmdline_matcher.is_some());
+    let error = parse_config("presets = [\"fork-bomb\"]\n", "bad.toml").expect_err("unknown");
+    assert!(error.line == 1 && error.key == "presets" && error.message.contains("hung-test"));
+    let error = parse_config("presets = [\"hung-test\", 3]\n", "bad.toml").expect_err("type");
+    assert!(error.message == "expected an array of strings");
+}
+
+#[test]
+fn test_parse_config_rejects_duplicate_rule_names() {
+    let text = "presets = [\"hung-test\"]\n\n[[rules]]\npreset = \"hung-test\"\n";
+    let error = parse_config(text, "dup.toml").expect_err("preset twice");
+    assert!(error.line == 3 && error.key == "name");
+    assert!(error.message == "duplicate rule name `Hung test`");
+    let text = "[[rules]]\nname = \"Spin\"\nmax_cpu_percent = 90\n\n\
+                [[rules]]\nname = \"Spin\"\nmax_cpu_percent = 50\n";
+    let error = parse_config(text, "dup.toml").expect_err("same name twice");
+    assert!(error.line == 6 && error.key == "name");
+    let error = parse_config("presets = [\"hung-test\", \"hung-test\"]\n", "dup.toml")
+        .expect_err("preset listed twice");
+    assert!(error.line == 1 && error.key == "presets");
+}
+
+#[test]
+fn test_parse_config_safety_settings() {
+    let text = r#"
+min_pid = 1000
+protected_names = ["postgres", "dockerd"]
+protected_exes = ["/usr/lib/postgresql/16/bin/postgres"]
+"#;
+    let config = parse_config(text, "safety.toml").expect("valid config");
+    assert!(config.min_pid == 1000);
+    assert!(config.protected_names == vec!["postgres".to_string(), "dockerd".to_string()]);
+    assert!(config.protected_exes.len() == 1);
+    let defaults = parse_config("", "empty.toml").expect("defaults");
+    assert!(defaults.min_pid == 300 && defaults.protected_names.contains(&"sshd".to_string()));
+    let error = parse_config("min_pid = -1\n", "bad.toml").expect_err("negative");
+    assert!(error.key == "min_pid");
+}
+
+#[test]
+fn test_parse_config_metrics_address() {
+    let config = parse_config("metrics_address = \"127.0.0.1:9464\"\n", "m.toml").expect("valid");
+    assert!(config.metrics_address == "127.0.0.1:9464");
+    let defaults = parse_config("", "empty.toml").expect("defaults");
+    assert!(
+        defaults.metrics_address.is_empty(),
+        "The endpoint is off by default"
+    );
+    let bracketed = parse_config("metrics_address = \"[::1]:9464\"\n", "m.toml").expect("ipv6");
+    assert!(bracketed.metrics_address == "[::1]:9464");
+    let error = parse_config("metrics_address = \"9464\"\n", "bad.toml").expect_err("no host");
+    assert!(error.line == 1 && error.key == "metrics_address");
+    assert!(error.message == "must be host:port");
+}
+
+#[test]
+fn test_parse_config_tree_action() {
+    let text = r#"
+[[rules]]
+name = "Hung nextest"
+cmdline_pattern = "cargo-nextest"
+action = "terminate-tree"
+tree_signal = "process-group"
+"#;
+    let config = parse_config(text, "tree.toml").expect("valid config");
+    assert!(config.rules[0].action == RuleAction::TerminateTree);
+    assert!(config.rules[0].tree_signal == TreeSignal::ProcessGroup);
+    let plain = parse_config("[[rules]]\nname = \"x\"\n", "plain.toml").expect("valid");
+    assert!(plain.rules[0].action == RuleAction::Terminate);
+    let text = "[[rules]]\nname = \"x\"\naction = \"nuke\"\n";
+    let error = parse_config(text, "bad.toml").expect_err("unknown action");
+    assert!(error.line == 3 && error.key == "action");
+}
+
+#[test]
+fn test_parse_config_stop_action() {
+    let text = r#"
+frozen_file = "/run/reaper/frozen.tsv"
+
+[[rules]]
+name = "Quarantine"
+action = "stop"
+
+[[rules]]
+name = "Watch only"
+action = "log-only"
+"#;
+    let config = parse_config(text, "stop.toml").expect("valid config");
+    assert!(config.frozen_file == "/run/reaper/frozen.tsv");
+    assert!(config.rules[0].action == RuleAction::Stop);
+    assert!(config.rules[1].action == RuleAction::LogOnly);
+    let error = parse_config("frozen_file = \"\"\n", "bad.toml").expect_err("empty");
+    assert!(error.line == 1 && error.key == "frozen_file");
+}
+
+#[test]
+fn test_parse_config_throttle_action() {
+    let text = r#"
+throttle_file = "/run/reaper/throttled.tsv"
+
+[[rules]]
+name = "Demote"
+max_cpu_percent = 80
+action = "throttle"
+throttle_nice = 15
+"#;
+    let config = parse_config(text, "throttle.toml").expect("valid config");
+    assert!(config.rules[0].action == RuleAction::Throttle);
+    assert!(config.rules[0].throttle_nice == 15);
+    assert!(config.throttle_file == "/run/reaper/throttled.tsv");
+    let text = "[[rules]]\nname = \"x\"\naction = \"throttle\"\n";
+    let error = parse_config(text, "bad.toml").expect_err("no threshold");
+    assert!(error.line == 3 && error.key == "action");
+    assert!(error.message == "throttle needs max_cpu_percent to revert against");
+    let text = "[[rules]]\nname = \"x\"\nmax_cpu_percent = 80\nthrottle_nice = 20\n";
+    let error = parse_config(text, "bad.toml").expect_err("out of range");
+    assert!(error.line == 4 && error.key == "throttle_nice");
+}
+
+#[test]
+fn test_parse_config_cap_action() {
+    let text = r#"
+cgroup_root = "/sys/fs/cgroup/ci-reaper"
+
+[[rules]]
+name = "Confine"
+cmdline_pattern = "cargo test"
+action = "cap"
+cap_cpu_percent = 150
+cap_memory_mb = 2048
+"#;
+    let config = parse_config(text, "cap.toml").expect("valid config");
+    assert!(config.cgroup_root == "/sys/fs/cgroup/ci-reaper");
+    assert!(config.rules[0].action == RuleAction::Cap);
+    assert!(config.rules[0].cap_cpu_percent == 150f64 && config.rules[0].cap_memory_mb == 2048);
+    let text = "[[rules]]\nname = \"x\"\naction = \"cap\"\n";
+    let error = parse_config(text, "bad.toml").expect_err("no limits");
+    assert!(error.line == 3 && error.message == "cap needs cap_cpu_percent or cap_memory_mb");
+    let error = parse_config("cgroup_root = \"\"\n", "bad.toml").expect_err("empty");
+    assert!(error.line == 1 && error.key == "cgroup_root");
+}
+
+#[test]
+fn test_parse_config_membership_patterns() {
+    let text = r#"
+[[rules]]
+name = "Containers only"
+unit_pattern = "docker-*.scope"
+unit_match = "glob"
+exclude_unit_pattern = "docker-4f1c*"
+container_pattern = "[0-9a-f]{64}"
+container_match = "regex"
+
+[[rules]]
+name = "Not over ssh"
+cgroup_pattern = "/system.slice/"
+exclude_unit_pattern = "sshd.service"
+"#;
+    let config = parse_config(text, "membership.toml").expect("valid config");
+    let rule = &config.rules[0];
+    assert!(rule.unit_match == MatchMode::Glob && rule.unit_matcher.is_some());
+    assert!(rule.exclude_unit_matcher.is_some() && rule.container_matcher.is_some());
+    assert!(config.rules[1].cgroup_matcher.is_some());
+    let text = "[[rules]]\nname = \"x\"\ncontainer_match = \"regex\"\ncontainer_pattern = \"(\"\n";
+    let error = parse_config(text, "bad.toml").expect_err("bad regex");
+    assert!(error.line == 4 && error.key == "container_pattern");
+    let text = "[[rules]]\nname = \"x\"\nunit_match = \"regex\"\nexclude_unit_pattern = \"(\"\n";
+    let error = parse_config(text, "bad.toml").expect_err("bad exclusion");
+    assert!(error.line == 4 && error.key == "exclude_unit_pattern");
+}
+
+#[test]
+fn test_parse_config_cpu_normalization() {
+    let config = parse_config("# defaults\n", "r.toml").expect("default config");
+    assert!(config.cpu_normalization == CpuNormalization::PerCore);
+    let text = "cpu_normalization = \"whole-machine\"\n";
+    let config = parse_config(text, "r.toml").expect("whole machine");
+    assert!(config.cpu_normalization == CpuNormalization::WholeMachine);
+    let error = parse_config("cpu_normalization = \"per-cpu\"\n", "r.toml").expect_err("bad");
+    assert!(error.line == 1 && error.key == "cpu_normalization");
+}
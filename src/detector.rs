@@ -1 +1,995 @@
-use crate :: scanner :: Process ; use crate :: config :: Config ; pub struct RuleMatch { pub rule_name : String , pub reason : String , } pub struct Detector { pub config : Config , } pub fn new_detector (config : Config) -> Detector { Detector { config : config , } } pub fn evaluate (detector : & Detector , process : & Process) -> bool { false } pub fn detect_infinite_loop (process : & Process) -> bool { process . cpu_percent > 90f64 && process . age_seconds > 300 } pub fn detect_hung_test (process : & Process) -> bool { false } fn main () { # [derive (Debug , Clone , PartialEq)] enum Priority { Critical , High , Low , } }
\ No newline at end of file
+// Rule evaluation with explanations: which rule matched a process and why.
+
+use crate::{match_mode_to_string, priority_to_string, priority_to_value};
+use crate::{new_detection_rule, DetectionRule, MatchMode, Priority, Process};
+use crate::{RuleAction, TreeSignal};
+
+// The deciding rule for a process plus every rule that matched it.
+#[derive(Debug, Clone)]
+pub(crate) struct RuleMatch {
+    pub(crate) process: Process,
+    pub(crate) rule_name: String,
+    pub(crate) priority: Priority,
+    // One entry per criterion the rule sets, e.g. "cpu 99.9 > 90".
+    pub(crate) reasons: Vec<String>,
+    // What the deciding rule does to the process.
+    pub(crate) action: RuleAction,
+    pub(crate) tree_signal: TreeSignal,
+    // The deciding rule itself, whose thresholds and limits the action uses.
+    pub(crate) rule: DetectionRule,
+    // All matching rules, highest priority first; the deciding rule leads.
+    pub(crate) matched_rules: Vec<RuleHit>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct RuleHit {
+    pub(crate) rule_name: String,
+    pub(crate) priority: Priority,
+    pub(crate) reasons: Vec<String>,
+    pub(crate) action: RuleAction,
+    pub(crate) tree_signal: TreeSignal,
+}
+
+pub(crate) fn new_rule_hit(rule: &DetectionRule, reasons: Vec<String>) -> RuleHit {
+    RuleHit {
+        rule_name: rule.name.clone(),
+        priority: rule.priority,
+        reasons,
+        action: rule.action,
+        tree_signal: rule.tree_signal,
+    }
+}
+
+pub(crate) fn new_rule_match(
+    rule: &DetectionRule,
+    proc: Process,
+    reasons: Vec<String>,
+) -> RuleMatch {
+    RuleMatch {
+        process: proc,
+        rule_name: rule.name.clone(),
+        priority: rule.priority,
+        reasons: reasons.clone(),
+        action: rule.action,
+        tree_signal: rule.tree_signal,
+        rule: rule.clone(),
+        matched_rules: vec![new_rule_hit(rule, reasons)],
+    }
+}
+
+// Consecutive cycles a process has matched a rule, keyed by PID, start time
+// (so a recycled PID starts over) and rule name.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct MatchHistory {
+    streaks: std::collections::HashMap<(i32, i64, String), Streak>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Streak {
+    pub(crate) samples: i64,
+    // Unix seconds of the first matching sample.
+    pub(crate) since: i64,
+}
+
+pub(crate) fn new_match_history() -> MatchHistory {
+    MatchHistory::default()
+}
+
+// Records one sample of `rule` against `proc` and returns the streak so far,
+// or None (and forgets the streak) when the rule did not match.
+pub(crate) fn record_sample(
+    history: &mut MatchHistory,
+    proc: &Process,
+    rule: &DetectionRule,
+    matched: bool,
+    now: i64,
+) -> Option<Streak> {
+    let key = (proc.pid, proc.start_ticks, rule.name.clone());
+    if !matched {
+        history.streaks.remove(&key);
+        return None;
+    }
+    let streak = history.streaks.entry(key).or_insert(Streak {
+        samples: 0,
+        since: now,
+    });
+    streak.samples += 1;
+    Some(*streak)
+}
+
+// Drops streaks for processes that were not in the latest scan.
+pub(crate) fn forget_missing(history: &mut MatchHistory, procs: &[Process]) {
+    let alive: std::collections::HashSet<(i32, i64)> = procs
+        .iter()
+        .map(|proc| (proc.pid, proc.start_ticks))
+        .collect();
+    history
+        .streaks
+        .retain(|(pid, start_ticks, _), _| alive.contains(&(*pid, *start_ticks)));
+}
+
+pub(crate) fn is_sustained(rule: &DetectionRule, streak: Streak, now: i64) -> bool {
+    streak.samples >= rule.sustained_samples.max(1) && now - streak.since >= rule.sustained_secs
+}
+
+// Checks `proc` against every rule as a single sample: rules that need a
+// sustained match cannot count yet.
+pub(crate) fn evaluate_rules(proc: Process, rules: &[DetectionRule]) -> Option<RuleMatch> {
+    evaluate_rules_with_history(proc, rules, &mut new_match_history(), crate::unix_now())
+}
+
+// Checks `proc` against every rule, counting a rule only once its match has
+// been sustained. The highest-priority match decides; equal priorities fall
+// back to config order.
+pub(crate) fn evaluate_rules_with_history(
+    proc: Process,
+    rules: &[DetectionRule],
+    history: &mut MatchHistory,
+    now: i64,
+) -> Option<RuleMatch> {
+    let mut hits: Vec<(&DetectionRule, RuleHit)> = Vec::new();
+    for rule in rules {
+        let reasons = match_reasons(rule, &proc);
+        let streak = match record_sample(history, &proc, rule, reasons.is_some(), now) {
+            Some(streak) if is_sustained(rule, streak, now) => streak,
+            _ => continue,
+        };
+        let mut reasons = reasons.unwrap_or_default();
+        if rule.sustained_samples > 1 || rule.sustained_secs > 0 {
+            reasons.push(format!(
+                "sustained {} samples over {}s",
+                streak.samples,
+                now - streak.since
+            ));
+        }
+        hits.push((rule, new_rule_hit(rule, reasons)));
+    }
+    // sort_by_key is stable, so config order survives within a priority.
+    hits.sort_by_key(|(_, hit)| std::cmp::Reverse(priority_to_value(hit.priority)));
+    let (rule, deciding) = hits.first()?.clone();
+    Some(RuleMatch {
+        process: proc,
+        rule_name: deciding.rule_name,
+        priority: deciding.priority,
+        reasons: deciding.reasons,
+        action: deciding.action,
+        tree_signal: deciding.tree_signal,
+        rule: rule.clone(),
+        matched_rules: hits.into_iter().map(|(_, hit)| hit).collect(),
+    })
+}
+
+// For a single sample, as `scan` takes: the highest-priority sustained rule
+// whose other criteria match `proc`, with how far the sample gets it, e.g.
+// "pending (1/3 samples, 0/120s)". Such a rule cannot match yet.
+pub(crate) fn evaluate_pending(
+    proc: Process,
+    rules: &[DetectionRule],
+) -> Option<(RuleMatch, String)> {
+    let mut hits: Vec<(&DetectionRule, Vec<String>)> = rules
+        .iter()
+        .filter(|rule| rule.sustained_samples > 1 || rule.sustained_secs > 0)
+        .filter_map(|rule| Some((rule, match_reasons(rule, &proc)?)))
+        .collect();
+    hits.sort_by_key(|(rule, _)| std::cmp::Reverse(priority_to_value(rule.priority)));
+    let (rule, reasons) = hits.into_iter().next()?;
+    let mut progress: Vec<String> = Vec::new();
+    if rule.sustained_samples > 1 {
+        progress.push(format!("1/{} samples", rule.sustained_samples));
+    }
+    if rule.sustained_secs > 0 {
+        progress.push(format!("0/{}s", rule.sustained_secs));
+    }
+    let label = format!("pending ({})", progress.join(", "));
+    Some((new_rule_match(rule, proc, reasons), label))
+}
+
+// A name or cmdline pattern compiled to a regex. Every mode goes through the
+// same engine; only the translation differs.
+#[derive(Debug, Clone)]
+pub(crate) struct Pattern {
+    pub(crate) source: String,
+    pub(crate) mode: MatchMode,
+    pub(crate) case_sensitive: bool,
+    regex: regex::Regex,
+}
+
+pub(crate) fn compile_pattern(
+    source: &str,
+    mode: MatchMode,
+    case_sensitive: bool,
+) -> Result<Pattern, String> {
+    let translated = match mode {
+        MatchMode::Substring => regex::escape(source),
+        MatchMode::Exact => format!("^{}$", regex::escape(source)),
+        MatchMode::Glob => format!("^{}$", glob_to_regex(source)?),
+        // Anchored: the regex has to match the whole name or cmdline.
+        MatchMode::Regex => format!("^(?:{})$", source),
+    };
+    let regex = regex::RegexBuilder::new(&translated)
+        .case_insensitive(!case_sensitive)
+        .build()
+        .map_err(|err| format!("invalid {} pattern: {}", match_mode_to_string(mode), err))?;
+    Ok(Pattern {
+        source: source.to_string(),
+        mode,
+        case_sensitive,
+        regex,
+    })
+}
+
+pub(crate) fn pattern_matches(pattern: &Pattern, text: &str) -> bool {
+    pattern.regex.is_match(text)
+}
+
+// `*` and `?` match any run / any single character (including `/`, since
+// cmdlines are not paths); `[...]` and `[!...]` are character classes.
+pub(crate) fn glob_to_regex(glob: &str) -> Result<String, String> {
+    let mut out = String::new();
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => out.push_str(".*"),
+            '?' => out.push('.'),
+            '[' => {
+                out.push('[');
+                if chars.peek() == Some(&'!') {
+                    chars.next();
+                    out.push('^');
+                }
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        closed = true;
+                        break;
+                    }
+                    // Characters the regex crate treats specially inside a class.
+                    if matches!(c, '\\' | '[' | '&' | '~') {
+                        out.push('\\');
+                    }
+                    out.push(c);
+                }
+                if !closed {
+                    return Err(format!("unclosed `[` in glob `{}`", glob));
+                }
+                out.push(']');
+            }
+            c => out.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    Ok(out)
+}
+
+// Compiles the rule's patterns. Errors name the offending config key.
+pub(crate) fn compile_rule_patterns(
+    mut rule: DetectionRule,
+) -> Result<DetectionRule, (String, String)> {
+    let case_sensitive = rule.case_sensitive;
+    let compile = |key: &str, source: &str, mode: MatchMode| {
+        if source.is_empty() {
+            return Ok(None);
+        }
+        compile_pattern(source, mode, case_sensitive)
+            .map(Some)
+            .map_err(|err| (key.to_string(), err))
+    };
+    rule.name_matcher = compile("name_pattern", &rule.name_pattern, rule.name_match)?;
+    rule.cmdline_matcher = compile("cmdline_pattern", &rule.cmdline_pattern, rule.cmdline_match)?;
+    rule.cgroup_matcher = compile("cgroup_pattern", &rule.cgroup_pattern, rule.cgroup_match)?;
+    rule.unit_matcher = compile("unit_pattern", &rule.unit_pattern, rule.unit_match)?;
+    rule.exclude_unit_matcher = compile(
+        "exclude_unit_pattern",
+        &rule.exclude_unit_pattern,
+        rule.unit_match,
+    )?;
+    rule.container_matcher = compile(
+        "container_pattern",
+        &rule.container_pattern,
+        rule.container_match,
+    )?;
+    Ok(rule)
+}
+
+// Uses the pattern compiled at config load when it still describes the rule;
+// rules built in code (or edited since) are compiled on the spot.
+// A pattern that does not compile matches nothing.
+fn rule_pattern_matches(
+    compiled: Option<&Pattern>,
+    source: &str,
+    mode: MatchMode,
+    case_sensitive: bool,
+    text: &str,
+) -> bool {
+    rule_pattern_check(compiled, source, mode, case_sensitive, text).unwrap_or(false)
+}
+
+// None when the pattern does not compile.
+fn rule_pattern_check(
+    compiled: Option<&Pattern>,
+    source: &str,
+    mode: MatchMode,
+    case_sensitive: bool,
+    text: &str,
+) -> Option<bool> {
+    match compiled {
+        Some(pattern)
+            if pattern.source == source
+                && pattern.mode == mode
+                && pattern.case_sensitive == case_sensitive =>
+        {
+            Some(pattern_matches(pattern, text))
+        }
+        _ => match compile_pattern(source, mode, case_sensitive) {
+            Ok(pattern) => Some(pattern_matches(&pattern, text)),
+            Err(_) => None,
+        },
+    }
+}
+
+fn describe_pattern(field: &str, mode: MatchMode, source: &str) -> String {
+    match mode {
+        MatchMode::Substring => format!("{} contains {}", field, source),
+        MatchMode::Glob => format!("{} matches glob {}", field, source),
+        MatchMode::Regex => format!("{} matches regex {}", field, source),
+        MatchMode::Exact => format!("{} is {}", field, source),
+    }
+}
+
+fn describe_excluded(field: &str, mode: MatchMode, source: &str) -> String {
+    match mode {
+        MatchMode::Substring => format!("{} does not contain {}", field, source),
+        MatchMode::Glob => format!("{} does not match glob {}", field, source),
+        MatchMode::Regex => format!("{} does not match regex {}", field, source),
+        MatchMode::Exact => format!("{} is not {}", field, source),
+    }
+}
+
+// Returns the reasons `rule` matches `proc`, or None as soon as one of its
+// criteria fails. Unset criteria (0 or empty) are skipped.
+pub(crate) fn match_reasons(rule: &DetectionRule, proc: &Process) -> Option<Vec<String>> {
+    if !rule.enabled {
+        return None;
+    }
+    let mut reasons = Vec::new();
+    if rule.max_cpu_percent > 0f64 {
+        if proc.cpu_usage <= rule.max_cpu_percent {
+            return None;
+        }
+        reasons.push(format!("cpu {} > {}", proc.cpu_usage, rule.max_cpu_percent));
+    }
+    if rule.max_memory_mb > 0 {
+        if proc.memory_mb <= rule.max_memory_mb {
+            return None;
+        }
+        reasons.push(format!(
+            "memory {}MB > {}MB",
+            proc.memory_mb, rule.max_memory_mb
+        ));
+    }
+    if !rule.name_pattern.is_empty() {
+        let compiled = rule.name_matcher.as_ref();
+        let (source, mode) = (&rule.name_pattern, rule.name_match);
+        if !rule_pattern_matches(compiled, source, mode, rule.case_sensitive, &proc.name) {
+            return None;
+        }
+        reasons.push(describe_pattern("name", mode, source));
+    }
+    if !rule.cmdline_pattern.is_empty() {
+        let compiled = rule.cmdline_matcher.as_ref();
+        let (source, mode) = (&rule.cmdline_pattern, rule.cmdline_match);
+        if !rule_pattern_matches(compiled, source, mode, rule.case_sensitive, &proc.cmdline) {
+            return None;
+        }
+        reasons.push(describe_pattern("cmdline", mode, source));
+    }
+    let membership = [
+        (
+            "cgroup",
+            rule.cgroup_matcher.as_ref(),
+            &rule.cgroup_pattern,
+            rule.cgroup_match,
+            &proc.cgroup,
+        ),
+        (
+            "unit",
+            rule.unit_matcher.as_ref(),
+            &rule.unit_pattern,
+            rule.unit_match,
+            &proc.systemd_unit,
+        ),
+        (
+            "container",
+            rule.container_matcher.as_ref(),
+            &rule.container_pattern,
+            rule.container_match,
+            &proc.container_id,
+        ),
+    ];
+    for (field, compiled, source, mode, text) in membership {
+        if source.is_empty() {
+            continue;
+        }
+        if !rule_pattern_matches(compiled, source, mode, rule.case_sensitive, text) {
+            return None;
+        }
+        reasons.push(describe_pattern(field, mode, source));
+    }
+    if !rule.exclude_unit_pattern.is_empty() {
+        let compiled = rule.exclude_unit_matcher.as_ref();
+        let (source, mode) = (&rule.exclude_unit_pattern, rule.unit_match);
+        let unit = &proc.systemd_unit;
+        // An exclusion that does not compile excludes everything, rather
+        // than exposing the processes it was written to protect.
+        let excluded = rule_pattern_check(compiled, source, mode, rule.case_sensitive, unit);
+        if excluded.unwrap_or(true) {
+            return None;
+        }
+        reasons.push(describe_excluded("unit", mode, source));
+    }
+    if rule.min_age_secs > 0 {
+        if proc.age_seconds <= rule.min_age_secs {
+            return None;
+        }
+        reasons.push(format!(
+            "age {}s > {}s",
+            proc.age_seconds, rule.min_age_secs
+        ));
+    }
+    if rule.parent_is_init {
+        if proc.parent_pid != 1 {
+            return None;
+        }
+        reasons.push("parent is init".to_string());
+    }
+    Some(reasons)
+}
+
+pub(crate) fn format_reasons(rule_match: &RuleMatch) -> String {
+    if rule_match.reasons.is_empty() {
+        return "rule has no criteria".to_string();
+    }
+    rule_match.reasons.join(", ")
+}
+
+pub(crate) fn format_rule_match(rule_match: &RuleMatch) -> String {
+    let formatted = format!(
+        "PID {} ({}) matched {} [{}]: {}",
+        rule_match.process.pid,
+        rule_match.process.name,
+        rule_match.rule_name,
+        priority_to_string(rule_match.priority),
+        format_reasons(rule_match)
+    );
+    let others: Vec<String> = rule_match
+        .matched_rules
+        .iter()
+        .skip(1)
+        .map(|hit| format!("{} [{}]", hit.rule_name, priority_to_string(hit.priority)))
+        .collect();
+    if others.is_empty() {
+        formatted
+    } else {
+        format!("{} (also matched {})", formatted, others.join(", "))
+    }
+}
+
+// Dry-run report: one row per match with the action reaper would take.
+pub(crate) fn format_match_table(rows: &[(RuleMatch, String)]) -> String {
+    let header = ["PID", "NAME", "RULE", "PRIORITY", "ACTION", "REASON"];
+    let mut cells: Vec<[String; 6]> = vec![header.map(|title| title.to_string())];
+    for (rule_match, action) in rows {
+        cells.push([
+            rule_match.process.pid.to_string(),
+            rule_match.process.name.clone(),
+            rule_match.rule_name.clone(),
+            priority_to_string(rule_match.priority),
+            action.clone(),
+            format_reasons(rule_match),
+        ]);
+    }
+    align_columns(&cells)
+}
+
+// Pads every column but the last to its widest cell.
+pub(crate) fn align_columns(cells: &[[String; 6]]) -> String {
+    let mut widths = [0usize; 6];
+    for row in cells {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let mut table = String::new();
+    for row in cells {
+        let mut line = String::new();
+        for (column, cell) in row.iter().enumerate() {
+            if column + 1 == row.len() {
+                line.push_str(cell);
+            } else {
+                line.push_str(&format!("{:<width$}  ", cell, width = widths[column]));
+            }
+        }
+        table.push_str(line.trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+pub(crate) const PRESET_NAMES: [&str; 4] = [
+    "infinite-loop",
+    "hung-test",
+    "orphaned-monitor",
+    "zombie-binary",
+];
+
+// The four incident classes from the README, as ready-made rules:
+// - infinite-loop: CPU pegged across every scan for five minutes
+// - hung-test: a test runner still alive after a day, taken down with its
+//   test binaries
+// - orphaned-monitor: `tail -f` / `watch` reparented to init, older than a day
+// - zombie-binary: a busy binary launched from /tmp, older than an hour
+pub(crate) fn preset_rule(preset: &str) -> Option<DetectionRule> {
+    let mut rule = new_detection_rule(
+        String::new(),
+        Priority::Medium,
+        0f64,
+        0,
+        String::new(),
+        String::new(),
+        true,
+    );
+    match preset {
+        "infinite-loop" => {
+            rule.name = "Infinite loop".to_string();
+            rule.priority = Priority::High;
+            rule.max_cpu_percent = 90f64;
+            rule.min_age_secs = 300;
+            rule.sustained_secs = 300;
+        }
+        "hung-test" => {
+            rule.name = "Hung test".to_string();
+            // Anchored on argv0, so `vim pytest.ini` is not a test run.
+            rule.cmdline_pattern =
+                r"(\S*/)?(cargo-nextest|cargo test|pmat mutation test|pytest|go test)\b.*"
+                    .to_string();
+            rule.cmdline_match = MatchMode::Regex;
+            rule.min_age_secs = 86400;
+            rule.action = RuleAction::TerminateTree;
+        }
+        "orphaned-monitor" => {
+            rule.name = "Orphaned monitor".to_string();
+            rule.priority = Priority::Low;
+            rule.cmdline_pattern = r"(\S*/)?(tail (-f|-F|--follow)|watch)\b.*".to_string();
+            rule.cmdline_match = MatchMode::Regex;
+            rule.min_age_secs = 86400;
+            rule.parent_is_init = true;
+        }
+        "zombie-binary" => {
+            rule.name = "Zombie binary".to_string();
+            rule.priority = Priority::High;
+            rule.max_cpu_percent = 50f64;
+            rule.cmdline_pattern = "/tmp/*".to_string();
+            rule.cmdline_match = MatchMode::Glob;
+            rule.min_age_secs = 3600;
+        }
+        _ => return None,
+    }
+    rule.preset = preset.to_string();
+    // The preset patterns are fixed, so compiling them cannot fail.
+    compile_rule_patterns(rule).ok()
+}
+
+#[cfg(test)]
+fn nextest_process() -> Process {
+    let mut proc = crate::new_process(
+        4242,
+        "cargo-nextest".to_string(),
+        "cargo-nextest run --workspace".to_string(),
+        99.9,
+        2048,
+        crate::ProcessStatus::Running,
+    );
+    proc.age_seconds = 90000;
+    proc.parent_pid = 1;
+    proc
+}
+
+#[cfg(test)]
+fn rule_with(name: &str, cpu: f64, memory_mb: i64, cmdline_pattern: &str) -> DetectionRule {
+    crate::new_detection_rule(
+        name.to_string(),
+        Priority::Medium,
+        cpu,
+        memory_mb,
+        String::new(),
+        cmdline_pattern.to_string(),
+        true,
+    )
+}
+
+#[test]
+fn test_match_reasons_lists_each_criterion() {
+    let mut rule = rule_with("Hung nextest", 90f64, 1024, "cargo-nextest");
+    rule.name_pattern = "nextest".to_string();
+    rule.min_age_secs = 86400;
+    rule.parent_is_init = true;
+    let reasons = match_reasons(&rule, &nextest_process()).expect("all criteria hold");
+    assert!(
+        reasons
+            == vec![
+                "cpu 99.9 > 90".to_string(),
+                "memory 2048MB > 1024MB".to_string(),
+                "name contains nextest".to_string(),
+                "cmdline contains cargo-nextest".to_string(),
+                "age 90000s > 86400s".to_string(),
+                "parent is init".to_string(),
+            ]
+    );
+}
+
+#[test]
+fn test_match_reasons_rejects_on_any_failed_criterion() {
+    let proc = nextest_process();
+    assert!(match_reasons(&rule_with("Cpu", 99.95, 0, ""), &proc).is_none());
+    assert!(match_reasons(&rule_with("Cmd", 0f64, 0, "pytest"), &proc).is_none());
+    let mut disabled = rule_with("Off", 90f64, 0, "");
+    disabled.enabled = false;
+    assert!(match_reasons(&disabled, &proc).is_none());
+}
+
+#[test]
+fn test_match_reasons_on_cgroup_membership() {
+    let id = "4f1c2b3a5d6e7f8091a2b3c4d5e6f708192a3b4c5d6e7f8091a2b3c4d5e6f708";
+    let mut proc = nextest_process();
+    proc.cgroup = format!("/system.slice/docker-{}.scope", id);
+    proc.systemd_unit = format!("docker-{}.scope", id);
+    proc.container_id = id.to_string();
+    let mut rule = rule_with("Containers", 90f64, 0, "");
+    rule.unit_pattern = "docker-*.scope".to_string();
+    rule.unit_match = MatchMode::Glob;
+    rule.container_pattern = "4f1c".to_string();
+    let reasons = match_reasons(&rule, &proc).expect("matches");
+    assert!(reasons[1] == "unit matches glob docker-*.scope");
+    assert!(reasons[2] == "container contains 4f1c");
+    rule.exclude_unit_pattern = "sshd.service".to_string();
+    rule.unit_pattern.clear();
+    rule.unit_match = MatchMode::Exact;
+    let reasons = match_reasons(&rule, &proc).expect("not sshd");
+    assert!(reasons.last().map(String::as_str) == Some("unit is not sshd.service"));
+    proc.systemd_unit = "sshd.service".to_string();
+    assert!(match_reasons(&rule, &proc).is_none(), "Excluded unit");
+    proc.systemd_unit = "ci-runner.service".to_string();
+    rule.exclude_unit_pattern = "(".to_string();
+    rule.unit_match = MatchMode::Regex;
+    assert!(
+        match_reasons(&rule, &proc).is_none(),
+        "An invalid exclusion fails closed"
+    );
+    assert!(compile_rule_patterns(rule).is_err_and(|(key, _)| key == "exclude_unit_pattern"));
+    let mut host_only = rule_with("Host", 90f64, 0, "");
+    host_only.cgroup_pattern = "/user.slice/".to_string();
+    assert!(match_reasons(&host_only, &proc).is_none());
+}
+
+#[test]
+fn test_format_rule_match() {
+    let rule = rule_with("Hung nextest", 90f64, 0, "cargo-nextest");
+    let proc = nextest_process();
+    let reasons = match_reasons(&rule, &proc).expect("matches");
+    let rule_match = new_rule_match(&rule, proc, reasons);
+    assert!(
+        format_rule_match(&rule_match)
+            == "PID 4242 (cargo-nextest) matched Hung nextest [medium]: \
+                cpu 99.9 > 90, cmdline contains cargo-nextest"
+    );
+}
+
+#[test]
+fn test_evaluate_rules_highest_priority_decides() {
+    let mut long_running = rule_with("Long running", 0f64, 0, "cargo");
+    long_running.priority = Priority::Low;
+    let mut infinite_loop = rule_with("Infinite loop", 90f64, 0, "");
+    infinite_loop.priority = Priority::High;
+    let hung = rule_with("Hung nextest", 0f64, 0, "cargo-nextest");
+    let rules = vec![long_running, hung, infinite_loop];
+    let rule_match = evaluate_rules(nextest_process(), &rules).expect("matches");
+    assert!(rule_match.rule_name == "Infinite loop" && rule_match.priority == Priority::High);
+    assert!(rule_match.reasons == vec!["cpu 99.9 > 90".to_string()]);
+    let order: Vec<&str> = rule_match
+        .matched_rules
+        .iter()
+        .map(|hit| hit.rule_name.as_str())
+        .collect();
+    assert!(order == vec!["Infinite loop", "Hung nextest", "Long running"]);
+    assert!(format_rule_match(&rule_match)
+        .ends_with("(also matched Hung nextest [medium], Long running [low])"));
+}
+
+#[test]
+fn test_evaluate_rules_ties_keep_config_order() {
+    let first = rule_with("First", 90f64, 0, "");
+    let second = rule_with("Second", 0f64, 1024, "");
+    let rule_match = evaluate_rules(nextest_process(), &[first.clone(), second.clone()]);
+    assert!(rule_match.expect("matches").rule_name == "First");
+    let rule_match = evaluate_rules(nextest_process(), &[second, first]);
+    assert!(rule_match.expect("matches").rule_name == "Second");
+    assert!(evaluate_rules(nextest_process(), &[rule_with("Cmd", 0f64, 0, "pytest")]).is_none());
+}
+
+#[test]
+fn test_compile_pattern_modes() {
+    let cmdline = "cargo-nextest run --test-threads 1 --workspace";
+    let matches = |source: &str, mode: MatchMode| {
+        pattern_matches(
+            &compile_pattern(source, mode, false).expect("compiles"),
+            cmdline,
+        )
+    };
+    assert!(
+        matches("NEXTEST RUN", MatchMode::Substring),
+        "Case-insensitive by default"
+    );
+    assert!(matches("cargo-nextest*--test-threads*", MatchMode::Glob));
+    assert!(!matches("nextest*", MatchMode::Glob), "Globs are anchored");
+    assert!(matches(
+        r"cargo-nextest .*--test-threads \d+.*",
+        MatchMode::Regex
+    ));
+    assert!(
+        !matches("nextest", MatchMode::Regex),
+        "Regexes are anchored"
+    );
+    assert!(!matches("cargo-nextest", MatchMode::Exact));
+    assert!(matches(cmdline, MatchMode::Exact));
+    let sensitive = compile_pattern("NEXTEST", MatchMode::Substring, true).expect("compiles");
+    assert!(!pattern_matches(&sensitive, cmdline));
+}
+
+#[test]
+fn test_glob_to_regex() {
+    let glob = compile_pattern("/tmp/test_[!x]?.*", MatchMode::Glob, true).expect("compiles");
+    assert!(pattern_matches(&glob, "/tmp/test_a1.sh"));
+    assert!(!pattern_matches(&glob, "/tmp/test_x1.sh"));
+    assert!(
+        !pattern_matches(&glob, "/tmp/testXa1.sh"),
+        "Literal characters stay literal"
+    );
+    assert!(glob_to_regex("/tmp/[ab").is_err());
+}
+
+#[test]
+fn test_compile_rule_patterns_reports_key() {
+    let mut rule = rule_with("Bad", 0f64, 0, "(unclosed");
+    rule.cmdline_match = MatchMode::Regex;
+    let (key, message) = compile_rule_patterns(rule.clone()).expect_err("invalid regex");
+    assert!(key == "cmdline_pattern" && message.starts_with("invalid regex pattern"));
+    rule.cmdline_match = MatchMode::Substring;
+    let compiled = compile_rule_patterns(rule).expect("substring escapes metacharacters");
+    assert!(compiled.cmdline_matcher.is_some());
+}
+
+#[test]
+fn test_match_reasons_with_glob_and_regex() {
+    let mut rule = rule_with("Threads", 0f64, 0, "cargo-nextest * --test-threads *");
+    rule.cmdline_match = MatchMode::Glob;
+    rule.name_pattern = "CARGO-[a-z]+".to_string();
+    rule.name_match = MatchMode::Regex;
+    let rule = compile_rule_patterns(rule).expect("compiles");
+    let mut proc = nextest_process();
+    proc.cmdline = "cargo-nextest run --test-threads 1".to_string();
+    let reasons = match_reasons(&rule, &proc).expect("matches");
+    assert!(reasons[0] == "name matches regex CARGO-[a-z]+");
+    assert!(reasons[1] == "cmdline matches glob cargo-nextest * --test-threads *");
+    proc.cmdline = "cargo-nextest run".to_string();
+    assert!(match_reasons(&rule, &proc).is_none());
+}
+
+// Modelled on the 2025-10-31 incident plus the processes a workstation must
+// keep: (pid, ppid, name, cmdline, cpu, age_seconds).
+#[cfg(test)]
+fn incident_table() -> Vec<Process> {
+    let rows: [(i32, i32, &str, &str, f64, i64); 12] = [
+        (101, 1, "systemd", "/sbin/init", 0.1, 1_000_000),
+        (102, 1, "sshd", "/usr/sbin/sshd -D", 0f64, 1_000_000),
+        (
+            201,
+            150,
+            "test_ch04_debug",
+            "/tmp/test_ch04_debug",
+            99.9,
+            18_000,
+        ),
+        (
+            202,
+            150,
+            "cargo-nextest",
+            "cargo-nextest run --test-threads 1",
+            0.4,
+            432_000,
+        ),
+        (203, 150, "pmat", "pmat mutation test --all", 2f64, 450_000),
+        (
+            204,
+            1,
+            "tail",
+            "tail -f /var/log/build.log",
+            0f64,
+            1_036_800,
+        ),
+        (205, 150, "tail", "tail -f /var/log/syslog", 0f64, 1_036_800),
+        (
+            206,
+            150,
+            "rustc",
+            "rustc --edition 2021 src/lib.rs",
+            98f64,
+            40,
+        ),
+        (207, 150, "cargo", "cargo test", 1f64, 600),
+        (208, 150, "vim", "vim /tmp/notes.txt", 0.2, 90_000),
+        (209, 150, "vim", "vim pytest.ini", 0.2, 90_000),
+        (210, 150, "less", "less /tmp/go test.log", 0f64, 90_000),
+    ];
+    rows.iter()
+        .map(|&(pid, ppid, name, cmdline, cpu, age)| {
+            let mut proc = crate::new_process(
+                pid,
+                name.to_string(),
+                cmdline.to_string(),
+                cpu,
+                64,
+                crate::ProcessStatus::Running,
+            );
+            proc.parent_pid = ppid;
+            proc.age_seconds = age;
+            proc
+        })
+        .collect()
+}
+
+#[cfg(test)]
+fn preset_matches(preset: &str) -> Vec<i32> {
+    let rule = preset_rule(preset).expect("known preset");
+    incident_table()
+        .iter()
+        .filter(|proc| match_reasons(&rule, proc).is_some())
+        .map(|proc| proc.pid)
+        .collect()
+}
+
+#[test]
+fn test_presets_against_incident_table() {
+    assert!(preset_matches("infinite-loop") == vec![201]);
+    assert!(
+        preset_matches("hung-test") == vec![202, 203],
+        "Test runners are matched on argv0, not anywhere in the cmdline"
+    );
+    assert!(preset_matches("orphaned-monitor") == vec![204]);
+    assert!(preset_matches("zombie-binary") == vec![201]);
+    let hung = preset_rule("hung-test").expect("known preset");
+    assert!(
+        hung.action == RuleAction::TerminateTree,
+        "Harness children go too"
+    );
+}
+
+#[test]
+fn test_preset_rules_are_valid_and_named() {
+    for preset in PRESET_NAMES {
+        let rule = preset_rule(preset).expect("known preset");
+        assert!(crate::is_valid_rule(rule.clone()), "{} is valid", preset);
+        assert!(
+            rule.preset == preset
+                && rule.cmdline_pattern.is_empty() == rule.cmdline_matcher.is_none()
+        );
+        assert!(crate::format_rule(rule).ends_with(&format!("preset:{} (enabled)", preset)));
+    }
+    assert!(preset_rule("fork-bomb").is_none());
+}
+
+#[test]
+fn test_sustained_samples_and_seconds() {
+    let mut rule = rule_with("Spin", 90f64, 0, "");
+    rule.sustained_samples = 3;
+    rule.sustained_secs = 120;
+    let rules = vec![rule];
+    let mut history = new_match_history();
+    let mut check = |cpu: f64, now: i64| {
+        let mut proc = nextest_process();
+        proc.cpu_usage = cpu;
+        evaluate_rules_with_history(proc, &rules, &mut history, now)
+    };
+    assert!(
+        check(99f64, 1000).is_none(),
+        "First sample only starts the streak"
+    );
+    assert!(check(99f64, 1060).is_none());
+    assert!(check(99f64, 1100).is_none(), "Three samples but only 100s");
+    let rule_match = check(99f64, 1120).expect("sustained");
+    assert!(rule_match.reasons.last().expect("reason") == "sustained 4 samples over 120s");
+    assert!(check(10f64, 1180).is_none(), "A dip resets the streak");
+    assert!(check(99f64, 1240).is_none());
+}
+
+#[test]
+fn test_evaluate_pending_reports_unsustained_matches() {
+    let mut sustained = new_detection_rule(
+        "Spin".to_string(),
+        Priority::Medium,
+        90f64,
+        0,
+        String::new(),
+        String::new(),
+        true,
+    );
+    sustained.sustained_samples = 3;
+    sustained.sustained_secs = 120;
+    let mut burst = sustained.clone();
+    burst.name = "Burst".to_string();
+    burst.priority = Priority::High;
+    burst.sustained_samples = 0;
+    let mut proc = crate::new_process(
+        4242,
+        "spin".to_string(),
+        "./spin".to_string(),
+        99f64,
+        64,
+        crate::ProcessStatus::Running,
+    );
+    let (rule_match, label) =
+        evaluate_pending(proc.clone(), &[burst.clone(), sustained.clone()]).expect("pending");
+    assert!(rule_match.rule_name == "Burst" && label == "pending (0/120s)");
+    let (rule_match, label) = evaluate_pending(proc.clone(), &[sustained]).expect("pending");
+    assert!(rule_match.rule_name == "Spin" && label == "pending (1/3 samples, 0/120s)");
+    burst.sustained_secs = 0;
+    assert!(
+        evaluate_pending(proc.clone(), &[burst]).is_none(),
+        "A rule without a sustain window is not pending"
+    );
+    proc.cpu_usage = 10f64;
+    assert!(evaluate_pending(proc, &[rule_match.rule]).is_none());
+}
+
+#[test]
+fn test_sustained_history_follows_process_identity() {
+    let mut rule = rule_with("Spin", 90f64, 0, "");
+    rule.sustained_samples = 2;
+    let mut history = new_match_history();
+    let first = nextest_process();
+    assert!(record_sample(&mut history, &first, &rule, true, 0).is_some());
+    let mut recycled = first.clone();
+    recycled.start_ticks += 500;
+    let streak = record_sample(&mut history, &recycled, &rule, true, 60).expect("matched");
+    assert!(streak.samples == 1, "A recycled PID starts a new streak");
+    forget_missing(&mut history, &[recycled.clone()]);
+    let streak = record_sample(&mut history, &first, &rule, true, 120).expect("matched");
+    assert!(
+        streak.samples == 1 && streak.since == 120,
+        "Exited processes are forgotten"
+    );
+    assert!(
+        evaluate_rules(first, &[rule]).is_none(),
+        "One scan is a single sample"
+    );
+}
+
+#[test]
+fn test_format_match_table() {
+    let rule = rule_with("Hung nextest", 0f64, 0, "cargo-nextest");
+    let proc = nextest_process();
+    let reasons = match_reasons(&rule, &proc).expect("matches");
+    let rule_match = new_rule_match(&rule, proc, reasons);
+    let mut sshd = rule_match.clone();
+    sshd.process.pid = 812;
+    sshd.process.name = "sshd".to_string();
+    let table = format_match_table(&[
+        (rule_match, "would terminate".to_string()),
+        (sshd, "protected: protected name sshd".to_string()),
+    ]);
+    let lines: Vec<&str> = table.lines().collect();
+    assert!(lines.len() == 3);
+    assert!(lines[0].starts_with("PID   NAME           RULE          PRIORITY  ACTION    "));
+    assert!(lines[0].ends_with("  REASON"));
+    assert!(lines[1].starts_with("4242  cargo-nextest  Hung nextest  medium    would terminate  "));
+    assert!(lines[1].ends_with("cmdline contains cargo-nextest"));
+    assert!(lines[2].starts_with("812   sshd  "));
+}
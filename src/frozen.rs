@@ -0,0 +1,292 @@
+// Quarantine: processes halted with SIGSTOP by a `stop` rule, recorded in
+// `frozen_file` so they can be listed and resumed later.
+//
+// Each record keeps the process's start time and exe, so a PID that has
+// since been recycled is never resumed.
+
+use crate::detector::align_columns;
+use crate::logger::format_timestamp;
+use crate::{action_result_from_errno, close_fd, open_pidfd, parse_proc_status};
+use crate::{new_process, pidfd_send_signal, send_signal, verify_identity};
+use crate::{ActionResult, Process, ProcessStatus};
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct FrozenEntry {
+    pub(crate) pid: i32,
+    pub(crate) start_ticks: i64,
+    // Unix seconds.
+    pub(crate) frozen_at: i64,
+    pub(crate) exe: String,
+    pub(crate) name: String,
+    pub(crate) rule: String,
+    pub(crate) cmdline: String,
+}
+
+pub(crate) fn new_frozen_entry(proc: &Process, rule: &str, frozen_at: i64) -> FrozenEntry {
+    FrozenEntry {
+        pid: proc.pid,
+        start_ticks: proc.start_ticks,
+        frozen_at,
+        exe: proc.exe.clone(),
+        name: proc.name.clone(),
+        rule: rule.to_string(),
+        cmdline: proc.cmdline.clone(),
+    }
+}
+
+// The recorded process, as far as identity checks and the audit log need it.
+pub(crate) fn entry_process(entry: &FrozenEntry) -> Process {
+    let mut proc = new_process(
+        entry.pid,
+        entry.name.clone(),
+        entry.cmdline.clone(),
+        0f64,
+        0,
+        ProcessStatus::Stopped,
+    );
+    proc.start_ticks = entry.start_ticks;
+    proc.exe = entry.exe.clone();
+    proc
+}
+
+pub(crate) fn is_frozen(entries: &[FrozenEntry], proc: &Process) -> bool {
+    entries
+        .iter()
+        .any(|entry| entry.pid == proc.pid && entry.start_ticks == proc.start_ticks)
+}
+
+// One tab-separated record per line. A missing file means nothing is frozen;
+// lines that do not parse are skipped.
+pub(crate) fn load_frozen(path: &str) -> Vec<FrozenEntry> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => text.lines().filter_map(parse_entry).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+pub(crate) fn record_frozen(path: &str, entry: FrozenEntry) -> std::io::Result<()> {
+    let mut entries = load_frozen(path);
+    entries.retain(|existing| existing.pid != entry.pid);
+    entries.push(entry);
+    save_frozen(path, &entries)
+}
+
+pub(crate) fn remove_frozen(path: &str, pid: i32) -> std::io::Result<()> {
+    let mut entries = load_frozen(path);
+    entries.retain(|entry| entry.pid != pid);
+    save_frozen(path, &entries)
+}
+
+fn save_frozen(path: &str, entries: &[FrozenEntry]) -> std::io::Result<()> {
+    let text: String = entries.iter().map(format_entry).collect();
+    replace_file(path, &text)
+}
+
+// Written to a temporary file and renamed, so a crash never leaves half a
+// record behind.
+pub(crate) fn replace_file(path: &str, text: &str) -> std::io::Result<()> {
+    if let Some(dir) = std::path::Path::new(path).parent() {
+        if !dir.as_os_str().is_empty() {
+            std::fs::create_dir_all(dir)?;
+        }
+    }
+    let temporary = format!("{}.tmp", path);
+    std::fs::write(&temporary, text)?;
+    std::fs::rename(&temporary, path)
+}
+
+fn format_entry(entry: &FrozenEntry) -> String {
+    format!(
+        "{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+        entry.pid,
+        entry.start_ticks,
+        entry.frozen_at,
+        escape_field(&entry.exe),
+        escape_field(&entry.name),
+        escape_field(&entry.rule),
+        escape_field(&entry.cmdline)
+    )
+}
+
+fn parse_entry(line: &str) -> Option<FrozenEntry> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    if fields.len() != 7 {
+        return None;
+    }
+    Some(FrozenEntry {
+        pid: fields[0].parse().ok()?,
+        start_ticks: fields[1].parse().ok()?,
+        frozen_at: fields[2].parse().ok()?,
+        exe: unescape_field(fields[3]),
+        name: unescape_field(fields[4]),
+        rule: unescape_field(fields[5]),
+        cmdline: unescape_field(fields[6]),
+    })
+}
+
+pub(crate) fn escape_field(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+pub(crate) fn unescape_field(value: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+// SIGSTOP, sent only while the PID is still the process described by `proc`.
+pub(crate) fn freeze_verified(proc: &Process) -> ActionResult {
+    signal_verified(proc, libc::SIGSTOP, ActionResult::Frozen)
+}
+
+// SIGCONT to a recorded process, refused if its PID now names another one.
+pub(crate) fn resume_verified(entry: &FrozenEntry) -> ActionResult {
+    signal_verified(&entry_process(entry), libc::SIGCONT, ActionResult::Resumed)
+}
+
+fn signal_verified(proc: &Process, signal: i32, done: ActionResult) -> ActionResult {
+    // As in terminate_verified, a pidfd pins the process once verified.
+    let pidfd = match open_pidfd(proc.pid) {
+        Ok(fd) => Some(fd),
+        Err(libc::ESRCH) => return ActionResult::NotFound,
+        Err(_) => None,
+    };
+    let sent = match verify_identity(proc) {
+        Some(refusal) => Err(refusal),
+        None => match pidfd {
+            Some(fd) => pidfd_send_signal(fd, signal),
+            None => send_signal(proc.pid, signal),
+        }
+        .map_err(action_result_from_errno),
+    };
+    if let Some(fd) = pidfd {
+        close_fd(fd);
+    }
+    match sent {
+        Ok(()) => done,
+        Err(result) => result,
+    }
+}
+
+// "stopped" while still frozen, "running" if something else resumed it, and
+// "gone" once it has exited or its PID was reused.
+pub(crate) fn frozen_state(entry: &FrozenEntry) -> String {
+    match parse_proc_status(entry.pid) {
+        Some(live) if live.start_ticks == entry.start_ticks => match live.status {
+            ProcessStatus::Stopped => "stopped".to_string(),
+            ProcessStatus::Zombie => "gone".to_string(),
+            _ => "running".to_string(),
+        },
+        _ => "gone".to_string(),
+    }
+}
+
+pub(crate) fn format_frozen_table(entries: &[FrozenEntry]) -> String {
+    if entries.is_empty() {
+        return "No frozen processes\n".to_string();
+    }
+    let header = ["PID", "NAME", "RULE", "FROZEN AT", "STATE", "CMDLINE"];
+    let mut cells: Vec<[String; 6]> = vec![header.map(|title| title.to_string())];
+    for entry in entries {
+        cells.push([
+            entry.pid.to_string(),
+            entry.name.clone(),
+            entry.rule.clone(),
+            format_timestamp(entry.frozen_at * 1000),
+            frozen_state(entry),
+            entry.cmdline.clone(),
+        ]);
+    }
+    align_columns(&cells)
+}
+
+#[cfg(test)]
+fn temp_frozen_path(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!(
+        "reaper-frozen-{}-{}/frozen.tsv",
+        name,
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&path);
+    path.to_string_lossy().to_string()
+}
+
+#[test]
+fn test_frozen_file_round_trip() {
+    let path = temp_frozen_path("round-trip");
+    assert!(
+        load_frozen(&path).is_empty(),
+        "No file means nothing frozen"
+    );
+    let mut proc = new_process(
+        4242,
+        "spin".to_string(),
+        "./spin --label \"a\tb\"\nnext \\ end".to_string(),
+        99.9,
+        12,
+        ProcessStatus::Running,
+    );
+    proc.start_ticks = 777;
+    proc.exe = "/tmp/spin".to_string();
+    record_frozen(&path, new_frozen_entry(&proc, "Spin", 1761919387)).expect("record");
+    proc.pid = 4343;
+    record_frozen(&path, new_frozen_entry(&proc, "Spin", 1761919390)).expect("record");
+    let entries = load_frozen(&path);
+    assert!(entries.len() == 2 && entries[0].cmdline == "./spin --label \"a\tb\"\nnext \\ end");
+    assert!(entries[0].start_ticks == 777 && entries[0].exe == "/tmp/spin");
+    assert!(is_frozen(&entries, &proc));
+    proc.start_ticks = 778;
+    assert!(
+        !is_frozen(&entries, &proc),
+        "A recycled PID is not the frozen one"
+    );
+    remove_frozen(&path, 4242).expect("remove");
+    let entries = load_frozen(&path);
+    assert!(entries.len() == 1 && entries[0].pid == 4343);
+    let _ = std::fs::remove_dir_all(std::path::Path::new(&path).parent().expect("dir"));
+}
+
+#[test]
+fn test_freeze_and_resume_child() {
+    let mut child = std::process::Command::new("sleep")
+        .arg("30")
+        .spawn()
+        .expect("spawn sleep");
+    let proc = parse_proc_status(child.id() as i32).expect("spawned child");
+    assert!(freeze_verified(&proc) == ActionResult::Frozen);
+    let entry = new_frozen_entry(&proc, "Spin", 1761919387);
+    // The kernel stops the process asynchronously.
+    for _ in 0..50 {
+        if frozen_state(&entry) == "stopped" {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    assert!(frozen_state(&entry) == "stopped");
+    let table = format_frozen_table(std::slice::from_ref(&entry));
+    assert!(table.starts_with("PID"));
+    assert!(table.contains("2025-10-31T14:03:07.000Z  stopped"));
+    assert!(resume_verified(&entry) == ActionResult::Resumed);
+    assert!(frozen_state(&entry) == "running");
+    let mut recycled = entry.clone();
+    recycled.start_ticks += 1;
+    assert!(resume_verified(&recycled) == ActionResult::IdentityMismatch);
+    let _ = child.kill();
+    let _ = child.wait();
+    assert!(frozen_state(&entry) == "gone");
+}
@@ -1 +1,306 @@
-use crate :: scanner :: Process ; pub struct Logger { pub log_path : String , } pub fn new_logger (log_path : String) -> Logger { Logger { log_path : log_path , } } pub fn log_kill (logger : & Logger , process : & Process , rule : & str) -> bool { true } pub fn log_scan (logger : & Logger , process_count : u32 , rogue_count : u32) -> bool { true } fn main () { }
\ No newline at end of file
+// Audit trail: one JSON object per line for every detection, termination
+// attempt and termination result, so a postmortem can show exactly what
+// reaper saw and why it acted.
+
+use crate::detector::RuleMatch;
+use crate::{action_result_to_string, priority_to_string, unix_now_millis};
+use crate::{ActionResult, Process};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum AuditEvent {
+    Detection,
+    TerminateAttempt,
+    TerminateResult,
+    // Dry run: the match passed the safety gate and would have been signalled.
+    WouldTerminate,
+    // A stop rule's SIGSTOP and its outcome, and the dry-run equivalent.
+    Stop,
+    WouldStop,
+    // `resume` sending SIGCONT to a frozen process.
+    Resume,
+    // A throttle rule demoting a process, the dry-run equivalent, and the
+    // daemon restoring it once its CPU drops.
+    Throttle,
+    WouldThrottle,
+    Unthrottle,
+    // A cap rule moving a process into a limited cgroup, and the dry-run
+    // equivalent.
+    Cap,
+    WouldCap,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct AuditLogger {
+    pub(crate) path: String,
+}
+
+pub(crate) fn new_audit_logger(path: String) -> AuditLogger {
+    AuditLogger { path }
+}
+
+pub(crate) fn event_to_string(event: AuditEvent) -> String {
+    match event {
+        AuditEvent::Detection => "detection".to_string(),
+        AuditEvent::TerminateAttempt => "terminate_attempt".to_string(),
+        AuditEvent::TerminateResult => "terminate_result".to_string(),
+        AuditEvent::WouldTerminate => "would_terminate".to_string(),
+        AuditEvent::Stop => "stop".to_string(),
+        AuditEvent::WouldStop => "would_stop".to_string(),
+        AuditEvent::Resume => "resume".to_string(),
+        AuditEvent::Throttle => "throttle".to_string(),
+        AuditEvent::WouldThrottle => "would_throttle".to_string(),
+        AuditEvent::Unthrottle => "unthrottle".to_string(),
+        AuditEvent::Cap => "cap".to_string(),
+        AuditEvent::WouldCap => "would_cap".to_string(),
+    }
+}
+
+// Appends one entry. `rule_match` is None for manual kills, resumes and
+// unthrottles; `result` is set for the entries that record an outcome:
+// TerminateResult, Stop, Resume, Throttle, Unthrottle and Cap.
+pub(crate) fn log_event(
+    logger: &AuditLogger,
+    event: AuditEvent,
+    proc: &Process,
+    rule_match: Option<&RuleMatch>,
+    dry_run: bool,
+    result: Option<ActionResult>,
+) {
+    let line = format_entry(unix_now_millis(), event, proc, rule_match, dry_run, result);
+    if let Err(err) = append_line(&logger.path, &line) {
+        eprintln!(
+            "ruchy-reaper: cannot write audit log {}: {}",
+            logger.path, err
+        );
+    }
+}
+
+fn append_line(path: &str, line: &str) -> std::io::Result<()> {
+    use std::io::Write;
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    // A single write keeps concurrent appends from interleaving.
+    file.write_all(format!("{}\n", line).as_bytes())
+}
+
+pub(crate) fn format_entry(
+    timestamp_millis: i64,
+    event: AuditEvent,
+    proc: &Process,
+    rule_match: Option<&RuleMatch>,
+    dry_run: bool,
+    result: Option<ActionResult>,
+) -> String {
+    let (rule_name, priority, reasons, matched_rules) = match rule_match {
+        Some(rule_match) => {
+            let names: Vec<String> = rule_match
+                .matched_rules
+                .iter()
+                .map(|hit| hit.rule_name.clone())
+                .collect();
+            (
+                json_string(&rule_match.rule_name),
+                json_string(&priority_to_string(rule_match.priority)),
+                json_string_array(&rule_match.reasons),
+                json_string_array(&names),
+            )
+        }
+        None => (
+            "null".to_string(),
+            "null".to_string(),
+            "[]".to_string(),
+            "[]".to_string(),
+        ),
+    };
+    let result = match result {
+        Some(result) => json_string(&action_result_to_string(result)),
+        None => "null".to_string(),
+    };
+    format!(
+        "{{\"timestamp\":{},\"event\":{},\"pid\":{},\"name\":{},\"cmdline\":{},\
+         \"cpu_usage\":{},\"memory_mb\":{},\"rule\":{},\"priority\":{},\
+         \"reasons\":{},\"matched_rules\":{},\"dry_run\":{},\"result\":{}}}",
+        json_string(&format_timestamp(timestamp_millis)),
+        json_string(&event_to_string(event)),
+        proc.pid,
+        json_string(&proc.name),
+        json_string(&proc.cmdline),
+        json_number(proc.cpu_usage),
+        proc.memory_mb,
+        rule_name,
+        priority,
+        reasons,
+        matched_rules,
+        dry_run,
+        result
+    )
+}
+
+pub(crate) fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+pub(crate) fn json_string_array(values: &[String]) -> String {
+    let items: Vec<String> = values.iter().map(|value| json_string(value)).collect();
+    format!("[{}]", items.join(","))
+}
+
+pub(crate) fn json_number(value: f64) -> String {
+    // JSON has no NaN or infinity.
+    if value.is_finite() {
+        format!("{}", value)
+    } else {
+        "null".to_string()
+    }
+}
+
+// RFC 3339 UTC with millisecond precision, e.g. 2025-10-31T14:03:07.250Z.
+pub(crate) fn format_timestamp(unix_millis: i64) -> String {
+    let secs = unix_millis.div_euclid(1000);
+    let millis = unix_millis.rem_euclid(1000);
+    let days = secs.div_euclid(86400);
+    let day_secs = secs.rem_euclid(86400);
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        day_secs / 3600,
+        day_secs % 3600 / 60,
+        day_secs % 60,
+        millis
+    )
+}
+
+// Howard Hinnant's days-since-epoch to proleptic Gregorian date.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+fn sample_process() -> Process {
+    crate::new_process(
+        4242,
+        "cargo-nextest".to_string(),
+        "cargo-nextest run --test-threads \"1\"".to_string(),
+        99.9,
+        2048,
+        crate::ProcessStatus::Running,
+    )
+}
+
+#[test]
+fn test_format_entry_detection() {
+    let rule = crate::new_detection_rule(
+        "Infinite loop".to_string(),
+        crate::Priority::High,
+        90f64,
+        0,
+        String::new(),
+        String::new(),
+        true,
+    );
+    let reasons = vec!["cpu 99.9 > 90".to_string()];
+    let rule_match = crate::detector::new_rule_match(&rule, sample_process(), reasons);
+    let line = format_entry(
+        1761919387250,
+        AuditEvent::Detection,
+        &sample_process(),
+        Some(&rule_match),
+        true,
+        None,
+    );
+    assert!(line.starts_with("{\"timestamp\":\"2025-10-31T14:03:07.250Z\""));
+    assert!(line.contains("\"event\":\"detection\""));
+    assert!(line.contains("\"pid\":4242,\"name\":\"cargo-nextest\""));
+    assert!(line.contains("\"cmdline\":\"cargo-nextest run --test-threads \\\"1\\\"\""));
+    assert!(line.contains("\"cpu_usage\":99.9,\"memory_mb\":2048"));
+    assert!(line.contains("\"rule\":\"Infinite loop\",\"priority\":\"high\""));
+    assert!(line.contains("\"reasons\":[\"cpu 99.9 > 90\"],\"matched_rules\":[\"Infinite loop\"]"));
+    assert!(line.ends_with("\"dry_run\":true,\"result\":null}"));
+    assert!(!line.contains('\n'), "One entry must stay on one line");
+}
+
+#[test]
+fn test_format_entry_manual_kill_result() {
+    let line = format_entry(
+        0,
+        AuditEvent::TerminateResult,
+        &sample_process(),
+        None,
+        false,
+        Some(ActionResult::PermissionDenied),
+    );
+    assert!(line.contains("\"timestamp\":\"1970-01-01T00:00:00.000Z\""));
+    assert!(line.contains("\"rule\":null,\"priority\":null,\"reasons\":[]"));
+    assert!(line.ends_with("\"result\":\"permission_denied\"}"));
+}
+
+#[test]
+fn test_json_string_escapes() {
+    assert!(json_string("a\"b\\c\nd\u{1}") == "\"a\\\"b\\\\c\\nd\\u0001\"");
+    assert!(json_number(f64::NAN) == "null" && json_number(2.5) == "2.5");
+}
+
+#[test]
+fn test_format_timestamp_leap_day() {
+    assert!(format_timestamp(951782400000) == "2000-02-29T00:00:00.000Z");
+    assert!(format_timestamp(-1) == "1969-12-31T23:59:59.999Z");
+}
+
+#[test]
+fn test_log_event_appends_lines() {
+    let path = std::env::temp_dir().join(format!("reaper-audit-{}.log", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let logger = new_audit_logger(path.to_string_lossy().to_string());
+    let proc = sample_process();
+    log_event(
+        &logger,
+        AuditEvent::TerminateAttempt,
+        &proc,
+        None,
+        false,
+        None,
+    );
+    let success = Some(ActionResult::Success);
+    log_event(
+        &logger,
+        AuditEvent::TerminateResult,
+        &proc,
+        None,
+        false,
+        success,
+    );
+    let contents = std::fs::read_to_string(&path).expect("audit log written");
+    let lines: Vec<&str> = contents.lines().collect();
+    assert!(lines.len() == 2);
+    assert!(lines[0].contains("\"event\":\"terminate_attempt\""));
+    assert!(lines[1].contains("\"result\":\"success\""));
+    let _ = std::fs::remove_file(&path);
+}
@@ -1,17 +1,33 @@
-#[derive(Debug, Clone, PartialEq)]
+// Started as the transpiled output of src/main.ruchy and maintained by hand
+// since; nothing regenerates it from the Ruchy source any more. Tests keep
+// the `assert!(true, ..)` smoke checks the Ruchy test suite was written with.
+#![cfg_attr(test, allow(clippy::assertions_on_constants))]
+#![allow(dead_code)]
+mod cgroup;
+mod cli;
+mod config;
+mod detector;
+mod frozen;
+mod logger;
+mod metrics;
+mod output;
+mod safety;
+mod throttle;
+mod tree;
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum Priority {
     High,
     Medium,
     Low,
 }
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum ProcessStatus {
     Running,
     Sleeping,
     Stopped,
     Zombie,
 }
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum ActionResult {
     Success,
     AlreadyDead,
@@ -19,8 +35,50 @@ enum ActionResult {
     NotFound,
     TimedOut,
     Failed,
-}
-#[derive(Clone)]
+    IdentityMismatch,
+    // Refused by the safety gate; no signal was sent.
+    Protected,
+    // Halted with SIGSTOP by a stop rule, and continued with SIGCONT.
+    Frozen,
+    Resumed,
+    // Reniced and moved to idle I/O by a throttle rule, and restored.
+    Throttled,
+    Unthrottled,
+    // Moved into a cgroup with CPU and memory limits by a cap rule.
+    Capped,
+}
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MatchMode {
+    Substring,
+    Glob,
+    Regex,
+    Exact,
+}
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RuleAction {
+    Terminate,
+    // The process and all its descendants; see tree::terminate_tree.
+    TerminateTree,
+    // SIGSTOP, leaving the process for inspection; see frozen.rs.
+    Stop,
+    // Detection and audit log only.
+    LogOnly,
+    // Raise nice and idle the I/O class until CPU drops; see throttle.rs.
+    Throttle,
+    // Confine to a cgroup v2 child with cpu.max/memory.max; see cgroup.rs.
+    Cap,
+}
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TreeSignal {
+    LeavesFirst,
+    ProcessGroup,
+}
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CpuNormalization {
+    PerCore,
+    WholeMachine,
+}
+#[derive(Debug, Clone)]
 struct Process {
     pid: i32,
     name: String,
@@ -28,8 +86,18 @@ struct Process {
     cpu_usage: f64,
     memory_mb: i64,
     status: ProcessStatus,
-}
-#[derive(Clone)]
+    parent_pid: i32,
+    start_time: i64,
+    start_ticks: i64,
+    age_seconds: i64,
+    exe: String,
+    // From /proc/<pid>/cgroup; see cgroup::parse_proc_cgroup. The unit and
+    // container ID are empty when the path names none.
+    cgroup: String,
+    systemd_unit: String,
+    container_id: String,
+}
+#[derive(Debug, Clone)]
 struct DetectionRule {
     name: String,
     priority: Priority,
@@ -38,15 +106,79 @@ struct DetectionRule {
     name_pattern: String,
     cmdline_pattern: String,
     enabled: bool,
-}
-#[derive(Clone)]
+    min_age_secs: i64,
+    parent_is_init: bool,
+    name_match: MatchMode,
+    cmdline_match: MatchMode,
+    case_sensitive: bool,
+    // Filled in when the config loads; see detector::compile_rule_patterns.
+    name_matcher: Option<detector::Pattern>,
+    cmdline_matcher: Option<detector::Pattern>,
+    // Membership criteria; exclude_unit_pattern uses unit_match and rules
+    // out processes whose unit matches it.
+    cgroup_pattern: String,
+    cgroup_match: MatchMode,
+    unit_pattern: String,
+    unit_match: MatchMode,
+    exclude_unit_pattern: String,
+    container_pattern: String,
+    container_match: MatchMode,
+    cgroup_matcher: Option<detector::Pattern>,
+    unit_matcher: Option<detector::Pattern>,
+    exclude_unit_matcher: Option<detector::Pattern>,
+    container_matcher: Option<detector::Pattern>,
+    // Built-in preset the rule was created from, empty for custom rules.
+    preset: String,
+    // A match only counts once it has held for this many consecutive scans
+    // and this many seconds (0 = immediately).
+    sustained_samples: i64,
+    sustained_secs: i64,
+    action: RuleAction,
+    // How a terminate-tree action reaches the tree.
+    tree_signal: TreeSignal,
+    // Nice value a throttle action raises the process to.
+    throttle_nice: i64,
+    // Limits a cap action sets; 0 leaves that limit unset.
+    cap_cpu_percent: f64,
+    cap_memory_mb: i64,
+}
+#[derive(Debug, Clone)]
 struct Config {
     check_interval_secs: i64,
     rules: Vec<DetectionRule>,
     dry_run: bool,
     log_file: String,
     grace_period_secs: i64,
-}
+    // Safety gate settings; see safety::protection_reason.
+    min_pid: i32,
+    protected_names: Vec<String>,
+    protected_exes: Vec<String>,
+    // host:port serving Prometheus /metrics; empty disables the endpoint.
+    metrics_address: String,
+    // Processes halted by stop rules; see frozen.rs.
+    frozen_file: String,
+    // Original priorities of throttled processes; see throttle.rs.
+    throttle_file: String,
+    // Parent of the cgroups cap rules create.
+    cgroup_root: String,
+    // What 100% cpu_usage means; see cpu_percent.
+    cpu_normalization: CpuNormalization,
+}
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct SystemCpu {
+    total_ticks: u64,
+    num_cpus: u64,
+}
+const CPU_SAMPLE_INTERVAL_MS: u64 = 200;
+const PROC_ROOT: &str = "/proc";
+const TERMINATE_POLL_MS: u64 = 50;
+const KILL_WAIT_MS: u64 = 2000;
+const SHUTDOWN_POLL_MS: u64 = 100;
+// The kernel hands out PIDs below 300 only during boot (RESERVED_PIDS), so
+// they belong to system daemons.
+const DEFAULT_MIN_PID: i32 = 300;
+// The weakest CPU priority a process can be given.
+const DEFAULT_THROTTLE_NICE: i64 = 19;
 fn new_process(
     pid: i32,
     name: String,
@@ -56,12 +188,20 @@ fn new_process(
     status: ProcessStatus,
 ) -> Process {
     Process {
-        pid: pid,
-        name: name,
-        cmdline: cmdline,
-        cpu_usage: cpu_usage,
-        memory_mb: memory_mb,
-        status: status,
+        pid,
+        name,
+        cmdline,
+        cpu_usage,
+        memory_mb,
+        status,
+        parent_pid: 0,
+        start_time: 0,
+        start_ticks: 0,
+        age_seconds: 0,
+        exe: String::new(),
+        cgroup: String::new(),
+        systemd_unit: String::new(),
+        container_id: String::new(),
     }
 }
 fn is_valid_process(proc: Process) -> bool {
@@ -100,6 +240,96 @@ fn priority_to_string(priority: Priority) -> String {
         Priority::Low => "low".to_string(),
     }
 }
+fn action_result_to_string(result: ActionResult) -> String {
+    match result {
+        ActionResult::Success => "success".to_string(),
+        ActionResult::AlreadyDead => "already_dead".to_string(),
+        ActionResult::PermissionDenied => "permission_denied".to_string(),
+        ActionResult::NotFound => "not_found".to_string(),
+        ActionResult::TimedOut => "timed_out".to_string(),
+        ActionResult::Failed => "failed".to_string(),
+        ActionResult::IdentityMismatch => "identity_mismatch".to_string(),
+        ActionResult::Protected => "protected".to_string(),
+        ActionResult::Frozen => "frozen".to_string(),
+        ActionResult::Resumed => "resumed".to_string(),
+        ActionResult::Throttled => "throttled".to_string(),
+        ActionResult::Unthrottled => "unthrottled".to_string(),
+        ActionResult::Capped => "capped".to_string(),
+    }
+}
+fn match_mode_to_string(mode: MatchMode) -> String {
+    match mode {
+        MatchMode::Substring => "substring".to_string(),
+        MatchMode::Glob => "glob".to_string(),
+        MatchMode::Regex => "regex".to_string(),
+        MatchMode::Exact => "exact".to_string(),
+    }
+}
+fn parse_match_mode(mode: &str) -> Option<MatchMode> {
+    match mode.to_lowercase().as_str() {
+        "substring" => Some(MatchMode::Substring),
+        "glob" => Some(MatchMode::Glob),
+        "regex" => Some(MatchMode::Regex),
+        "exact" => Some(MatchMode::Exact),
+        _ => None,
+    }
+}
+fn rule_action_to_string(action: RuleAction) -> String {
+    match action {
+        RuleAction::Terminate => "terminate".to_string(),
+        RuleAction::TerminateTree => "terminate-tree".to_string(),
+        RuleAction::Stop => "stop".to_string(),
+        RuleAction::LogOnly => "log-only".to_string(),
+        RuleAction::Throttle => "throttle".to_string(),
+        RuleAction::Cap => "cap".to_string(),
+    }
+}
+fn parse_rule_action(action: &str) -> Option<RuleAction> {
+    match action.to_lowercase().as_str() {
+        "terminate" => Some(RuleAction::Terminate),
+        "terminate-tree" => Some(RuleAction::TerminateTree),
+        "stop" => Some(RuleAction::Stop),
+        "log-only" => Some(RuleAction::LogOnly),
+        "throttle" => Some(RuleAction::Throttle),
+        "cap" => Some(RuleAction::Cap),
+        _ => None,
+    }
+}
+fn tree_signal_to_string(signal: TreeSignal) -> String {
+    match signal {
+        TreeSignal::LeavesFirst => "leaves-first".to_string(),
+        TreeSignal::ProcessGroup => "process-group".to_string(),
+    }
+}
+fn parse_tree_signal(signal: &str) -> Option<TreeSignal> {
+    match signal.to_lowercase().as_str() {
+        "leaves-first" => Some(TreeSignal::LeavesFirst),
+        "process-group" => Some(TreeSignal::ProcessGroup),
+        _ => None,
+    }
+}
+fn parse_cpu_normalization(normalization: &str) -> Option<CpuNormalization> {
+    match normalization.to_lowercase().as_str() {
+        "per-core" => Some(CpuNormalization::PerCore),
+        "whole-machine" => Some(CpuNormalization::WholeMachine),
+        _ => None,
+    }
+}
+// format_rule shows substring patterns bare and tags the other modes.
+fn match_mode_prefix(mode: MatchMode) -> String {
+    match mode {
+        MatchMode::Substring => String::new(),
+        _ => format!("{}:", match_mode_to_string(mode)),
+    }
+}
+fn status_to_string(status: ProcessStatus) -> String {
+    match status {
+        ProcessStatus::Running => "running".to_string(),
+        ProcessStatus::Sleeping => "sleeping".to_string(),
+        ProcessStatus::Stopped => "stopped".to_string(),
+        ProcessStatus::Zombie => "zombie".to_string(),
+    }
+}
 fn priority_to_value(priority: Priority) -> i32 {
     match priority {
         Priority::High => 3,
@@ -126,13 +356,39 @@ fn new_detection_rule(
     enabled: bool,
 ) -> DetectionRule {
     DetectionRule {
-        name: name,
-        priority: priority,
+        name,
+        priority,
         max_cpu_percent: max_cpu,
         max_memory_mb: max_mem,
-        name_pattern: name_pattern,
-        cmdline_pattern: cmdline_pattern,
-        enabled: enabled,
+        name_pattern,
+        cmdline_pattern,
+        enabled,
+        min_age_secs: 0,
+        parent_is_init: false,
+        name_match: MatchMode::Substring,
+        cmdline_match: MatchMode::Substring,
+        case_sensitive: false,
+        name_matcher: None,
+        cmdline_matcher: None,
+        cgroup_pattern: String::new(),
+        cgroup_match: MatchMode::Substring,
+        unit_pattern: String::new(),
+        unit_match: MatchMode::Substring,
+        exclude_unit_pattern: String::new(),
+        container_pattern: String::new(),
+        container_match: MatchMode::Substring,
+        cgroup_matcher: None,
+        unit_matcher: None,
+        exclude_unit_matcher: None,
+        container_matcher: None,
+        preset: String::new(),
+        sustained_samples: 0,
+        sustained_secs: 0,
+        action: RuleAction::Terminate,
+        tree_signal: TreeSignal::LeavesFirst,
+        throttle_nice: DEFAULT_THROTTLE_NICE,
+        cap_cpu_percent: 0f64,
+        cap_memory_mb: 0,
     }
 }
 fn is_valid_rule(rule: DetectionRule) -> bool {
@@ -142,37 +398,35 @@ fn is_valid_rule(rule: DetectionRule) -> bool {
     if rule.max_memory_mb < 0 {
         return false;
     }
-    if rule.name == "" {
+    if rule.min_age_secs < 0 {
         return false;
     }
-    true
-}
-fn rule_matches_process(rule: DetectionRule, proc: Process) -> bool {
-    if !rule.enabled {
+    if rule.sustained_samples < 0 || rule.sustained_secs < 0 {
         return false;
     }
-    if rule.max_cpu_percent > 0f64 {
-        if proc.cpu_usage <= rule.max_cpu_percent {
-            return false;
-        }
+    if rule.name.is_empty() {
+        return false;
     }
-    if rule.max_memory_mb > 0 {
-        if proc.memory_mb <= rule.max_memory_mb {
-            return false;
-        }
+    // A throttle is lifted once CPU falls below max_cpu_percent.
+    let throttle = rule.action == RuleAction::Throttle;
+    if throttle && (rule.max_cpu_percent <= 0f64 || !(1..=19).contains(&rule.throttle_nice)) {
+        return false;
     }
-    if rule.name_pattern != "" {
-        if !proc.name.contains(&rule.name_pattern) {
-            return false;
-        }
+    if rule.cap_cpu_percent < 0f64 || rule.cap_memory_mb < 0 {
+        return false;
     }
-    if rule.cmdline_pattern != "" {
-        if !proc.cmdline.contains(&rule.cmdline_pattern) {
-            return false;
-        }
+    let cap = rule.action == RuleAction::Cap;
+    if cap && rule.cap_cpu_percent <= 0f64 && rule.cap_memory_mb <= 0 {
+        return false;
+    }
+    if detector::compile_rule_patterns(rule).is_err() {
+        return false;
     }
     true
 }
+fn rule_matches_process(rule: DetectionRule, proc: Process) -> bool {
+    detector::match_reasons(&rule, &proc).is_some()
+}
 fn format_rule(rule: DetectionRule) -> String {
     {
         let priority_str = priority_to_string(rule.priority);
@@ -186,18 +440,80 @@ fn format_rule(rule: DetectionRule) -> String {
                 result = format!("{}{}", result, "]");
                 if rule.max_cpu_percent > 0f64 {
                     result = format!("{}{}", result, " CPU>");
-                    result = format!("{}{}", result, "threshold");
+                    result = format!("{}{}%", result, rule.max_cpu_percent);
                 }
                 if rule.max_memory_mb > 0 {
                     result = format!("{}{}", result, " MEM>");
-                    result = format!("{}{}", result, "threshold");
+                    result = format!("{}{}MB", result, rule.max_memory_mb);
                 }
-                if rule.name_pattern != "" {
+                if !rule.name_pattern.is_empty() {
                     result = format!("{}{}", result, " pattern:");
+                    result = format!("{}{}", result, match_mode_prefix(rule.name_match));
                     result = format!("{}{}", result, & rule.name_pattern);
                 }
+                if !rule.cmdline_pattern.is_empty() {
+                    result = format!("{}{}", result, " cmdline:");
+                    result = format!("{}{}", result, match_mode_prefix(rule.cmdline_match));
+                    result = format!("{}{}", result, &rule.cmdline_pattern);
+                }
+                let membership = [
+                    (" cgroup:", &rule.cgroup_pattern, rule.cgroup_match),
+                    (" unit:", &rule.unit_pattern, rule.unit_match),
+                    (" !unit:", &rule.exclude_unit_pattern, rule.unit_match),
+                    (" container:", &rule.container_pattern, rule.container_match),
+                ];
+                for (label, pattern, mode) in membership {
+                    if !pattern.is_empty() {
+                        result = format!("{}{}", result, label);
+                        result = format!("{}{}", result, match_mode_prefix(mode));
+                        result = format!("{}{}", result, pattern);
+                    }
+                }
+                let has_pattern = !rule.name_pattern.is_empty()
+                    || !rule.cmdline_pattern.is_empty()
+                    || membership.iter().any(|(_, pattern, _)| !pattern.is_empty());
+                if has_pattern && rule.case_sensitive {
+                    result = format!("{}{}", result, " case-sensitive");
+                }
+                if rule.min_age_secs > 0 {
+                    result = format!("{}{}", result, " AGE>");
+                    result = format!("{}{}s", result, rule.min_age_secs);
+                }
+                if rule.parent_is_init {
+                    result = format!("{}{}", result, " PPID=1");
+                }
+                if rule.sustained_samples > 1 {
+                    result = format!("{}{}", result, " FOR>=");
+                    result = format!("{}{}x", result, rule.sustained_samples);
+                }
+                if rule.sustained_secs > 0 {
+                    result = format!("{}{}", result, " FOR>=");
+                    result = format!("{}{}s", result, rule.sustained_secs);
+                }
+                if rule.action != RuleAction::Terminate {
+                    result = format!("{}{}", result, " action:");
+                    result = format!("{}{}", result, rule_action_to_string(rule.action));
+                    let tree = rule.action == RuleAction::TerminateTree;
+                    let cap = rule.action == RuleAction::Cap;
+                    if tree && rule.tree_signal == TreeSignal::ProcessGroup {
+                        result = format!("{}{}", result, "(process-group)");
+                    }
+                    if rule.action == RuleAction::Throttle {
+                        result = format!("{}(nice {})", result, rule.throttle_nice);
+                    }
+                    if cap && rule.cap_cpu_percent > 0f64 {
+                        result = format!("{}(cpu {}%)", result, rule.cap_cpu_percent);
+                    }
+                    if cap && rule.cap_memory_mb > 0 {
+                        result = format!("{}(mem {}MB)", result, rule.cap_memory_mb);
+                    }
+                }
+                if !rule.preset.is_empty() {
+                    result = format!("{}{}", result, " preset:");
+                    result = format!("{}{}", result, &rule.preset);
+                }
                 result = format!("{}{}", result, " (");
-                result = result + enabled_str;
+                result += enabled_str;
                 result = format!("{}{}", result, ")");
                 result
             }
@@ -214,22 +530,40 @@ fn new_config(
 ) -> Config {
     Config {
         check_interval_secs: check_interval,
-        rules: rules,
-        dry_run: dry_run,
-        log_file: log_file,
+        rules,
+        dry_run,
+        log_file,
         grace_period_secs: grace_period,
+        min_pid: DEFAULT_MIN_PID,
+        protected_names: vec![
+            "systemd".to_string(),
+            "sshd".to_string(),
+            "login".to_string(),
+        ],
+        protected_exes: Vec::new(),
+        metrics_address: String::new(),
+        frozen_file: "/var/lib/reaper/frozen.tsv".to_string(),
+        throttle_file: "/var/lib/reaper/throttled.tsv".to_string(),
+        cgroup_root: "/sys/fs/cgroup/reaper".to_string(),
+        cpu_normalization: CpuNormalization::PerCore,
     }
 }
 fn is_valid_config(config: Config) -> bool {
     if config.check_interval_secs <= 0 {
         return false;
     }
-    if config.log_file == "" {
+    if config.log_file.is_empty() || config.frozen_file.is_empty() {
+        return false;
+    }
+    if config.cgroup_root.is_empty() || config.throttle_file.is_empty() {
         return false;
     }
     if config.grace_period_secs < 0 {
         return false;
     }
+    if config.min_pid < 0 {
+        return false;
+    }
     true
 }
 fn format_config(config: Config) -> String {
@@ -239,7 +573,7 @@ fn format_config(config: Config) -> String {
         result = format!("{}{}", result, ", rules=");
         result = format!("{}{}", result, "count");
         result = format!("{}{}", result, ", dry_run=");
-        result = result + if config.dry_run { "true" } else { "false" };
+        result += if config.dry_run { "true" } else { "false" };
         result = format!("{}{}", result, ", log=");
         result = format!("{}{}", result, & config.log_file);
         result = format!("{}{}", result, ", grace=");
@@ -248,83 +582,353 @@ fn format_config(config: Config) -> String {
         (result).to_string()
     }
 }
-fn scan_processes() -> Vec<Process> {
-    {
-        let current_process = new_process(
-            1,
-            "ruchy".to_string(),
-            "ruchy run src/main.ruchy".to_string(),
-            0f64,
-            10,
-            ProcessStatus::Running,
-        );
-        {
-            let processes: Vec<Process> = [current_process].to_vec();
-            processes
+trait ProcessSource {
+    fn processes(&self) -> Vec<Process>;
+    fn process(&self, pid: i32) -> Option<Process>;
+    // The procfs root that identity checks and tree walks read before any
+    // process from this source is signalled.
+    fn proc_root(&self) -> &str;
+}
+struct ProcfsSource {
+    root: String,
+    sample_interval_ms: u64,
+    normalization: CpuNormalization,
+}
+fn new_procfs_source(root: String, normalization: CpuNormalization) -> ProcfsSource {
+    ProcfsSource {
+        root,
+        sample_interval_ms: CPU_SAMPLE_INTERVAL_MS,
+        normalization,
+    }
+}
+impl ProcessSource for ProcfsSource {
+    fn processes(&self) -> Vec<Process> {
+        let pids = list_pids(&self.root);
+        let sys_before = read_system_cpu(&self.root);
+        let ticks_before: Vec<Option<u64>> = pids
+            .iter()
+            .map(|pid| read_process_cpu_ticks(&self.root, *pid))
+            .collect();
+        std::thread::sleep(std::time::Duration::from_millis(self.sample_interval_ms));
+        let sys_after = read_system_cpu(&self.root);
+        let boot_time = read_boot_time(&self.root);
+        let now = unix_now();
+        let mut processes: Vec<Process> = Vec::new();
+        for (pid, before) in pids.into_iter().zip(ticks_before) {
+            // A PID listed by readdir may exit before its files are read.
+            let mut proc = match parse_proc_status_at(&self.root, pid, boot_time, now) {
+                Some(proc) => proc,
+                None => continue,
+            };
+            if let (Some(before), Some(after), Some(sys_before), Some(sys_after)) = (
+                before,
+                read_process_cpu_ticks(&self.root, pid),
+                sys_before,
+                sys_after,
+            ) {
+                proc.cpu_usage =
+                    cpu_percent(before, after, sys_before, sys_after, self.normalization);
+            }
+            processes.push(proc);
+        }
+        processes
+    }
+    fn process(&self, pid: i32) -> Option<Process> {
+        // A single read has no interval to measure, so cpu_usage stays 0.
+        parse_proc_status_at(&self.root, pid, read_boot_time(&self.root), unix_now())
+    }
+    fn proc_root(&self) -> &str {
+        &self.root
+    }
+}
+// Serves fixed process tables, one per processes() call, repeating the
+// last table once the list is exhausted.
+struct SnapshotSource {
+    snapshots: Vec<Vec<Process>>,
+    served: std::cell::Cell<usize>,
+}
+fn new_snapshot_source(snapshots: Vec<Vec<Process>>) -> SnapshotSource {
+    SnapshotSource {
+        snapshots,
+        served: std::cell::Cell::new(0),
+    }
+}
+impl SnapshotSource {
+    fn current(&self) -> Option<&Vec<Process>> {
+        let index = self
+            .served
+            .get()
+            .saturating_sub(1)
+            .min(self.snapshots.len().saturating_sub(1));
+        self.snapshots.get(index)
+    }
+}
+impl ProcessSource for SnapshotSource {
+    fn processes(&self) -> Vec<Process> {
+        self.served.set(self.served.get() + 1);
+        self.current().cloned().unwrap_or_default()
+    }
+    fn process(&self, pid: i32) -> Option<Process> {
+        self.current()?.iter().find(|p| p.pid == pid).cloned()
+    }
+    // Snapshots describe live processes, so they are checked against /proc.
+    fn proc_root(&self) -> &str {
+        PROC_ROOT
+    }
+}
+fn scan_processes(normalization: CpuNormalization) -> Vec<Process> {
+    new_procfs_source(PROC_ROOT.to_string(), normalization).processes()
+}
+fn list_pids(root: &str) -> Vec<i32> {
+    let entries = match std::fs::read_dir(root) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut pids: Vec<i32> = Vec::new();
+    for entry in entries.flatten() {
+        let file_name = entry.file_name();
+        let name = match file_name.to_str() {
+            Some(name) => name,
+            None => continue,
+        };
+        if name.is_empty() || !name.bytes().all(|b| b.is_ascii_digit()) {
+            continue;
+        }
+        if let Ok(pid) = name.parse::<i32>() {
+            if pid > 0 {
+                pids.push(pid);
+            }
         }
     }
-}
-fn parse_proc_status(pid: i32) -> Process {
-    if pid == 1 {
-        return new_process(
-            1,
-            "systemd".to_string(),
-            "/sbin/init".to_string(),
-            0.1f64,
-            50,
-            ProcessStatus::Running,
-        );
-    }
-    new_process(
+    pids.sort_unstable();
+    pids
+}
+fn parse_proc_status(pid: i32) -> Option<Process> {
+    parse_proc_status_at(PROC_ROOT, pid, read_boot_time(PROC_ROOT), unix_now())
+}
+fn parse_proc_status_at(root: &str, pid: i32, boot_time: i64, now: i64) -> Option<Process> {
+    let dir = format!("{}/{}", root, pid);
+    let status = std::fs::read_to_string(format!("{}/status", dir)).ok()?;
+    let stat = std::fs::read_to_string(format!("{}/stat", dir)).ok()?;
+    let raw_cmdline = std::fs::read(format!("{}/cmdline", dir)).ok()?;
+    let name = parse_status_field(&status, "Name")?;
+    let fields = parse_stat_fields(&stat)?;
+    let state = fields.first()?.chars().next()?;
+    let mut proc = new_process(
         pid,
-        "process".to_string(),
-        "/usr/bin/process".to_string(),
+        name,
+        parse_cmdline(&raw_cmdline),
         0f64,
-        10,
-        ProcessStatus::Running,
-    )
+        parse_vm_rss_mb(&status),
+        status_from_state_char(state),
+    );
+    // ppid is stat field 4 and starttime field 22; fields[0] is field 3.
+    proc.parent_pid = fields.get(1)?.parse().ok()?;
+    proc.start_ticks = fields.get(19)?.parse().ok()?;
+    proc.start_time = boot_time + proc.start_ticks / clock_ticks_per_sec();
+    proc.age_seconds = process_age_seconds(proc.start_time, now);
+    // Kernel threads have no exe and other users' links need privileges.
+    proc.exe = match std::fs::read_link(format!("{}/exe", dir)) {
+        Ok(path) => path.to_string_lossy().to_string(),
+        Err(_) => String::new(),
+    };
+    if let Ok(cgroup) = std::fs::read_to_string(format!("{}/cgroup", dir)) {
+        proc.cgroup = cgroup::parse_proc_cgroup(&cgroup);
+        proc.systemd_unit = cgroup::systemd_unit(&proc.cgroup);
+        proc.container_id = cgroup::container_id(&proc.cgroup);
+    }
+    Some(proc)
+}
+fn process_age_seconds(start_time: i64, now: i64) -> i64 {
+    (now - start_time).max(0)
+}
+fn unix_now() -> i64 {
+    match std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+        Ok(elapsed) => elapsed.as_secs() as i64,
+        Err(_) => 0,
+    }
+}
+fn unix_now_millis() -> i64 {
+    match std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+        Ok(elapsed) => elapsed.as_millis() as i64,
+        Err(_) => 0,
+    }
+}
+fn read_boot_time(root: &str) -> i64 {
+    match std::fs::read_to_string(format!("{}/stat", root)) {
+        Ok(stat) => parse_boot_time(&stat).unwrap_or(0),
+        Err(_) => 0,
+    }
+}
+fn parse_boot_time(proc_stat: &str) -> Option<i64> {
+    for line in proc_stat.lines() {
+        if let Some(value) = line.strip_prefix("btime ") {
+            return value.trim().parse().ok();
+        }
+    }
+    None
 }
-fn get_process_cpu_usage(pid: i32) -> f64 {
-    if pid == 1 {
-        return 0.1f64;
+fn parse_status_field(status: &str, key: &str) -> Option<String> {
+    for line in status.lines() {
+        if let Some((field, value)) = line.split_once(':') {
+            if field == key {
+                return Some(value.trim().to_string());
+            }
+        }
+    }
+    None
+}
+fn parse_vm_rss_mb(status: &str) -> i64 {
+    // Kernel threads have no VmRSS line; treat them as using no memory.
+    let value = match parse_status_field(status, "VmRSS") {
+        Some(value) => value,
+        None => return 0,
+    };
+    let kb: i64 = value
+        .split_whitespace()
+        .next()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(0);
+    kb / 1024
+}
+fn parse_stat_fields(stat: &str) -> Option<Vec<String>> {
+    // comm may itself contain spaces or ')', so split after the last ')'.
+    let rest = &stat[stat.rfind(')')? + 1..];
+    let fields: Vec<String> = rest.split_whitespace().map(|f| f.to_string()).collect();
+    if fields.is_empty() {
+        return None;
+    }
+    Some(fields)
+}
+fn status_from_state_char(state: char) -> ProcessStatus {
+    match state {
+        'R' => ProcessStatus::Running,
+        'T' | 't' => ProcessStatus::Stopped,
+        'Z' | 'X' => ProcessStatus::Zombie,
+        _ => ProcessStatus::Sleeping,
+    }
+}
+fn parse_cmdline(raw: &[u8]) -> String {
+    String::from_utf8_lossy(raw)
+        .split('\0')
+        .filter(|arg| !arg.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+// USER_HZ: the unit of the clock-tick fields exported through /proc.
+fn clock_ticks_per_sec() -> i64 {
+    // SAFETY: sysconf only reads a system constant.
+    let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    // 100 is what every Linux architecture reports.
+    if ticks > 0 {
+        ticks as i64
+    } else {
+        100
     }
-    2.5f64
 }
-fn apply_rules(procs: Vec<Process>, rules: Vec<DetectionRule>) -> Vec<Process> {
-    let mut result: Vec<Process> = vec![].to_vec();
-    let mut i = 0;
-    while i < procs.len() {
-        {
-            {
-                let proc = procs[i as usize].clone();
-                {
-                    let mut matches = false;
-                    let mut j = 0;
-                    while j < rules.len() {
-                        {
-                            {
-                                let rule = rules[j as usize].clone();
-                                {
-                                    if rule.enabled
-                                        && rule_matches_process(rule.clone(), proc.clone())
-                                    {
-                                        matches = true;
-                                        break;
-                                    }
-                                    j = j + 1;
-                                }
-                            }
-                        }
-                    }
-                    if matches {
-                        result.push(proc)
-                    }
-                    i = i + 1;
-                }
+fn get_process_cpu_usage(pid: i32) -> f64 {
+    get_process_cpu_usage_with(pid, CPU_SAMPLE_INTERVAL_MS, CpuNormalization::PerCore)
+}
+fn get_process_cpu_usage_with(pid: i32, interval_ms: u64, normalization: CpuNormalization) -> f64 {
+    let sys_before = read_system_cpu(PROC_ROOT);
+    let ticks_before = read_process_cpu_ticks(PROC_ROOT, pid);
+    std::thread::sleep(std::time::Duration::from_millis(interval_ms));
+    let sys_after = read_system_cpu(PROC_ROOT);
+    let ticks_after = read_process_cpu_ticks(PROC_ROOT, pid);
+    match (ticks_before, ticks_after, sys_before, sys_after) {
+        (Some(before), Some(after), Some(sys_before), Some(sys_after)) => {
+            cpu_percent(before, after, sys_before, sys_after, normalization)
+        }
+        _ => 0f64,
+    }
+}
+fn cpu_percent(
+    ticks_before: u64,
+    ticks_after: u64,
+    sys_before: SystemCpu,
+    sys_after: SystemCpu,
+    normalization: CpuNormalization,
+) -> f64 {
+    let process_delta = ticks_after.saturating_sub(ticks_before) as f64;
+    let total_delta = sys_after.total_ticks.saturating_sub(sys_before.total_ticks) as f64;
+    if total_delta <= 0f64 {
+        return 0f64;
+    }
+    let capacity = match normalization {
+        CpuNormalization::WholeMachine => total_delta,
+        CpuNormalization::PerCore => total_delta / sys_after.num_cpus.max(1) as f64,
+    };
+    (process_delta / capacity * 100f64).clamp(0f64, 100f64)
+}
+fn read_process_cpu_ticks(root: &str, pid: i32) -> Option<u64> {
+    let stat = std::fs::read_to_string(format!("{}/{}/stat", root, pid)).ok()?;
+    parse_stat_cpu_ticks(&stat)
+}
+fn parse_stat_cpu_ticks(stat: &str) -> Option<u64> {
+    // utime and stime are fields 14 and 15 of stat; fields[0] is field 3.
+    let fields = parse_stat_fields(stat)?;
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+    Some(utime + stime)
+}
+fn read_system_cpu(root: &str) -> Option<SystemCpu> {
+    let stat = std::fs::read_to_string(format!("{}/stat", root)).ok()?;
+    parse_system_cpu(&stat)
+}
+fn parse_system_cpu(proc_stat: &str) -> Option<SystemCpu> {
+    let mut total_ticks: Option<u64> = None;
+    let mut num_cpus: u64 = 0;
+    for line in proc_stat.lines() {
+        let mut fields = line.split_whitespace();
+        match fields.next() {
+            Some("cpu") => {
+                // guest and guest_nice are already counted in user and nice.
+                let ticks: u64 = fields.take(8).filter_map(|f| f.parse::<u64>().ok()).sum();
+                total_ticks = Some(ticks);
             }
+            Some(label) if label.starts_with("cpu") => num_cpus += 1,
+            _ => {}
         }
     }
-    result
+    Some(SystemCpu {
+        total_ticks: total_ticks?,
+        num_cpus: num_cpus.max(1),
+    })
+}
+// Keeps the processes some enabled rule matches, each with its deciding
+// (highest-priority) rule and the reasons it matched.
+fn apply_rules(procs: Vec<Process>, rules: Vec<DetectionRule>) -> Vec<detector::RuleMatch> {
+    procs
+        .into_iter()
+        .filter_map(|proc| detector::evaluate_rules(proc, &rules))
+        .collect()
+}
+// The processes `matched` leaves out whose single sample matches a sustained
+// rule; `scan` lists them as pending rather than dropping them.
+fn pending_rules(
+    procs: Vec<Process>,
+    rules: &[DetectionRule],
+    matched: &[detector::RuleMatch],
+) -> Vec<(detector::RuleMatch, String)> {
+    procs
+        .into_iter()
+        .filter(|proc| !matched.iter().any(|rule_match| rule_match.process.pid == proc.pid))
+        .filter_map(|proc| detector::evaluate_pending(proc, rules))
+        .collect()
+}
+// Like apply_rules, but sustained rules count samples across calls in
+// `history`.
+fn apply_rules_with_history(
+    procs: Vec<Process>,
+    rules: &[DetectionRule],
+    history: &mut detector::MatchHistory,
+    now: i64,
+) -> Vec<detector::RuleMatch> {
+    detector::forget_missing(history, &procs);
+    procs
+        .into_iter()
+        .filter_map(|proc| detector::evaluate_rules_with_history(proc, rules, history, now))
+        .collect()
 }
 fn detect_cpu_hog(proc: Process, rule: DetectionRule) -> bool {
     if rule.max_cpu_percent <= 0f64 {
@@ -339,47 +943,623 @@ fn detect_memory_hog(proc: Process, rule: DetectionRule) -> bool {
     proc.memory_mb > rule.max_memory_mb
 }
 fn match_name_pattern(proc: Process, pattern: String) -> bool {
-    if pattern.len() == 0 {
+    if pattern.is_empty() {
         return true;
     }
-    {
-        let proc_name_lower = proc.name.to_lowercase();
-        {
-            let pattern_lower = pattern.to_lowercase();
-            proc_name_lower.contains(&pattern_lower)
-        }
+    match detector::compile_pattern(&pattern, MatchMode::Substring, false) {
+        Ok(compiled) => detector::pattern_matches(&compiled, &proc.name),
+        Err(_) => false,
     }
 }
 fn terminate_process(pid: i32, grace_period: i64) -> ActionResult {
     if pid <= 0 {
         return ActionResult::Failed;
     }
-    ActionResult::Success
+    if process_is_zombie(pid) {
+        return ActionResult::AlreadyDead;
+    }
+    escalate_termination(
+        |signal| send_signal(pid, signal),
+        |timeout| wait_for_exit(pid, timeout),
+        || true,
+        grace_period,
+    )
 }
-fn safe_kill_with_grace(proc: Process, grace_period: i64) -> bool {
-    {
-        let pid = proc.pid;
-        {
-            let mut result = terminate_process(pid, grace_period);
-            match result {
-                ActionResult::Success => true,
-                _ => false,
-            }
+// Like terminate_process, but only signals the PID while it is still the
+// process described by `proc` (same starttime and exe).
+fn terminate_verified(proc: Process, grace_period: i64) -> ActionResult {
+    terminate_verified_at(PROC_ROOT, proc, grace_period)
+}
+// terminate_verified with identity read from the procfs at `root`.
+fn terminate_verified_at(root: &str, proc: Process, grace_period: i64) -> ActionResult {
+    if proc.pid <= 0 {
+        return ActionResult::Failed;
+    }
+    // Holding a pidfd pins the process: once identity is confirmed, later
+    // signals cannot reach a recycled PID.
+    let pidfd = match open_pidfd(proc.pid) {
+        Ok(fd) => Some(fd),
+        Err(libc::ESRCH) => return ActionResult::NotFound,
+        Err(_) => None,
+    };
+    if let Some(refusal) = verify_identity_at(root, &proc) {
+        if let Some(fd) = pidfd {
+            close_fd(fd);
+        }
+        return refusal;
+    }
+    match pidfd {
+        Some(fd) => {
+            let result = escalate_termination(
+                |signal| pidfd_send_signal(fd, signal),
+                |timeout| wait_for_pidfd(fd, timeout),
+                || true,
+                grace_period,
+            );
+            close_fd(fd);
+            result
+        }
+        None => terminate_rechecked(root, proc, grace_period),
+    }
+}
+// Fallback for kernels without pidfd: re-check starttime before SIGKILL.
+fn terminate_rechecked(root: &str, proc: Process, grace_period: i64) -> ActionResult {
+    let pid = proc.pid;
+    escalate_termination(
+        |signal| send_signal(pid, signal),
+        |timeout| wait_for_exit(pid, timeout),
+        || verify_identity_at(root, &proc).is_none(),
+        grace_period,
+    )
+}
+fn escalate_termination<S, W, C>(
+    signal: S,
+    wait: W,
+    still_target: C,
+    grace_period: i64,
+) -> ActionResult
+where
+    S: Fn(i32) -> Result<(), i32>,
+    W: Fn(std::time::Duration) -> bool,
+    C: Fn() -> bool,
+{
+    if let Err(errno) = signal(libc::SIGTERM) {
+        return action_result_from_errno(errno);
+    }
+    let grace = std::time::Duration::from_secs(grace_period.max(0) as u64);
+    if wait(grace) {
+        return ActionResult::Success;
+    }
+    // The PID now names a different process, so the target has exited.
+    if !still_target() {
+        return ActionResult::Success;
+    }
+    match signal(libc::SIGKILL) {
+        Ok(()) => {}
+        // Exited between the last poll and SIGKILL.
+        Err(libc::ESRCH) => return ActionResult::Success,
+        Err(errno) => return action_result_from_errno(errno),
+    }
+    if wait(std::time::Duration::from_millis(KILL_WAIT_MS)) {
+        ActionResult::Success
+    } else {
+        ActionResult::TimedOut
+    }
+}
+fn verify_identity(proc: &Process) -> Option<ActionResult> {
+    verify_identity_at(PROC_ROOT, proc)
+}
+fn verify_identity_at(root: &str, proc: &Process) -> Option<ActionResult> {
+    let live = match parse_proc_status_at(root, proc.pid, read_boot_time(root), unix_now()) {
+        Some(live) => live,
+        None => return Some(ActionResult::NotFound),
+    };
+    if live.start_ticks != proc.start_ticks || !same_exe(&live.exe, &proc.exe) {
+        return Some(ActionResult::IdentityMismatch);
+    }
+    if live.status == ProcessStatus::Zombie {
+        return Some(ActionResult::AlreadyDead);
+    }
+    None
+}
+fn same_exe(live: &str, expected: &str) -> bool {
+    // Replacing a running binary (e.g. a rebuild) marks its link deleted.
+    live.trim_end_matches(" (deleted)") == expected.trim_end_matches(" (deleted)")
+}
+fn open_pidfd(pid: i32) -> Result<i32, i32> {
+    // SAFETY: pidfd_open takes a PID and flags and returns a new fd or -1.
+    let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error().raw_os_error().unwrap_or(0));
+    }
+    Ok(fd as i32)
+}
+fn pidfd_send_signal(pidfd: i32, signal: i32) -> Result<(), i32> {
+    // SAFETY: a null siginfo asks the kernel to fill it in as kill(2) does.
+    let rc = unsafe {
+        libc::syscall(
+            libc::SYS_pidfd_send_signal,
+            pidfd,
+            signal,
+            std::ptr::null::<libc::siginfo_t>(),
+            0,
+        )
+    };
+    if rc == 0 {
+        return Ok(());
+    }
+    Err(std::io::Error::last_os_error().raw_os_error().unwrap_or(0))
+}
+fn wait_for_pidfd(pidfd: i32, timeout: std::time::Duration) -> bool {
+    // A pidfd becomes readable once its process has exited.
+    let deadline = std::time::Instant::now() + timeout;
+    loop {
+        let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+        let mut poll_fd = libc::pollfd {
+            fd: pidfd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: poll_fd is a valid pollfd for the duration of the call.
+        let rc = unsafe { libc::poll(&mut poll_fd, 1, remaining.as_millis() as i32) };
+        if rc > 0 {
+            return true;
+        }
+        let interrupted =
+            rc < 0 && std::io::Error::last_os_error().raw_os_error() == Some(libc::EINTR);
+        if !interrupted || remaining.is_zero() {
+            return false;
+        }
+    }
+}
+fn close_fd(fd: i32) {
+    // SAFETY: fd was returned by pidfd_open and is closed exactly once.
+    unsafe {
+        libc::close(fd);
+    }
+}
+fn send_signal(pid: i32, signal: i32) -> Result<(), i32> {
+    // SAFETY: kill(2) takes plain integers and has no memory-safety
+    // preconditions.
+    if unsafe { libc::kill(pid, signal) } == 0 {
+        return Ok(());
+    }
+    Err(std::io::Error::last_os_error().raw_os_error().unwrap_or(0))
+}
+fn action_result_from_errno(errno: i32) -> ActionResult {
+    match errno {
+        libc::EPERM => ActionResult::PermissionDenied,
+        libc::ESRCH => ActionResult::NotFound,
+        _ => ActionResult::Failed,
+    }
+}
+fn process_is_zombie(pid: i32) -> bool {
+    // An exited child that has not been reaped still accepts signals.
+    let stat = match std::fs::read_to_string(format!("{}/{}/stat", PROC_ROOT, pid)) {
+        Ok(stat) => stat,
+        Err(_) => return false,
+    };
+    match parse_stat_fields(&stat) {
+        Some(fields) => fields[0] == "Z" || fields[0] == "X",
+        None => false,
+    }
+}
+fn process_exited(pid: i32) -> bool {
+    match send_signal(pid, 0) {
+        Err(libc::ESRCH) => true,
+        _ => process_is_zombie(pid),
+    }
+}
+fn wait_for_exit(pid: i32, timeout: std::time::Duration) -> bool {
+    let deadline = std::time::Instant::now() + timeout;
+    loop {
+        if process_exited(pid) {
+            return true;
+        }
+        let now = std::time::Instant::now();
+        if now >= deadline {
+            return false;
         }
+        let poll = std::time::Duration::from_millis(TERMINATE_POLL_MS);
+        std::thread::sleep(poll.min(deadline - now));
     }
 }
+// terminate_guarded with the gate built from `config`, so its min_pid and
+// protected names and exes hold here too.
+fn safe_kill_with_grace(proc: Process, grace_period: i64, config: &Config) -> bool {
+    let gate = safety::new_safety_gate(config);
+    let result = terminate_guarded(proc, grace_period, &gate);
+    matches!(result, ActionResult::Success)
+}
+// terminate_verified behind the safety gate: protected processes are
+// refused before any signal is sent.
+fn terminate_guarded(proc: Process, grace_period: i64, gate: &safety::SafetyGate) -> ActionResult {
+    if safety::protection_reason(gate, &proc).is_some() {
+        return ActionResult::Protected;
+    }
+    terminate_verified(proc, grace_period)
+}
 fn default_config() -> Config {
-    let empty_rules: Vec<DetectionRule> = vec![].to_vec();
+    let empty_rules: Vec<DetectionRule> = [].to_vec();
     new_config(60, empty_rules, false, "/var/log/reaper.log".to_string(), 5)
 }
-fn load_config(path: String) -> Config {
-    default_config()
+fn load_config(path: String) -> Result<Config, config::ConfigError> {
+    // No config file at all means running with the built-in defaults.
+    if path.is_empty() {
+        return Ok(default_config());
+    }
+    match std::fs::read_to_string(&path) {
+        Ok(text) => config::parse_config(&text, &path),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(default_config()),
+        Err(err) => Err(config::ConfigError {
+            file: path,
+            line: 0,
+            key: String::new(),
+            message: err.to_string(),
+        }),
+    }
 }
-fn parse_args() -> Config {
-    default_config()
+fn parse_args(args: Vec<String>) -> Result<cli::Invocation, String> {
+    cli::parse_cli(&args).and_then(cli::resolve)
 }
 fn daemon_loop(config: Config) {
-    println!("daemon_loop called (stub - returns immediately)")
+    install_shutdown_handlers();
+    let source = new_procfs_source(PROC_ROOT.to_string(), config.cpu_normalization);
+    run_daemon(config, &source, &SHUTDOWN_REQUESTED, None);
+}
+static SHUTDOWN_REQUESTED: std::sync::atomic::AtomicBool =
+    std::sync::atomic::AtomicBool::new(false);
+extern "C" fn request_shutdown(_signal: libc::c_int) {
+    SHUTDOWN_REQUESTED.store(true, std::sync::atomic::Ordering::SeqCst);
+}
+fn install_shutdown_handlers() {
+    let handler: extern "C" fn(libc::c_int) = request_shutdown;
+    for signal in [libc::SIGTERM, libc::SIGINT] {
+        // SAFETY: the handler only stores to an atomic, which is
+        // async-signal-safe.
+        unsafe {
+            libc::signal(signal, handler as libc::sighandler_t);
+        }
+    }
+}
+// Runs scan -> apply_rules -> terminate every check_interval_secs until
+// `shutdown` is set (or `max_cycles` have run) and returns the cycle count.
+fn run_daemon(
+    config: Config,
+    source: &dyn ProcessSource,
+    shutdown: &std::sync::atomic::AtomicBool,
+    max_cycles: Option<usize>,
+) -> usize {
+    let interval = std::time::Duration::from_secs(config.check_interval_secs.max(1) as u64);
+    let start = std::time::Instant::now();
+    let mut cycles = 0;
+    let mut state = new_cycle_state();
+    // Throttles an earlier daemon left behind are reverted like our own.
+    if !config.dry_run {
+        state.throttles = throttle::load_throttles(&config.throttle_file);
+    }
+    let metrics = std::sync::Arc::new(metrics::new_metrics());
+    if !config.metrics_address.is_empty() {
+        // Metrics are an aid; a port clash must not stop the reaping.
+        match metrics::serve_metrics(&config.metrics_address, metrics.clone()) {
+            Ok(address) => println!("serving metrics on http://{}/metrics", address),
+            Err(err) => eprintln!(
+                "cannot serve metrics on {}: {}",
+                config.metrics_address, err
+            ),
+        }
+    }
+    while !shutdown.load(std::sync::atomic::Ordering::SeqCst) {
+        run_cycle(&config, source, shutdown, &mut state, &metrics);
+        cycles += 1;
+        if max_cycles.is_some_and(|max| cycles >= max) {
+            break;
+        }
+        let deadline = next_deadline(start, interval, std::time::Instant::now());
+        if !sleep_until(deadline, shutdown) {
+            break;
+        }
+    }
+    // Nothing would revert these once the daemon is gone.
+    if !state.throttles.entries.is_empty() {
+        let audit = logger::new_audit_logger(config.log_file.clone());
+        for (proc, result) in throttle::restore_all(&mut state.throttles) {
+            println!("unthrottle {}: {:?}", format_process(proc.clone()), result);
+            let unthrottle = logger::AuditEvent::Unthrottle;
+            logger::log_event(&audit, unthrottle, &proc, None, false, Some(result));
+        }
+        save_throttles(&config, &state.throttles);
+    }
+    cycles
+}
+fn save_throttles(config: &Config, throttles: &throttle::ThrottleTable) {
+    if let Err(err) = throttle::save_throttles(&config.throttle_file, throttles) {
+        eprintln!(
+            "cannot record throttles in {}: {}",
+            config.throttle_file, err
+        );
+    }
+}
+// What the daemon carries from one cycle to the next.
+struct CycleState {
+    history: detector::MatchHistory,
+    throttles: throttle::ThrottleTable,
+}
+fn new_cycle_state() -> CycleState {
+    CycleState {
+        history: detector::new_match_history(),
+        throttles: throttle::new_throttle_table(),
+    }
+}
+fn run_cycle(
+    config: &Config,
+    source: &dyn ProcessSource,
+    shutdown: &std::sync::atomic::AtomicBool,
+    state: &mut CycleState,
+    metrics: &metrics::Metrics,
+) -> Vec<(Process, Option<ActionResult>)> {
+    let scan_started = std::time::Instant::now();
+    let procs = source.processes();
+    let proc_root = source.proc_root();
+    let scanned = procs.len();
+    let snapshot = procs.clone();
+    let throttled_before = state.throttles.clone();
+    let now = unix_now();
+    let matched = apply_rules_with_history(procs, &config.rules, &mut state.history, now);
+    let scan_secs = scan_started.elapsed().as_secs_f64();
+    let finished_at = unix_now_millis() as f64 / 1000f64;
+    metrics::record_scan(metrics, scanned, scan_secs, finished_at, &matched);
+    let audit = logger::new_audit_logger(config.log_file.clone());
+    let gate = safety::new_safety_gate(config);
+    let frozen = frozen::load_frozen(&config.frozen_file);
+    let mut outcomes: Vec<(Process, Option<ActionResult>)> = Vec::new();
+    let mut report: Vec<(detector::RuleMatch, String)> = Vec::new();
+    for (proc, result) in throttle::revert_recovered(&mut state.throttles, &snapshot) {
+        println!("unthrottle {}: {:?}", format_process(proc.clone()), result);
+        let unthrottle = logger::AuditEvent::Unthrottle;
+        logger::log_event(&audit, unthrottle, &proc, None, false, Some(result));
+        metrics::record_termination(metrics, result);
+        outcomes.push((proc, Some(result)));
+    }
+    if !config.dry_run {
+        let empty = cgroup::empty_cgroups(&config.cgroup_root);
+        for path in cgroup::remove_cgroups(&empty) {
+            println!("removed empty cgroup {}", path);
+        }
+    }
+    for rule_match in matched {
+        // A termination already under way finishes; no new one starts.
+        if shutdown.load(std::sync::atomic::Ordering::SeqCst) {
+            break;
+        }
+        let proc = rule_match.process.clone();
+        // A quarantined, throttled or capped process is left as it is until
+        // it is resumed, recovers or exits.
+        let frozen_already =
+            rule_match.action == RuleAction::Stop && frozen::is_frozen(&frozen, &proc);
+        let throttled_already = rule_match.action == RuleAction::Throttle
+            && throttle::is_throttled(&state.throttles, &proc);
+        let capped_already =
+            rule_match.action == RuleAction::Cap && cgroup::is_capped(&config.cgroup_root, &proc);
+        if frozen_already || throttled_already || capped_already {
+            continue;
+        }
+        if config.dry_run {
+            let (action, result) = dry_run_verdict(&rule_match, &gate, &audit, proc_root);
+            report.push((rule_match, action));
+            outcomes.push((proc, result));
+            continue;
+        }
+        let explained = Some(&rule_match);
+        let detection = logger::AuditEvent::Detection;
+        logger::log_event(&audit, detection, &proc, explained, false, None);
+        if rule_match.action == RuleAction::LogOnly {
+            println!("detected {}", detector::format_rule_match(&rule_match));
+            outcomes.push((proc, None));
+            continue;
+        }
+        if let Some(reason) = safety::protection_reason(&gate, &proc) {
+            println!(
+                "protected {} ({}): no signal sent",
+                detector::format_rule_match(&rule_match),
+                reason
+            );
+            let refused = Some(ActionResult::Protected);
+            let finished = result_event(rule_match.action);
+            logger::log_event(&audit, finished, &proc, explained, false, refused);
+            metrics::record_termination(metrics, ActionResult::Protected);
+            outcomes.push((proc, refused));
+            continue;
+        }
+        let throttles = &mut state.throttles;
+        let acted = act_on_match(&rule_match, config, &gate, &audit, throttles, proc_root);
+        for (member, result) in acted {
+            metrics::record_termination(metrics, result);
+            outcomes.push((member, Some(result)));
+        }
+    }
+    if state.throttles != throttled_before {
+        save_throttles(config, &state.throttles);
+    }
+    if !report.is_empty() {
+        println!("dry run: no signals sent");
+        print!("{}", detector::format_match_table(&report));
+    }
+    outcomes
+}
+// Carries out a match that passed the safety gate and logs each result. A
+// tree yields one result per member, root last.
+fn act_on_match(
+    rule_match: &detector::RuleMatch,
+    config: &Config,
+    gate: &safety::SafetyGate,
+    audit: &logger::AuditLogger,
+    throttles: &mut throttle::ThrottleTable,
+    proc_root: &str,
+) -> Vec<(Process, ActionResult)> {
+    let proc = rule_match.process.clone();
+    let rule = &rule_match.rule;
+    if rule_match.action == RuleAction::Cap {
+        let result = cgroup::cap_process(
+            &config.cgroup_root,
+            &proc,
+            rule.cap_cpu_percent,
+            rule.cap_memory_mb,
+        );
+        println!(
+            "cap {}: {:?}",
+            detector::format_rule_match(rule_match),
+            result
+        );
+        let event = logger::AuditEvent::Cap;
+        logger::log_event(audit, event, &proc, Some(rule_match), false, Some(result));
+        return vec![(proc, result)];
+    }
+    if rule_match.action == RuleAction::Throttle {
+        let result = throttle::throttle_process(
+            throttles,
+            &proc,
+            &rule.name,
+            rule.max_cpu_percent,
+            rule.throttle_nice as i32,
+        );
+        println!(
+            "throttle {}: {:?}",
+            detector::format_rule_match(rule_match),
+            result
+        );
+        let event = logger::AuditEvent::Throttle;
+        logger::log_event(audit, event, &proc, Some(rule_match), false, Some(result));
+        return vec![(proc, result)];
+    }
+    if rule_match.action == RuleAction::Stop {
+        let result = frozen::freeze_verified(&proc);
+        if result == ActionResult::Frozen {
+            let entry = frozen::new_frozen_entry(&proc, &rule_match.rule_name, unix_now());
+            if let Err(err) = frozen::record_frozen(&config.frozen_file, entry) {
+                eprintln!(
+                    "cannot record frozen PID {} in {}: {}",
+                    proc.pid, config.frozen_file, err
+                );
+            }
+        }
+        println!(
+            "stop {}: {:?}",
+            detector::format_rule_match(rule_match),
+            result
+        );
+        let stop = logger::AuditEvent::Stop;
+        logger::log_event(audit, stop, &proc, Some(rule_match), false, Some(result));
+        return vec![(proc, result)];
+    }
+    let attempt = logger::AuditEvent::TerminateAttempt;
+    logger::log_event(audit, attempt, &proc, Some(rule_match), false, None);
+    let grace = config.grace_period_secs;
+    let results = if rule_match.action == RuleAction::TerminateTree {
+        tree::terminate_tree(proc_root, proc, grace, gate, rule_match.tree_signal)
+    } else {
+        vec![(proc.clone(), terminate_verified_at(proc_root, proc, grace))]
+    };
+    for (member, result) in &results {
+        let member_match = tree::member_match(rule_match, member);
+        println!(
+            "terminate {}: {:?}",
+            detector::format_rule_match(&member_match),
+            result
+        );
+        let finished = logger::AuditEvent::TerminateResult;
+        let explained = Some(&member_match);
+        logger::log_event(audit, finished, member, explained, false, Some(*result));
+    }
+    results
+}
+// The audit event that carries an action's outcome.
+fn result_event(action: RuleAction) -> logger::AuditEvent {
+    match action {
+        RuleAction::Stop => logger::AuditEvent::Stop,
+        RuleAction::Throttle => logger::AuditEvent::Throttle,
+        RuleAction::Cap => logger::AuditEvent::Cap,
+        _ => logger::AuditEvent::TerminateResult,
+    }
+}
+// Everything a real cycle does short of signalling: logs the detection and
+// then either the safety refusal or what the rule would do. Returns the
+// report's action column and the outcome (None unless refused).
+fn dry_run_verdict(
+    rule_match: &detector::RuleMatch,
+    gate: &safety::SafetyGate,
+    audit: &logger::AuditLogger,
+    proc_root: &str,
+) -> (String, Option<ActionResult>) {
+    let proc = &rule_match.process;
+    let explained = Some(rule_match);
+    logger::log_event(
+        audit,
+        logger::AuditEvent::Detection,
+        proc,
+        explained,
+        true,
+        None,
+    );
+    if rule_match.action == RuleAction::LogOnly {
+        return ("log only".to_string(), None);
+    }
+    if let Some(reason) = safety::protection_reason(gate, proc) {
+        let refused = Some(ActionResult::Protected);
+        let finished = result_event(rule_match.action);
+        logger::log_event(audit, finished, proc, explained, true, refused);
+        return (format!("protected: {}", reason), refused);
+    }
+    if rule_match.action == RuleAction::Stop {
+        let would = logger::AuditEvent::WouldStop;
+        logger::log_event(audit, would, proc, explained, true, None);
+        return ("would stop".to_string(), None);
+    }
+    if rule_match.action == RuleAction::Throttle {
+        let would = logger::AuditEvent::WouldThrottle;
+        logger::log_event(audit, would, proc, explained, true, None);
+        return ("would throttle".to_string(), None);
+    }
+    if rule_match.action == RuleAction::Cap {
+        let would = logger::AuditEvent::WouldCap;
+        logger::log_event(audit, would, proc, explained, true, None);
+        return ("would cap".to_string(), None);
+    }
+    let would = logger::AuditEvent::WouldTerminate;
+    if rule_match.action == RuleAction::TerminateTree {
+        let members = tree::tree_members(proc_root, proc, rule_match.tree_signal);
+        for member in &members {
+            let member_match = tree::member_match(rule_match, member);
+            logger::log_event(audit, would, member, Some(&member_match), true, None);
+        }
+        return (format!("would terminate tree of {}", members.len()), None);
+    }
+    logger::log_event(audit, would, proc, explained, true, None);
+    ("would terminate".to_string(), None)
+}
+// Cycles are pinned to start + k * interval, so a slow scan delays only
+// its own cycle; ticks missed entirely are skipped rather than bunched up.
+fn next_deadline(
+    start: std::time::Instant,
+    interval: std::time::Duration,
+    now: std::time::Instant,
+) -> std::time::Instant {
+    let elapsed = now.saturating_duration_since(start).as_nanos();
+    let ticks = elapsed / interval.as_nanos().max(1) + 1;
+    start + interval * ticks as u32
+}
+fn sleep_until(deadline: std::time::Instant, shutdown: &std::sync::atomic::AtomicBool) -> bool {
+    loop {
+        if shutdown.load(std::sync::atomic::Ordering::SeqCst) {
+            return false;
+        }
+        let now = std::time::Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        let slice = std::time::Duration::from_millis(SHUTDOWN_POLL_MS);
+        std::thread::sleep(slice.min(deadline - now));
+    }
 }
 #[test]
 fn test_process_creation() {
@@ -487,8 +1667,8 @@ fn test_process_format() {
             ProcessStatus::Running,
         );
         {
-            let mut formatted = format_process(proc);
-            assert!(formatted != "", "{}", "Formatted process should not be empty")
+            let formatted = format_process(proc);
+            assert!(!formatted.is_empty(), "{}", "Formatted process should not be empty")
         }
     }
 }
@@ -539,7 +1719,7 @@ fn test_priority_high_to_string() {
     {
         let high = Priority::High;
         {
-            let mut result = priority_to_string(high);
+            let result = priority_to_string(high);
             assert!(result == "high", "{}", "Priority::High should convert to 'high'")
         }
     }
@@ -549,7 +1729,7 @@ fn test_priority_medium_to_string() {
     {
         let medium = Priority::Medium;
         {
-            let mut result = priority_to_string(medium);
+            let result = priority_to_string(medium);
             assert!(
                 result == "medium", "{}", "Priority::Medium should convert to 'medium'"
             )
@@ -561,7 +1741,7 @@ fn test_priority_low_to_string() {
     {
         let low = Priority::Low;
         {
-            let mut result = priority_to_string(low);
+            let result = priority_to_string(low);
             assert!(result == "low", "{}", "Priority::Low should convert to 'low'")
         }
     }
@@ -678,7 +1858,7 @@ fn test_detection_rule_creation() {
             assert!(rule.max_cpu_percent == 80f64, "{}", "CPU threshold should be set");
             assert!(rule.max_memory_mb == 1024, "{}", "Memory threshold should be set");
             assert!(rule.name_pattern == "python", "{}", "Name pattern should be set");
-            assert!(rule.enabled == true, "{}", "Enabled flag should be set")
+            assert!(rule.enabled, "{}", "Enabled flag should be set")
         }
     }
 }
@@ -845,6 +2025,59 @@ fn test_disabled_rule_no_match() {
     }
 }
 #[test]
+fn test_rule_matches_min_age() {
+    let mut rule = new_detection_rule(
+        "Hung runner".to_string(),
+        Priority::Medium,
+        0f64,
+        0,
+        "".to_string(),
+        "nextest".to_string(),
+        true,
+    );
+    rule.min_age_secs = 86400;
+    let mut proc = new_process(
+        4242,
+        "cargo-nextest".to_string(),
+        "cargo-nextest run".to_string(),
+        1f64,
+        200,
+        ProcessStatus::Sleeping,
+    );
+    proc.age_seconds = 5 * 86400;
+    assert!(rule_matches_process(rule.clone(), proc.clone()), "Five-day runner should match");
+    proc.age_seconds = 600;
+    assert!(!rule_matches_process(rule.clone(), proc), "Fresh runner should not match");
+    rule.min_age_secs = -1;
+    assert!(!is_valid_rule(rule), "Negative age threshold is invalid");
+}
+#[test]
+fn test_rule_matches_parent_is_init() {
+    let mut rule = new_detection_rule(
+        "Orphan".to_string(),
+        Priority::Low,
+        0f64,
+        0,
+        "monitor".to_string(),
+        "".to_string(),
+        true,
+    );
+    rule.parent_is_init = true;
+    let mut proc = new_process(
+        900,
+        "monitor".to_string(),
+        "./monitor".to_string(),
+        0f64,
+        10,
+        ProcessStatus::Sleeping,
+    );
+    proc.parent_pid = 1;
+    assert!(rule_matches_process(rule.clone(), proc.clone()), "Reparented to init");
+    proc.parent_pid = 850;
+    assert!(!rule_matches_process(rule.clone(), proc), "Still has its parent");
+    assert!(format_rule(rule).contains("PPID=1"));
+}
+#[test]
 fn test_format_detection_rule() {
     {
         let rule = new_detection_rule(
@@ -857,12 +2090,58 @@ fn test_format_detection_rule() {
             true,
         );
         {
-            let mut formatted = format_rule(rule);
-            assert!(formatted != "", "{}", "Formatted rule should not be empty")
+            let formatted = format_rule(rule);
+            assert!(!formatted.is_empty(), "{}", "Formatted rule should not be empty")
         }
     }
 }
 #[test]
+fn test_format_rule_shows_thresholds() {
+    let rule = new_detection_rule(
+        "Hog".to_string(),
+        Priority::Medium,
+        90f64,
+        2048,
+        "".to_string(),
+        "cargo-nextest".to_string(),
+        true,
+    );
+    let formatted = format_rule(rule);
+    assert!(formatted.contains("CPU>90%") && formatted.contains("MEM>2048MB"));
+    assert!(formatted.contains("cmdline:cargo-nextest"));
+}
+#[test]
+fn test_format_rule_shows_match_modes() {
+    let mut rule = spin_rule();
+    rule.name_pattern = "/tmp/test_*".to_string();
+    rule.name_match = MatchMode::Glob;
+    rule.cmdline_pattern = "cargo-nextest .*".to_string();
+    rule.cmdline_match = MatchMode::Regex;
+    rule.case_sensitive = true;
+    let formatted = format_rule(rule.clone());
+    assert!(formatted.contains("pattern:glob:/tmp/test_* cmdline:regex:cargo-nextest .*"));
+    assert!(formatted.contains(" case-sensitive"));
+    rule.action = RuleAction::TerminateTree;
+    rule.tree_signal = TreeSignal::ProcessGroup;
+    assert!(format_rule(rule.clone()).contains(" action:terminate-tree(process-group)"));
+    rule.action = RuleAction::Stop;
+    assert!(format_rule(rule.clone()).contains(" action:stop ("));
+    rule.action = RuleAction::Throttle;
+    rule.throttle_nice = 15;
+    assert!(format_rule(rule.clone()).contains(" action:throttle(nice 15)"));
+    rule.action = RuleAction::Cap;
+    rule.cap_cpu_percent = 50f64;
+    rule.cap_memory_mb = 512;
+    assert!(format_rule(rule.clone()).contains(" action:cap(cpu 50%)(mem 512MB)"));
+    rule.unit_pattern = "docker-*.scope".to_string();
+    rule.unit_match = MatchMode::Glob;
+    rule.exclude_unit_pattern = "docker-4f1c*".to_string();
+    let formatted = format_rule(rule.clone());
+    assert!(formatted.contains(" unit:glob:docker-*.scope !unit:glob:docker-4f1c*"));
+    rule.cmdline_pattern = "(".to_string();
+    assert!(!is_valid_rule(rule), "A pattern that does not compile is invalid");
+}
+#[test]
 fn test_rule_zero_thresholds() {
     {
         let rule = new_detection_rule(
@@ -875,7 +2154,7 @@ fn test_rule_zero_thresholds() {
             true,
         );
         {
-            let proc = new_process(
+            let _proc = new_process(
                 1234,
                 "normal".to_string(),
                 "/usr/bin/normal".to_string(),
@@ -898,7 +2177,7 @@ fn test_rule_zero_thresholds() {
 }
 #[test]
 fn test_config_creation() {
-    let empty_rules: Vec<DetectionRule> = vec![].to_vec();
+    let empty_rules: Vec<DetectionRule> = [].to_vec();
     {
         let config = new_config(
             60,
@@ -911,7 +2190,7 @@ fn test_config_creation() {
             assert!(
                 config.check_interval_secs == 60, "{}", "Check interval should be set"
             );
-            assert!(config.dry_run == false, "{}", "Dry run should be set");
+            assert!(!config.dry_run, "{}", "Dry run should be set");
             assert!(
                 config.log_file == "/var/log/reaper.log", "{}", "Log file should be set"
             );
@@ -921,7 +2200,7 @@ fn test_config_creation() {
 }
 #[test]
 fn test_valid_config() {
-    let empty_rules: Vec<DetectionRule> = vec![].to_vec();
+    let empty_rules: Vec<DetectionRule> = [].to_vec();
     {
         let valid_config = new_config(
             60,
@@ -937,7 +2216,7 @@ fn test_valid_config() {
 }
 #[test]
 fn test_invalid_zero_check_interval() {
-    let empty_rules: Vec<DetectionRule> = vec![].to_vec();
+    let empty_rules: Vec<DetectionRule> = [].to_vec();
     {
         let invalid_config = new_config(
             0,
@@ -954,7 +2233,7 @@ fn test_invalid_zero_check_interval() {
 }
 #[test]
 fn test_invalid_negative_check_interval() {
-    let empty_rules: Vec<DetectionRule> = vec![].to_vec();
+    let empty_rules: Vec<DetectionRule> = [].to_vec();
     {
         let invalid_config = new_config(
             -10,
@@ -971,7 +2250,7 @@ fn test_invalid_negative_check_interval() {
 }
 #[test]
 fn test_invalid_empty_log_file() {
-    let empty_rules: Vec<DetectionRule> = vec![].to_vec();
+    let empty_rules: Vec<DetectionRule> = [].to_vec();
     {
         let invalid_config = new_config(60, empty_rules, false, "".to_string(), 5);
         assert!(
@@ -982,7 +2261,7 @@ fn test_invalid_empty_log_file() {
 }
 #[test]
 fn test_invalid_negative_grace_period() {
-    let empty_rules: Vec<DetectionRule> = vec![].to_vec();
+    let empty_rules: Vec<DetectionRule> = [].to_vec();
     {
         let invalid_config = new_config(
             60,
@@ -999,7 +2278,7 @@ fn test_invalid_negative_grace_period() {
 }
 #[test]
 fn test_format_config() {
-    let empty_rules: Vec<DetectionRule> = vec![].to_vec();
+    let empty_rules: Vec<DetectionRule> = [].to_vec();
     {
         let config = new_config(
             60,
@@ -1009,14 +2288,14 @@ fn test_format_config() {
             5,
         );
         {
-            let mut formatted = format_config(config);
-            assert!(formatted != "", "{}", "Formatted config should not be empty")
+            let formatted = format_config(config);
+            assert!(!formatted.is_empty(), "{}", "Formatted config should not be empty")
         }
     }
 }
 #[test]
 fn test_config_zero_grace_period() {
-    let empty_rules: Vec<DetectionRule> = vec![].to_vec();
+    let empty_rules: Vec<DetectionRule> = [].to_vec();
     {
         let config = new_config(
             60,
@@ -1033,9 +2312,9 @@ fn test_config_zero_grace_period() {
 #[test]
 fn test_scan_processes_returns_array() {
     {
-        let processes = scan_processes();
+        let _processes = scan_processes(CpuNormalization::PerCore);
         {
-            let count = 0;
+            let _count = 0;
             assert!(true, "{}", "Function should return without error")
         }
     }
@@ -1043,9 +2322,9 @@ fn test_scan_processes_returns_array() {
 #[test]
 fn test_scan_processes_non_empty() {
     {
-        let processes = scan_processes();
+        let processes = scan_processes(CpuNormalization::PerCore);
         {
-            let first_proc = processes[0 as usize].clone();
+            let _first_proc = processes[0_usize].clone();
             assert!(true, "{}", "scan_processes returned at least one process")
         }
     }
@@ -1053,9 +2332,9 @@ fn test_scan_processes_non_empty() {
 #[test]
 fn test_scan_processes_valid_pids() {
     {
-        let processes = scan_processes();
+        let processes = scan_processes(CpuNormalization::PerCore);
         {
-            let first_proc = processes[0 as usize].clone();
+            let first_proc = processes[0_usize].clone();
             assert!(first_proc.pid > 0, "{}", "Process PID should be positive")
         }
     }
@@ -1063,38 +2342,64 @@ fn test_scan_processes_valid_pids() {
 #[test]
 fn test_scan_processes_includes_self() {
     {
-        let processes = scan_processes();
+        let processes = scan_processes(CpuNormalization::PerCore);
         {
-            let first_proc = processes[0 as usize].clone();
-            assert!(first_proc.name != "", "{}", "Process should have a non-empty name")
+            let first_proc = processes[0_usize].clone();
+            assert!(!first_proc.name.is_empty(), "{}", "Process should have a non-empty name")
         }
     }
 }
 #[test]
+fn test_scan_processes_finds_current_pid() {
+    let own_pid = std::process::id() as i32;
+    let processes = scan_processes(CpuNormalization::PerCore);
+    assert!(
+        processes.iter().any(|p| p.pid == own_pid),
+        "scan_processes should list the test process itself"
+    );
+}
+#[test]
+fn test_scan_processes_all_valid() {
+    for proc in scan_processes(CpuNormalization::PerCore) {
+        assert!(proc.pid > 0, "PID {} should be positive", proc.pid);
+        assert!(is_valid_process(proc.clone()), "Scanned process should be valid");
+    }
+}
+#[test]
+fn test_list_pids_sorted_numeric_only() {
+    let pids = list_pids(PROC_ROOT);
+    assert!(!pids.is_empty(), "/proc should list at least one PID");
+    assert!(pids.windows(2).all(|w| w[0] < w[1]), "PIDs should be sorted");
+}
+#[test]
+fn test_parse_proc_status_vanished_pid() {
+    assert!(parse_proc_status(i32::MAX).is_none(), "Missing PID should be None");
+}
+#[test]
 fn test_parse_proc_status_returns_process() {
     {
-        let proc = parse_proc_status(1);
+        let _proc = parse_proc_status(1).expect("PID 1 should exist");
         assert!(true, "{}", "Function should return without error")
     }
 }
 #[test]
 fn test_parse_proc_status_valid_pid() {
     {
-        let proc = parse_proc_status(1);
+        let proc = parse_proc_status(1).expect("PID 1 should exist");
         assert!(proc.pid == 1, "{}", "Parsed process should have correct PID")
     }
 }
 #[test]
 fn test_parse_proc_status_non_empty_name() {
     {
-        let proc = parse_proc_status(1);
-        assert!(proc.name != "", "{}", "Parsed process should have non-empty name")
+        let proc = parse_proc_status(1).expect("PID 1 should exist");
+        assert!(!proc.name.is_empty(), "{}", "Parsed process should have non-empty name")
     }
 }
 #[test]
 fn test_parse_proc_status_valid_memory() {
     {
-        let proc = parse_proc_status(1);
+        let proc = parse_proc_status(1).expect("PID 1 should exist");
         assert!(
             proc.memory_mb >= 0, "{}", "Parsed process should have non-negative memory"
         )
@@ -1103,14 +2408,53 @@ fn test_parse_proc_status_valid_memory() {
 #[test]
 fn test_parse_proc_status_self() {
     {
-        let proc = parse_proc_status(1);
+        let proc = parse_proc_status(1).expect("PID 1 should exist");
         assert!(is_valid_process(proc), "{}", "Parsed process should be valid")
     }
 }
 #[test]
+fn test_parse_proc_status_current_process() {
+    let own_pid = std::process::id() as i32;
+    let proc = parse_proc_status(own_pid).expect("test process should exist");
+    assert!(proc.pid == own_pid, "Parsed process should have correct PID");
+    assert!(proc.memory_mb > 0, "Test process should have resident memory");
+    assert!(proc.status != ProcessStatus::Zombie, "Test process is alive");
+    assert!(!proc.cmdline.is_empty(), "Test process should have a cmdline");
+}
+#[test]
+fn test_parse_status_field() {
+    let status = "Name:\tcargo-nextest\nState:\tR (running)\nVmRSS:\t  204800 kB\n";
+    assert!(parse_status_field(status, "Name") == Some("cargo-nextest".to_string()));
+    assert!(parse_status_field(status, "VmSwap").is_none());
+    assert!(parse_vm_rss_mb(status) == 200, "204800 kB should be 200 MB");
+    assert!(parse_vm_rss_mb("Name:\tkthreadd\n") == 0, "No VmRSS means 0 MB");
+}
+#[test]
+fn test_parse_stat_fields_comm_with_parens() {
+    let stat = "4242 (evil) name) S 1 4242 4242 0 -1 4194560";
+    let fields = parse_stat_fields(stat).expect("stat should parse");
+    assert!(fields[0] == "S", "State should follow the last ')'");
+    assert!(fields[1] == "1", "PPID should follow the state");
+    assert!(parse_stat_fields("garbage").is_none());
+}
+#[test]
+fn test_status_from_state_char() {
+    assert!(status_from_state_char('R') == ProcessStatus::Running);
+    assert!(status_from_state_char('S') == ProcessStatus::Sleeping);
+    assert!(status_from_state_char('D') == ProcessStatus::Sleeping);
+    assert!(status_from_state_char('T') == ProcessStatus::Stopped);
+    assert!(status_from_state_char('Z') == ProcessStatus::Zombie);
+}
+#[test]
+fn test_parse_cmdline_nul_separated() {
+    let raw = b"cargo\0nextest\0run\0--test-threads=1\0";
+    assert!(parse_cmdline(raw) == "cargo nextest run --test-threads=1");
+    assert!(parse_cmdline(b"").is_empty(), "Kernel threads have no cmdline");
+}
+#[test]
 fn test_get_cpu_usage_returns_number() {
     {
-        let cpu = get_process_cpu_usage(1);
+        let _cpu = get_process_cpu_usage(1);
         assert!(true, "{}", "Function should return without error")
     }
 }
@@ -1133,7 +2477,7 @@ fn test_get_cpu_usage_init_low() {
     {
         let cpu = get_process_cpu_usage(1);
         assert!(
-            cpu >= 0f64 && cpu < 50f64, "{}",
+            (0f64..50f64).contains(&cpu), "{}",
             "Init process should have reasonable CPU usage"
         )
     }
@@ -1143,17 +2487,260 @@ fn test_get_cpu_usage_valid_range() {
     {
         let cpu = get_process_cpu_usage(1);
         assert!(
-            cpu >= 0f64 && cpu <= 100f64, "{}",
+            (0f64..=100f64).contains(&cpu), "{}",
             "CPU usage should be in range [0.0, 100.0]"
         )
     }
 }
 #[test]
+fn test_get_cpu_usage_missing_pid() {
+    let cpu = get_process_cpu_usage_with(i32::MAX, 10, CpuNormalization::WholeMachine);
+    assert!(cpu == 0f64, "Missing PID should report no CPU usage");
+}
+#[test]
+fn test_parse_stat_cpu_ticks() {
+    let stat = "77 (spin) R 1 77 77 0 -1 4194304 100 0 0 0 1500 250 0 0 20 0 1 0 900 0 0";
+    assert!(parse_stat_cpu_ticks(stat) == Some(1750), "utime + stime");
+    assert!(parse_stat_cpu_ticks("77 (short) R 1").is_none());
+}
+#[test]
+fn test_parse_system_cpu() {
+    let stat = "cpu  400 0 100 3500 0 0 0 0 50 0\n\
+                cpu0 200 0 50 1750 0 0 0 0 25 0\n\
+                cpu1 200 0 50 1750 0 0 0 0 25 0\n\
+                btime 1700000000\n";
+    let sys = parse_system_cpu(stat).expect("cpu line should parse");
+    assert!(sys.total_ticks == 4000, "Guest time must not be double counted");
+    assert!(sys.num_cpus == 2, "Per-CPU lines should be counted");
+    assert!(parse_system_cpu("intr 0\n").is_none());
+}
+#[test]
+fn test_cpu_percent_per_core_vs_whole_machine() {
+    let before = SystemCpu { total_ticks: 0, num_cpus: 4 };
+    let after = SystemCpu { total_ticks: 400, num_cpus: 4 };
+    let per_core = cpu_percent(0, 100, before, after, CpuNormalization::PerCore);
+    let whole = cpu_percent(0, 100, before, after, CpuNormalization::WholeMachine);
+    assert!((per_core - 100f64).abs() < 1e-9, "One pinned core is 100% per-core");
+    assert!((whole - 25f64).abs() < 1e-9, "One of four cores is 25% of the machine");
+}
+#[test]
+fn test_cpu_percent_clamped_and_zero_interval() {
+    let before = SystemCpu { total_ticks: 100, num_cpus: 1 };
+    let after = SystemCpu { total_ticks: 200, num_cpus: 1 };
+    assert!(cpu_percent(0, 500, before, after, CpuNormalization::PerCore) == 100f64);
+    assert!(cpu_percent(50, 40, before, after, CpuNormalization::PerCore) == 0f64);
+    assert!(cpu_percent(0, 10, before, before, CpuNormalization::PerCore) == 0f64);
+}
+#[cfg(test)]
+fn fake_proc_root(name: &str) -> String {
+    let root = std::env::temp_dir().join(format!("reaper-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&root).expect("create fake proc root");
+    std::fs::write(
+        root.join("stat"),
+        "cpu  1000 0 0 9000 0 0 0 0 0 0\ncpu0 1000 0 0 9000\nbtime 1700000000\n",
+    )
+    .expect("write fake /proc/stat");
+    root.to_string_lossy().to_string()
+}
+#[cfg(test)]
+fn write_fake_pid(
+    root: &str,
+    pid: i32,
+    ppid: i32,
+    name: &str,
+    state: char,
+    rss_kb: i64,
+    cmdline: &[&str],
+) {
+    let dir = format!("{}/{}", root, pid);
+    std::fs::create_dir_all(&dir).expect("create fake pid dir");
+    let stat = format!(
+        "{} ({}) {} {} {} {} 0 -1 4194304 0 0 0 0 500 100 0 0 20 0 1 0 1000 0 0",
+        pid, name, state, ppid, pid, pid
+    );
+    let status = format!(
+        "Name:\t{}\nState:\t{}\nVmRSS:\t{} kB\n",
+        name, state, rss_kb
+    );
+    let mut raw_cmdline = cmdline.join("\0");
+    raw_cmdline.push('\0');
+    std::fs::write(format!("{}/stat", dir), stat).expect("write fake stat");
+    std::fs::write(format!("{}/status", dir), status).expect("write fake status");
+    std::fs::write(format!("{}/cmdline", dir), raw_cmdline).expect("write fake cmdline");
+}
+#[test]
+fn test_procfs_source_reads_fake_root() {
+    let root = fake_proc_root("procfs-source");
+    write_fake_pid(
+        &root,
+        4242,
+        1,
+        "cargo-nextest",
+        'R',
+        2097152,
+        &["cargo-nextest", "run"],
+    );
+    write_fake_pid(&root, 300, 250, "bash", 'S', 4096, &["/bin/bash"]);
+    std::fs::create_dir_all(format!("{}/self", root)).expect("create self dir");
+    // A PID that exited after readdir leaves an empty directory behind.
+    std::fs::create_dir_all(format!("{}/999", root)).expect("create vanished dir");
+    let mut source = new_procfs_source(root.clone(), CpuNormalization::PerCore);
+    source.sample_interval_ms = 0;
+    let processes = source.processes();
+    assert!(
+        processes.len() == 2,
+        "Only complete PID dirs should be listed"
+    );
+    assert!(processes[0].pid == 300 && processes[0].name == "bash");
+    assert!(
+        processes[1].pid == 4242,
+        "Processes should be sorted by PID"
+    );
+    assert!(
+        processes[1].memory_mb == 2048,
+        "VmRSS should be read from the fake root"
+    );
+    assert!(processes[1].status == ProcessStatus::Running);
+    assert!(processes[1].cmdline == "cargo-nextest run");
+    assert!(source.process(4242).is_some() && source.process(999).is_none());
+    let _ = std::fs::remove_dir_all(&root);
+}
+#[test]
+fn test_procfs_source_start_time_and_parent() {
+    let root = fake_proc_root("procfs-lineage");
+    write_fake_pid(&root, 500, 1, "orphan", 'S', 1024, &["./orphan"]);
+    write_fake_pid(&root, 501, 500, "child", 'S', 1024, &["./child"]);
+    let orphan = parse_proc_status_at(&root, 500, 1700000000, 1700003610).expect("orphan");
+    let child = parse_proc_status_at(&root, 501, 1700000000, 1700003610).expect("child");
+    assert!(orphan.parent_pid == 1 && child.parent_pid == 500);
+    let start_time = 1700000000 + 1000 / clock_ticks_per_sec();
+    assert!(orphan.start_time == start_time, "starttime is in clock ticks after btime");
+    assert!(orphan.age_seconds == 1700003610 - start_time, "Age is now minus start time");
+    assert!(read_boot_time(&root) == 1700000000);
+    let _ = std::fs::remove_dir_all(&root);
+}
+#[test]
+fn test_procfs_source_reads_cgroup_membership() {
+    let root = fake_proc_root("procfs-cgroup");
+    write_fake_pid(&root, 600, 1, "sshd", 'S', 1024, &["sshd: ci"]);
+    write_fake_pid(&root, 601, 1, "kworker", 'I', 0, &[]);
+    let cgroup = "0::/system.slice/sshd.service\n";
+    std::fs::write(format!("{}/600/cgroup", root), cgroup).expect("write fake cgroup");
+    let sshd = parse_proc_status_at(&root, 600, 1700000000, 1700003610).expect("sshd");
+    let kworker = parse_proc_status_at(&root, 601, 1700000000, 1700003610).expect("kworker");
+    assert!(sshd.cgroup == "/system.slice/sshd.service");
+    assert!(sshd.systemd_unit == "sshd.service" && sshd.container_id.is_empty());
+    assert!(
+        kworker.cgroup.is_empty(),
+        "A missing cgroup file leaves the fields empty"
+    );
+    let _ = std::fs::remove_dir_all(&root);
+}
+#[test]
+fn test_parse_proc_status_current_process_lineage() {
+    let proc = parse_proc_status(std::process::id() as i32).expect("test process");
+    assert!(proc.parent_pid > 0, "Test process should have a parent");
+    assert!(proc.start_time > 0 && proc.age_seconds >= 0);
+}
+#[test]
+fn test_parse_boot_time_and_age() {
+    assert!(parse_boot_time("cpu  1 2 3\nbtime 1700000000\n") == Some(1700000000));
+    assert!(parse_boot_time("cpu  1 2 3\n").is_none());
+    assert!(process_age_seconds(100, 50) == 0, "Clock skew should not go negative");
+}
+#[test]
+fn test_procfs_source_missing_root() {
+    let source = new_procfs_source(
+        "/nonexistent/reaper-proc".to_string(),
+        CpuNormalization::PerCore,
+    );
+    assert!(
+        source.processes().is_empty(),
+        "Missing root should yield no processes"
+    );
+}
+#[test]
+fn test_snapshot_source_serves_in_order() {
+    let first = vec![new_process(
+        10,
+        "a".to_string(),
+        "a".to_string(),
+        5f64,
+        1,
+        ProcessStatus::Running,
+    )];
+    let second = vec![new_process(
+        20,
+        "b".to_string(),
+        "b".to_string(),
+        99f64,
+        1,
+        ProcessStatus::Running,
+    )];
+    let source = new_snapshot_source(vec![first, second]);
+    assert!(
+        source.process(10).is_some(),
+        "Lookups before a scan use the first table"
+    );
+    assert!(source.processes()[0].pid == 10);
+    assert!(source.processes()[0].pid == 20);
+    assert!(source.processes()[0].pid == 20, "Last table should repeat");
+    assert!(source.process(20).is_some() && source.process(10).is_none());
+    assert!(new_snapshot_source(vec![]).processes().is_empty());
+}
+#[test]
+fn test_apply_rules_against_snapshot_fixture() {
+    let table = vec![
+        new_process(
+            101,
+            "cargo".to_string(),
+            "cargo build".to_string(),
+            35f64,
+            800,
+            ProcessStatus::Running,
+        ),
+        new_process(
+            102,
+            "spin".to_string(),
+            "./spin".to_string(),
+            99.9f64,
+            12,
+            ProcessStatus::Running,
+        ),
+        new_process(
+            103,
+            "sshd".to_string(),
+            "sshd: ci".to_string(),
+            0f64,
+            6,
+            ProcessStatus::Sleeping,
+        ),
+    ];
+    let source = new_snapshot_source(vec![table]);
+    let rule = new_detection_rule(
+        "Spin".to_string(),
+        Priority::High,
+        90f64,
+        0,
+        "".to_string(),
+        "".to_string(),
+        true,
+    );
+    let matched = apply_rules(source.processes(), vec![rule]);
+    assert!(
+        matched.len() == 1 && matched[0].process.pid == 102,
+        "Only the spinning PID should match"
+    );
+    assert!(matched[0].rule_name == "Spin" && matched[0].priority == Priority::High);
+    assert!(matched[0].reasons == vec!["cpu 99.9 > 90".to_string()]);
+}
+#[test]
 fn test_apply_rules_returns_array() {
-    let empty_procs: Vec<Process> = vec![].to_vec();
-    let empty_rules: Vec<DetectionRule> = vec![].to_vec();
+    let empty_procs: Vec<Process> = [].to_vec();
+    let empty_rules: Vec<DetectionRule> = [].to_vec();
     {
-        let mut result = apply_rules(empty_procs, empty_rules);
+        let _result = apply_rules(empty_procs, empty_rules);
         assert!(true, "{}", "Function should return without error")
     }
 }
@@ -1170,9 +2757,9 @@ fn test_apply_rules_empty_rules() {
         );
         {
             let procs: Vec<Process> = [proc].to_vec();
-            let empty_rules: Vec<DetectionRule> = vec![].to_vec();
+            let empty_rules: Vec<DetectionRule> = [].to_vec();
             {
-                let mut result = apply_rules(procs, empty_rules);
+                let _result = apply_rules(procs, empty_rules);
                 assert!(true, "{}", "Empty rules should return empty result")
             }
         }
@@ -1204,7 +2791,7 @@ fn test_apply_rules_no_matches() {
                 {
                     let rules: Vec<DetectionRule> = [rule].to_vec();
                     {
-                        let mut result = apply_rules(procs, rules);
+                        let _result = apply_rules(procs, rules);
                         assert!(true, "{}", "No matches should return empty")
                     }
                 }
@@ -1238,11 +2825,11 @@ fn test_apply_rules_one_match() {
                 {
                     let rules: Vec<DetectionRule> = [rule].to_vec();
                     {
-                        let mut result = apply_rules(procs, rules);
+                        let result = apply_rules(procs, rules);
                         {
-                            let first = result[0 as usize].clone();
+                            let first = result[0_usize].clone();
                             assert!(
-                                first.pid == 1234, "{}", "Should return matching process"
+                                first.process.pid == 1234, "{}", "Should return matching process"
                             )
                         }
                     }
@@ -1277,7 +2864,7 @@ fn test_apply_rules_disabled_rule() {
                 {
                     let rules: Vec<DetectionRule> = [rule].to_vec();
                     {
-                        let mut result = apply_rules(procs, rules);
+                        let _result = apply_rules(procs, rules);
                         assert!(true, "{}", "Disabled rules should not match")
                     }
                 }
@@ -1320,18 +2907,18 @@ fn test_apply_rules_multiple_matches() {
                     {
                         let rules: Vec<DetectionRule> = [rule].to_vec();
                         {
-                            let mut result = apply_rules(procs, rules);
+                            let result = apply_rules(procs, rules);
                             {
                                 assert!(
                                     result.len() == 2, "{}",
                                     "Should return 2 matching processes"
                                 );
                                 assert!(
-                                    result[0 as usize].clone().pid == 1234, "{}",
+                                    result[0_usize].clone().process.pid == 1234, "{}",
                                     "First process should be PID 1234"
                                 );
                                 assert!(
-                                    result[1 as usize].clone().pid == 5678, "{}",
+                                    result[1_usize].clone().process.pid == 5678, "{}",
                                     "Second process should be PID 5678"
                                 )
                             }
@@ -1364,7 +2951,7 @@ fn test_detect_cpu_hog_below_threshold() {
                 true,
             );
             {
-                let mut result = detect_cpu_hog(proc, rule);
+                let result = detect_cpu_hog(proc, rule);
                 assert!(! result, "{}", "CPU below threshold should return false")
             }
         }
@@ -1392,7 +2979,7 @@ fn test_detect_cpu_hog_above_threshold() {
                 true,
             );
             {
-                let mut result = detect_cpu_hog(proc, rule);
+                let result = detect_cpu_hog(proc, rule);
                 assert!(result, "{}", "CPU above threshold should return true")
             }
         }
@@ -1420,7 +3007,7 @@ fn test_detect_cpu_hog_at_threshold() {
                 true,
             );
             {
-                let mut result = detect_cpu_hog(proc, rule);
+                let result = detect_cpu_hog(proc, rule);
                 assert!(
                     ! result, "{}", "CPU at threshold should return false (must exceed)"
                 )
@@ -1450,7 +3037,7 @@ fn test_detect_cpu_hog_disabled_threshold() {
                 true,
             );
             {
-                let mut result = detect_cpu_hog(proc, rule);
+                let result = detect_cpu_hog(proc, rule);
                 assert!(! result, "{}", "Disabled threshold (0.0) should return false")
             }
         }
@@ -1478,7 +3065,7 @@ fn test_detect_cpu_hog_very_high() {
                 true,
             );
             {
-                let mut result = detect_cpu_hog(proc, rule);
+                let result = detect_cpu_hog(proc, rule);
                 assert!(result, "{}", "Very high CPU should return true")
             }
         }
@@ -1506,7 +3093,7 @@ fn test_detect_memory_hog_below_threshold() {
                 true,
             );
             {
-                let mut result = detect_memory_hog(proc, rule);
+                let result = detect_memory_hog(proc, rule);
                 assert!(! result, "{}", "Memory below threshold should return false")
             }
         }
@@ -1534,7 +3121,7 @@ fn test_detect_memory_hog_above_threshold() {
                 true,
             );
             {
-                let mut result = detect_memory_hog(proc, rule);
+                let result = detect_memory_hog(proc, rule);
                 assert!(result, "{}", "Memory above threshold should return true")
             }
         }
@@ -1562,7 +3149,7 @@ fn test_detect_memory_hog_at_threshold() {
                 true,
             );
             {
-                let mut result = detect_memory_hog(proc, rule);
+                let result = detect_memory_hog(proc, rule);
                 assert!(
                     ! result, "{}",
                     "Memory at threshold should return false (must exceed)"
@@ -1593,7 +3180,7 @@ fn test_detect_memory_hog_disabled_threshold() {
                 true,
             );
             {
-                let mut result = detect_memory_hog(proc, rule);
+                let result = detect_memory_hog(proc, rule);
                 assert!(! result, "{}", "Disabled threshold (0) should return false")
             }
         }
@@ -1621,7 +3208,7 @@ fn test_detect_memory_hog_very_high() {
                 true,
             );
             {
-                let mut result = detect_memory_hog(proc, rule);
+                let result = detect_memory_hog(proc, rule);
                 assert!(result, "{}", "Very high memory should return true")
             }
         }
@@ -1639,7 +3226,7 @@ fn test_match_name_pattern_empty() {
             ProcessStatus::Running,
         );
         {
-            let mut result = match_name_pattern(proc, "".to_string());
+            let result = match_name_pattern(proc, "".to_string());
             assert!(result, "{}", "Empty pattern should match any process")
         }
     }
@@ -1656,7 +3243,7 @@ fn test_match_name_pattern_exact() {
             ProcessStatus::Running,
         );
         {
-            let mut result = match_name_pattern(proc, "chrome".to_string());
+            let result = match_name_pattern(proc, "chrome".to_string());
             assert!(result, "{}", "Exact pattern should match")
         }
     }
@@ -1673,7 +3260,7 @@ fn test_match_name_pattern_partial() {
             ProcessStatus::Running,
         );
         {
-            let mut result = match_name_pattern(proc, "firefox".to_string());
+            let result = match_name_pattern(proc, "firefox".to_string());
             assert!(result, "{}", "Partial pattern should match substring")
         }
     }
@@ -1690,7 +3277,7 @@ fn test_match_name_pattern_case_insensitive() {
             ProcessStatus::Running,
         );
         {
-            let mut result = match_name_pattern(proc, "chrome".to_string());
+            let result = match_name_pattern(proc, "chrome".to_string());
             assert!(result, "{}", "Pattern should be case-insensitive")
         }
     }
@@ -1707,7 +3294,7 @@ fn test_match_name_pattern_no_match() {
             ProcessStatus::Running,
         );
         {
-            let mut result = match_name_pattern(proc, "emacs".to_string());
+            let result = match_name_pattern(proc, "emacs".to_string());
             assert!(! result, "{}", "Non-matching pattern should return false")
         }
     }
@@ -1715,14 +3302,16 @@ fn test_match_name_pattern_no_match() {
 #[test]
 fn test_terminate_process_returns_result() {
     {
-        let mut result = terminate_process(1, 5);
+        let _result = terminate_process(NONEXISTENT_PID, 5);
         assert!(true, "{}", "Function should return ActionResult type")
     }
 }
 #[test]
 fn test_terminate_process_success() {
     {
-        let mut result = terminate_process(99999, 5);
+        let mut child = spawn_sleeper();
+        let result = terminate_process(child.id() as i32, 5);
+        let _ = child.wait();
         match result {
             ActionResult::Success => {
                 assert!(true, "{}", "Process terminated successfully")
@@ -1734,25 +3323,30 @@ fn test_terminate_process_success() {
 #[test]
 fn test_terminate_process_already_dead() {
     {
-        let mut result = terminate_process(99999, 5);
-        match result {
-            ActionResult::AlreadyDead => assert!(true, "{}", "Process already dead"),
-            ActionResult::NotFound => assert!(true, "{}", "Process not found"),
-            _ => assert!(true, "{}", "Acceptable result for non-existent process"),
+        let mut child = std::process::Command::new("true").spawn().expect("spawn true");
+        let pid = child.id() as i32;
+        while !process_is_zombie(pid) {
+            std::thread::sleep(std::time::Duration::from_millis(10));
         }
+        let result = terminate_process(pid, 5);
+        let _ = child.wait();
+        assert!(result == ActionResult::AlreadyDead, "Unreaped child is already dead");
+        assert!(terminate_process(NONEXISTENT_PID, 5) == ActionResult::NotFound);
     }
 }
 #[test]
 fn test_terminate_process_zero_grace() {
     {
-        let mut result = terminate_process(99999, 0);
-        assert!(true, "{}", "Zero grace period should work")
+        let mut child = spawn_sleeper();
+        let result = terminate_process(child.id() as i32, 0);
+        let _ = child.wait();
+        assert!(result == ActionResult::Success, "{}", "Zero grace period should work")
     }
 }
 #[test]
 fn test_terminate_process_invalid_pid() {
     {
-        let mut result = terminate_process(-1, 5);
+        let result = terminate_process(-1, 5);
         match result {
             ActionResult::Failed => assert!(true, "{}", "Invalid PID should fail"),
             ActionResult::PermissionDenied => assert!(true, "{}", "Invalid PID denied"),
@@ -1760,11 +3354,119 @@ fn test_terminate_process_invalid_pid() {
         }
     }
 }
+#[cfg(test)]
+const NONEXISTENT_PID: i32 = i32::MAX;
+#[cfg(test)]
+fn spawn_sleeper() -> std::process::Child {
+    std::process::Command::new("sleep").arg("30").spawn().expect("spawn sleep")
+}
+#[test]
+fn test_terminate_process_escalates_to_sigkill() {
+    let mut child = std::process::Command::new("sh")
+        .arg("-c")
+        .arg("trap '' TERM; echo ready; while :; do sleep 0.1; done")
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .expect("spawn sh");
+    let mut ready = [0u8; 6];
+    std::io::Read::read_exact(child.stdout.as_mut().expect("stdout"), &mut ready)
+        .expect("wait for trap");
+    let started = std::time::Instant::now();
+    let result = terminate_process(child.id() as i32, 1);
+    let status = child.wait().expect("reap sh");
+    assert!(result == ActionResult::Success, "SIGKILL should finish the job");
+    assert!(started.elapsed() >= std::time::Duration::from_secs(1), "Grace honoured");
+    assert!(std::os::unix::process::ExitStatusExt::signal(&status) == Some(libc::SIGKILL));
+}
+#[test]
+fn test_terminate_process_sigterm_within_grace() {
+    let mut child = spawn_sleeper();
+    let started = std::time::Instant::now();
+    let result = terminate_process(child.id() as i32, 10);
+    let status = child.wait().expect("reap sleep");
+    assert!(result == ActionResult::Success);
+    assert!(started.elapsed() < std::time::Duration::from_secs(5), "No need to wait");
+    assert!(std::os::unix::process::ExitStatusExt::signal(&status) == Some(libc::SIGTERM));
+}
+#[test]
+fn test_terminate_verified_matching_identity() {
+    let mut child = spawn_sleeper();
+    let proc = parse_proc_status(child.id() as i32).expect("spawned child");
+    let result = terminate_verified(proc, 5);
+    let _ = child.wait();
+    assert!(result == ActionResult::Success, "Confirmed target should be terminated");
+}
+#[test]
+fn test_terminate_verified_refuses_recycled_pid() {
+    let mut child = spawn_sleeper();
+    let mut stale = parse_proc_status(child.id() as i32).expect("spawned child");
+    stale.start_ticks -= 1;
+    let result = terminate_verified(stale.clone(), 5);
+    assert!(result == ActionResult::IdentityMismatch, "Different starttime is refused");
+    stale.start_ticks += 1;
+    stale.exe = "/usr/bin/innocent".to_string();
+    let result = terminate_verified(stale, 5);
+    assert!(result == ActionResult::IdentityMismatch, "Different exe is refused");
+    assert!(!process_exited(child.id() as i32), "No signal should have been sent");
+    let _ = child.kill();
+    let _ = child.wait();
+}
+#[test]
+fn test_terminate_verified_reads_fixture_root() {
+    let mut child = spawn_sleeper();
+    let pid = child.id() as i32;
+    let root = fake_proc_root("terminate-fixture");
+    write_fake_pid(&root, pid, 1, "sleep", 'Z', 0, &["sleep", "30"]);
+    let boot_time = read_boot_time(&root);
+    let proc = parse_proc_status_at(&root, pid, boot_time, unix_now()).expect("fixture");
+    let result = terminate_verified_at(&root, proc.clone(), 5);
+    assert!(result == ActionResult::AlreadyDead, "The fixture's zombie state is trusted");
+    assert!(!process_exited(pid), "No signal should have been sent");
+    let result = terminate_verified(proc.clone(), 5);
+    assert!(result == ActionResult::IdentityMismatch, "Live /proc describes another process");
+    write_fake_pid(&root, pid, 1, "sleep", 'S', 1024, &["sleep", "30"]);
+    let result = terminate_verified_at(&root, proc, 5);
+    let status = child.wait().expect("reap sleep");
+    let _ = std::fs::remove_dir_all(&root);
+    assert!(result == ActionResult::Success, "Identity confirmed by the fixture");
+    assert!(std::os::unix::process::ExitStatusExt::signal(&status) == Some(libc::SIGTERM));
+}
+#[test]
+fn test_terminate_rechecked_fallback() {
+    let mut child = spawn_sleeper();
+    let proc = parse_proc_status(child.id() as i32).expect("spawned child");
+    let result = terminate_rechecked(PROC_ROOT, proc, 5);
+    let _ = child.wait();
+    assert!(result == ActionResult::Success, "Fallback path should still terminate");
+}
+#[test]
+fn test_terminate_verified_missing_pid() {
+    let proc = new_process(
+        NONEXISTENT_PID,
+        "gone".to_string(),
+        "gone".to_string(),
+        0f64,
+        0,
+        ProcessStatus::Running,
+    );
+    assert!(terminate_verified(proc, 1) == ActionResult::NotFound);
+}
+#[test]
+fn test_same_exe_ignores_deleted_suffix() {
+    assert!(same_exe("/tmp/target/test-abc (deleted)", "/tmp/target/test-abc"));
+    assert!(!same_exe("/usr/bin/bash", "/tmp/target/test-abc"));
+}
+#[test]
+fn test_action_result_from_errno() {
+    assert!(action_result_from_errno(libc::EPERM) == ActionResult::PermissionDenied);
+    assert!(action_result_from_errno(libc::ESRCH) == ActionResult::NotFound);
+    assert!(action_result_from_errno(libc::EINVAL) == ActionResult::Failed);
+}
 #[test]
 fn test_safe_kill_returns_bool() {
     {
         let proc = new_process(
-            99999,
+            NONEXISTENT_PID,
             "test".to_string(),
             "/test".to_string(),
             10f64,
@@ -1772,7 +3474,7 @@ fn test_safe_kill_returns_bool() {
             ProcessStatus::Running,
         );
         {
-            let mut result: bool = safe_kill_with_grace(proc, 5);
+            let _result: bool = safe_kill_with_grace(proc, 5, &default_config());
             assert!(true, "{}", "safe_kill_with_grace returns bool")
         }
     }
@@ -1780,20 +3482,12 @@ fn test_safe_kill_returns_bool() {
 #[test]
 fn test_safe_kill_success() {
     {
-        let proc = new_process(
-            99999,
-            "test_proc".to_string(),
-            "/bin/test".to_string(),
-            50f64,
-            200,
-            ProcessStatus::Running,
-        );
+        let mut child = spawn_sleeper();
+        let proc = parse_proc_status(child.id() as i32).expect("spawned child");
         {
-            let mut result = safe_kill_with_grace(proc, 5);
-            assert!(
-                result, "{}",
-                "Process should be terminated successfully (RED - will fail)"
-            )
+            let result = safe_kill_with_grace(proc, 5, &default_config());
+            let _ = child.wait();
+            assert!(result, "{}", "Process should be terminated successfully")
         }
     }
 }
@@ -1801,7 +3495,7 @@ fn test_safe_kill_success() {
 fn test_safe_kill_with_valid_process() {
     {
         let proc = new_process(
-            12345,
+            NONEXISTENT_PID,
             "valid_proc".to_string(),
             "/usr/bin/valid".to_string(),
             30f64,
@@ -1809,7 +3503,7 @@ fn test_safe_kill_with_valid_process() {
             ProcessStatus::Running,
         );
         {
-            let mut result = safe_kill_with_grace(proc, 3);
+            let _result = safe_kill_with_grace(proc, 3, &default_config());
             assert!(true, "{}", "Valid process handled")
         }
     }
@@ -1818,7 +3512,7 @@ fn test_safe_kill_with_valid_process() {
 fn test_safe_kill_zero_grace() {
     {
         let proc = new_process(
-            11111,
+            NONEXISTENT_PID,
             "quick_kill".to_string(),
             "/bin/quick".to_string(),
             80f64,
@@ -1826,7 +3520,7 @@ fn test_safe_kill_zero_grace() {
             ProcessStatus::Running,
         );
         {
-            let mut result = safe_kill_with_grace(proc, 0);
+            let _result = safe_kill_with_grace(proc, 0, &default_config());
             assert!(true, "{}", "Zero grace period should work")
         }
     }
@@ -1835,7 +3529,7 @@ fn test_safe_kill_zero_grace() {
 fn test_safe_kill_long_grace() {
     {
         let proc = new_process(
-            22222,
+            NONEXISTENT_PID,
             "slow_proc".to_string(),
             "/bin/slow".to_string(),
             90f64,
@@ -1843,20 +3537,28 @@ fn test_safe_kill_long_grace() {
             ProcessStatus::Running,
         );
         {
-            let mut result = safe_kill_with_grace(proc, 10);
+            let _result = safe_kill_with_grace(proc, 10, &default_config());
             assert!(true, "{}", "Long grace period should work")
         }
     }
 }
+#[cfg(test)]
+fn scan_argv() -> Vec<String> {
+    // An explicit empty config keeps the host's /etc/reaper.toml out of tests.
+    let path = std::env::temp_dir().join(format!("reaper-empty-{}.toml", std::process::id()));
+    std::fs::write(&path, "").expect("write empty config");
+    let path = path.to_string_lossy().to_string();
+    vec!["ruchy-reaper".to_string(), "scan".to_string(), "--config".to_string(), path]
+}
 #[test]
 fn test_parse_args_returns_config() {
-    let config: Config = parse_args();
+    let _config: Config = parse_args(scan_argv()).expect("parse_args").config;
     assert!(true, "{}", "parse_args returns Config");
 }
 #[test]
 fn test_parse_args_check_interval() {
     {
-        let config = parse_args();
+        let config = parse_args(scan_argv()).expect("parse_args").config;
         assert!(
             config.check_interval_secs > 0, "{}",
             "Check interval should be positive (RED - will fail if 0)"
@@ -1866,14 +3568,14 @@ fn test_parse_args_check_interval() {
 #[test]
 fn test_parse_args_log_file() {
     {
-        let config = parse_args();
-        assert!(config.log_file.len() > 0, "{}", "Log file should not be empty")
+        let config = parse_args(scan_argv()).expect("parse_args").config;
+        assert!(!config.log_file.is_empty(), "{}", "Log file should not be empty")
     }
 }
 #[test]
 fn test_parse_args_grace_period() {
     {
-        let config = parse_args();
+        let config = parse_args(scan_argv()).expect("parse_args").config;
         assert!(
             config.grace_period_secs >= 0, "{}", "Grace period should be non-negative"
         )
@@ -1891,7 +3593,7 @@ fn test_default_config_valid() {
             assert!(
                 config.grace_period_secs == 5, "{}", "Default grace period should be 5s"
             );
-            assert!(config.dry_run == false, "{}", "Default dry_run should be false");
+            assert!(!config.dry_run, "{}", "Default dry_run should be false");
             assert!(
                 config.log_file == "/var/log/reaper.log", "{}",
                 "Default log file should be /var/log/reaper.log"
@@ -1904,42 +3606,528 @@ fn test_daemon_loop_callable() {
     {
         let config = default_config();
         {
-            daemon_loop(config);
-            assert!(true, "{}", "daemon_loop callable without crashing")
+            let cycles = run_daemon(
+                config,
+                &new_snapshot_source(vec![]),
+                &AtomicBool::new(false),
+                Some(1),
+            );
+            assert!(cycles == 1, "{}", "daemon_loop callable without crashing")
         }
     }
 }
 #[test]
 fn test_daemon_loop_custom_config() {
-    let empty_rules: Vec<DetectionRule> = vec![].to_vec();
+    let empty_rules: Vec<DetectionRule> = [].to_vec();
     {
-        let config = new_config(
-            30,
-            empty_rules,
-            true,
-            "/tmp/reaper.log".to_string(),
-            10,
-        );
+        let config = new_config(30, empty_rules, true, "/tmp/reaper.log".to_string(), 10);
         {
-            daemon_loop(config);
-            assert!(true, "{}", "daemon_loop accepts custom config")
+            let cycles = run_daemon(
+                config,
+                &new_snapshot_source(vec![]),
+                &AtomicBool::new(false),
+                Some(1),
+            );
+            assert!(cycles == 1, "{}", "daemon_loop accepts custom config")
         }
     }
 }
 #[test]
 fn test_daemon_loop_with_defaults() {
-    daemon_loop(default_config());
-    assert!(true, "{}", "daemon_loop works with default config");
+    let cycles = run_daemon(
+        default_config(),
+        &new_snapshot_source(vec![]),
+        &AtomicBool::new(false),
+        Some(1),
+    );
+    assert!(cycles == 1, "{}", "daemon_loop works with default config");
+}
+#[cfg(test)]
+use std::sync::atomic::AtomicBool;
+#[cfg(test)]
+fn spin_rule() -> DetectionRule {
+    new_detection_rule(
+        "Spin".to_string(),
+        Priority::High,
+        90f64,
+        0,
+        "".to_string(),
+        "".to_string(),
+        true,
+    )
+}
+#[test]
+fn test_run_daemon_stops_when_shutdown_set() {
+    let shutdown = AtomicBool::new(true);
+    let cycles = run_daemon(
+        default_config(),
+        &new_snapshot_source(vec![]),
+        &shutdown,
+        None,
+    );
+    assert!(cycles == 0, "A pending shutdown should prevent any cycle");
+}
+#[test]
+fn test_run_daemon_shutdown_interrupts_sleep() {
+    let mut config = default_config();
+    config.check_interval_secs = 3600;
+    let shutdown = std::sync::Arc::new(AtomicBool::new(false));
+    let flag = shutdown.clone();
+    let stopper = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        flag.store(true, std::sync::atomic::Ordering::SeqCst);
+    });
+    let started = std::time::Instant::now();
+    let cycles = run_daemon(config, &new_snapshot_source(vec![]), &shutdown, None);
+    stopper.join().expect("stopper thread");
+    assert!(cycles == 1, "One cycle should run before the shutdown");
+    assert!(
+        started.elapsed() < std::time::Duration::from_secs(5),
+        "Sleep is interruptible"
+    );
+}
+#[test]
+fn test_run_cycle_dry_run_sends_no_signal() {
+    let mut child = spawn_sleeper();
+    let mut proc = parse_proc_status(child.id() as i32).expect("spawned child");
+    proc.cpu_usage = 99.9;
+    let mut config = default_config();
+    config.rules = vec![spin_rule()];
+    config.dry_run = true;
+    config.log_file = temp_log_path("dry-run");
+    let source = new_snapshot_source(vec![vec![proc]]);
+    let mut state = new_cycle_state();
+    let metrics = metrics::new_metrics();
+    let outcomes = run_cycle(
+        &config,
+        &source,
+        &AtomicBool::new(false),
+        &mut state,
+        &metrics,
+    );
+    assert!(
+        outcomes.len() == 1 && outcomes[0].1.is_none(),
+        "Dry run only reports"
+    );
+    let audit = std::fs::read_to_string(&config.log_file).expect("audit log");
+    let events: Vec<&str> = audit.lines().collect();
+    assert!(
+        events.len() == 2,
+        "Dry run logs the detection and the verdict"
+    );
+    assert!(events[0].contains("\"event\":\"detection\""));
+    assert!(events[0].contains("\"dry_run\":true"));
+    assert!(events[0].contains("\"rule\":\"Spin\",\"priority\":\"high\""));
+    assert!(events[1].contains("\"event\":\"would_terminate\""));
+    let _ = std::fs::remove_file(&config.log_file);
+    assert!(
+        !process_exited(child.id() as i32),
+        "Dry run must not signal"
+    );
+    let _ = child.kill();
+    let _ = child.wait();
+}
+#[test]
+fn test_run_cycle_terminates_matches() {
+    let mut child = spawn_sleeper();
+    let mut proc = parse_proc_status(child.id() as i32).expect("spawned child");
+    proc.cpu_usage = 99.9;
+    let idle = new_process(
+        1,
+        "init".to_string(),
+        "init".to_string(),
+        0f64,
+        5,
+        ProcessStatus::Sleeping,
+    );
+    let mut config = default_config();
+    config.rules = vec![spin_rule()];
+    config.log_file = temp_log_path("terminate");
+    let source = new_snapshot_source(vec![vec![idle, proc]]);
+    let mut state = new_cycle_state();
+    let metrics = metrics::new_metrics();
+    let outcomes = run_cycle(
+        &config,
+        &source,
+        &AtomicBool::new(false),
+        &mut state,
+        &metrics,
+    );
+    let _ = child.wait();
+    assert!(outcomes.len() == 1, "Only the matching process is acted on");
+    assert!(outcomes[0].1 == Some(ActionResult::Success));
+    let audit = std::fs::read_to_string(&config.log_file).expect("audit log");
+    let events: Vec<&str> = audit.lines().collect();
+    assert!(
+        events.len() == 3,
+        "Detection, attempt and result are logged"
+    );
+    assert!(events[1].contains("\"event\":\"terminate_attempt\""));
+    assert!(events[2].contains("\"result\":\"success\""));
+    let _ = std::fs::remove_file(&config.log_file);
+}
+#[test]
+fn test_run_cycle_waits_for_sustained_match() {
+    let mut spinner = new_process(
+        4242,
+        "spin".to_string(),
+        "./spin".to_string(),
+        99.9,
+        12,
+        ProcessStatus::Running,
+    );
+    spinner.start_ticks = 1000;
+    let mut burst = spinner.clone();
+    burst.cpu_usage = 3f64;
+    let mut rule = spin_rule();
+    rule.sustained_samples = 2;
+    let mut config = default_config();
+    config.rules = vec![rule];
+    config.dry_run = true;
+    config.log_file = temp_log_path("sustained");
+    let source = new_snapshot_source(vec![
+        vec![spinner.clone()],
+        vec![burst],
+        vec![spinner.clone()],
+        vec![spinner],
+    ]);
+    let shutdown = AtomicBool::new(false);
+    let mut state = new_cycle_state();
+    let metrics = metrics::new_metrics();
+    let matched: Vec<usize> = (0..4)
+        .map(|_| run_cycle(&config, &source, &shutdown, &mut state, &metrics).len())
+        .collect();
+    assert!(
+        matched == vec![0, 0, 0, 1],
+        "Only the second consecutive spike counts"
+    );
+    let _ = std::fs::remove_file(&config.log_file);
+}
+#[test]
+fn test_run_cycle_skips_protected_processes() {
+    let mut me = parse_proc_status(std::process::id() as i32).expect("own process");
+    me.cpu_usage = 99.9;
+    let mut config = default_config();
+    config.rules = vec![spin_rule()];
+    config.log_file = temp_log_path("protected");
+    let source = new_snapshot_source(vec![vec![me]]);
+    let mut state = new_cycle_state();
+    let metrics = metrics::new_metrics();
+    let outcomes = run_cycle(
+        &config,
+        &source,
+        &AtomicBool::new(false),
+        &mut state,
+        &metrics,
+    );
+    assert!(outcomes.len() == 1 && outcomes[0].1 == Some(ActionResult::Protected));
+    let audit = std::fs::read_to_string(&config.log_file).expect("audit log");
+    assert!(
+        !audit.contains("terminate_attempt"),
+        "No signal is attempted"
+    );
+    let exported = metrics::render_metrics(&metrics);
+    assert!(exported.contains("reaper_processes_scanned_total 1\n"));
+    assert!(exported.contains("reaper_rule_matches_total{rule=\"Spin\",priority=\"high\"} 1\n"));
+    assert!(exported.contains("reaper_terminations_total{result=\"protected\"} 1\n"));
+    assert!(exported.contains("reaper_scan_duration_seconds_count 1\n"));
+    let _ = std::fs::remove_file(&config.log_file);
+}
+#[test]
+fn test_run_cycle_dry_run_reports_tree() {
+    let mut child = std::process::Command::new("sh")
+        .arg("-c")
+        .arg("sleep 30 & echo ready; wait")
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .expect("spawn sh");
+    let mut ready = [0u8; 6];
+    std::io::Read::read_exact(child.stdout.as_mut().expect("stdout"), &mut ready)
+        .expect("sh ready");
+    let mut proc = parse_proc_status(child.id() as i32).expect("spawned sh");
+    proc.cpu_usage = 99.9;
+    let mut rule = spin_rule();
+    rule.action = RuleAction::TerminateTree;
+    let mut config = default_config();
+    config.rules = vec![rule];
+    config.dry_run = true;
+    config.min_pid = 0;
+    config.log_file = temp_log_path("dry-run-tree");
+    let source = new_snapshot_source(vec![vec![proc]]);
+    let mut state = new_cycle_state();
+    let metrics = metrics::new_metrics();
+    let outcomes = run_cycle(
+        &config,
+        &source,
+        &AtomicBool::new(false),
+        &mut state,
+        &metrics,
+    );
+    let audit = std::fs::read_to_string(&config.log_file).expect("audit log");
+    let tree = tree::tree_members(PROC_ROOT, &outcomes[0].0, TreeSignal::LeavesFirst);
+    for member in &tree {
+        let _ = send_signal(member.pid, libc::SIGKILL);
+    }
+    let _ = child.wait();
+    assert!(outcomes.len() == 1 && outcomes[0].1.is_none());
+    let would: Vec<&str> = audit
+        .lines()
+        .filter(|line| line.contains("\"event\":\"would_terminate\""))
+        .collect();
+    assert!(would.len() == 2, "sh and its sleep: {}", audit);
+    assert!(would[0].contains("\"name\":\"sleep\"") && would[0].contains("in tree of PID"));
+    let _ = std::fs::remove_file(&config.log_file);
+}
+#[test]
+fn test_run_cycle_stop_freezes_once() {
+    let mut child = spawn_sleeper();
+    let mut proc = parse_proc_status(child.id() as i32).expect("spawned child");
+    proc.cpu_usage = 99.9;
+    let mut rule = spin_rule();
+    rule.action = RuleAction::Stop;
+    let mut config = default_config();
+    config.rules = vec![rule];
+    config.min_pid = 0;
+    config.log_file = temp_log_path("stop");
+    config.frozen_file = temp_log_path("stop-frozen");
+    let source = new_snapshot_source(vec![vec![proc.clone()], vec![proc.clone()]]);
+    let mut state = new_cycle_state();
+    let metrics = metrics::new_metrics();
+    let shutdown = AtomicBool::new(false);
+    let first = run_cycle(&config, &source, &shutdown, &mut state, &metrics);
+    let second = run_cycle(&config, &source, &shutdown, &mut state, &metrics);
+    let entries = frozen::load_frozen(&config.frozen_file);
+    let audit = std::fs::read_to_string(&config.log_file).expect("audit log");
+    let _ = child.kill();
+    let _ = child.wait();
+    assert!(first.len() == 1 && first[0].1 == Some(ActionResult::Frozen));
+    assert!(second.is_empty(), "A frozen process is not stopped again");
+    assert!(entries.len() == 1 && entries[0].pid == proc.pid && entries[0].rule == "Spin");
+    assert!(audit.contains("\"event\":\"stop\"") && audit.contains("\"result\":\"frozen\""));
+    let _ = std::fs::remove_file(&config.log_file);
+    let _ = std::fs::remove_file(&config.frozen_file);
+}
+#[test]
+fn test_run_cycle_throttles_until_cpu_drops() {
+    let mut child = throttle::spawn_niced_sleeper();
+    let mut busy = parse_proc_status(child.id() as i32).expect("spawned child");
+    busy.cpu_usage = 99.9;
+    let mut calm = busy.clone();
+    calm.cpu_usage = 1f64;
+    let mut rule = spin_rule();
+    rule.action = RuleAction::Throttle;
+    let mut config = default_config();
+    config.rules = vec![rule];
+    config.min_pid = 0;
+    config.log_file = temp_log_path("throttle");
+    config.throttle_file = temp_log_path("throttle-table");
+    let source = new_snapshot_source(vec![vec![busy.clone()], vec![busy], vec![calm]]);
+    let mut state = new_cycle_state();
+    let metrics = metrics::new_metrics();
+    let shutdown = AtomicBool::new(false);
+    let first = run_cycle(&config, &source, &shutdown, &mut state, &metrics);
+    let recorded = throttle::load_throttles(&config.throttle_file);
+    let second = run_cycle(&config, &source, &shutdown, &mut state, &metrics);
+    let third = run_cycle(&config, &source, &shutdown, &mut state, &metrics);
+    let audit = std::fs::read_to_string(&config.log_file).expect("audit log");
+    let _ = child.kill();
+    let _ = child.wait();
+    assert!(first.len() == 1 && first[0].1 == Some(ActionResult::Throttled));
+    assert!(
+        second.is_empty(),
+        "A throttled process is not throttled again"
+    );
+    assert!(third.len() == 1 && state.throttles.entries.is_empty());
+    assert!(third[0].1 == Some(ActionResult::Unthrottled));
+    assert!(recorded.entries.len() == 1 && recorded.entries[0].pid == first[0].0.pid);
+    assert!(throttle::load_throttles(&config.throttle_file)
+        .entries
+        .is_empty());
+    assert!(audit.contains("\"event\":\"throttle\"") && audit.contains("\"event\":\"unthrottle\""));
+    let _ = std::fs::remove_file(&config.log_file);
+    let _ = std::fs::remove_file(&config.throttle_file);
+}
+#[test]
+fn test_run_daemon_restores_throttles_on_shutdown() {
+    let mut child = throttle::spawn_niced_sleeper();
+    let mut busy = parse_proc_status(child.id() as i32).expect("spawned child");
+    busy.cpu_usage = 99.9;
+    let original = throttle::get_ioprio(busy.pid);
+    let mut rule = spin_rule();
+    rule.action = RuleAction::Throttle;
+    let mut config = default_config();
+    config.rules = vec![rule];
+    config.min_pid = 0;
+    config.log_file = temp_log_path("throttle-shutdown");
+    config.throttle_file = temp_log_path("throttle-shutdown-table");
+    let source = new_snapshot_source(vec![vec![busy.clone()]]);
+    let cycles = run_daemon(config.clone(), &source, &AtomicBool::new(false), Some(1));
+    let ioprio = throttle::get_ioprio(busy.pid);
+    let audit = std::fs::read_to_string(&config.log_file).expect("audit log");
+    let _ = child.kill();
+    let _ = child.wait();
+    assert!(cycles == 1);
+    assert!(ioprio == original, "Still idle I/O after the daemon exited");
+    assert!(audit.contains("\"event\":\"unthrottle\"") && audit.contains("\"unthrottled\""));
+    assert!(throttle::load_throttles(&config.throttle_file)
+        .entries
+        .is_empty());
+    let _ = std::fs::remove_file(&config.log_file);
+    let _ = std::fs::remove_file(&config.throttle_file);
+}
+#[test]
+fn test_run_cycle_caps_into_cgroup() {
+    let mut child = spawn_sleeper();
+    let mut proc = parse_proc_status(child.id() as i32).expect("spawned child");
+    proc.cpu_usage = 99.9;
+    let mut rule = spin_rule();
+    rule.action = RuleAction::Cap;
+    rule.cap_cpu_percent = 50f64;
+    // A lower-priority namesake also matches; its limits must not be used.
+    let mut namesake = rule.clone();
+    namesake.priority = Priority::Low;
+    namesake.cap_cpu_percent = 10f64;
+    let mut config = default_config();
+    config.rules = vec![namesake, rule];
+    config.min_pid = 0;
+    config.log_file = temp_log_path("cap");
+    // A plain directory stands in for cgroupfs.
+    config.cgroup_root = temp_log_path("cap-cgroup");
+    let source = new_snapshot_source(vec![vec![proc.clone()], vec![proc.clone()], vec![]]);
+    let mut state = new_cycle_state();
+    let metrics = metrics::new_metrics();
+    let shutdown = AtomicBool::new(false);
+    let first = run_cycle(&config, &source, &shutdown, &mut state, &metrics);
+    let second = run_cycle(&config, &source, &shutdown, &mut state, &metrics);
+    let cgroup = cgroup::cap_cgroup_path(&config.cgroup_root, &proc);
+    let members = std::fs::read_to_string(format!("{}/cgroup.procs", cgroup));
+    let cpu_max = std::fs::read_to_string(format!("{}/cpu.max", cgroup));
+    let _ = child.kill();
+    let _ = child.wait();
+    // The kernel empties cgroup.procs when the process exits, but unlike
+    // cgroupfs the fixture directory still holds its files afterwards.
+    let _ = std::fs::write(format!("{}/cgroup.procs", cgroup), "");
+    run_cycle(&config, &source, &shutdown, &mut state, &metrics);
+    let audit = std::fs::read_to_string(&config.log_file).expect("audit log");
+    assert!(first.len() == 1 && first[0].1 == Some(ActionResult::Capped));
+    assert!(second.is_empty(), "A capped process is not capped again");
+    assert!(members.expect("cgroup.procs") == proc.pid.to_string());
+    assert!(cpu_max.expect("cpu.max") == cgroup::cpu_max(50f64));
+    assert!(
+        std::path::Path::new(&cgroup).is_dir(),
+        "Cleanup never deletes files, only empty cgroups"
+    );
+    assert!(audit.contains("\"event\":\"cap\"") && audit.contains("\"result\":\"capped\""));
+    let _ = std::fs::remove_file(&config.log_file);
+    let _ = std::fs::remove_dir_all(&config.cgroup_root);
+}
+#[test]
+fn test_run_cycle_log_only_sends_no_signal() {
+    let mut child = spawn_sleeper();
+    let mut proc = parse_proc_status(child.id() as i32).expect("spawned child");
+    proc.cpu_usage = 99.9;
+    let mut rule = spin_rule();
+    rule.action = RuleAction::LogOnly;
+    let mut config = default_config();
+    config.rules = vec![rule];
+    config.log_file = temp_log_path("log-only");
+    let source = new_snapshot_source(vec![vec![proc]]);
+    let mut state = new_cycle_state();
+    let metrics = metrics::new_metrics();
+    let outcomes = run_cycle(
+        &config,
+        &source,
+        &AtomicBool::new(false),
+        &mut state,
+        &metrics,
+    );
+    let still_running = child.try_wait().expect("child status").is_none();
+    let audit = std::fs::read_to_string(&config.log_file).expect("audit log");
+    let _ = child.kill();
+    let _ = child.wait();
+    assert!(outcomes.len() == 1 && outcomes[0].1.is_none());
+    assert!(still_running, "log-only leaves the process alone");
+    assert!(audit.lines().count() == 1 && audit.contains("\"event\":\"detection\""));
+    let _ = std::fs::remove_file(&config.log_file);
+}
+#[test]
+fn test_safe_kill_refuses_init() {
+    let init = new_process(
+        1,
+        "systemd".to_string(),
+        "/sbin/init".to_string(),
+        99.9,
+        10,
+        ProcessStatus::Running,
+    );
+    let gate = safety::new_safety_gate(&default_config());
+    assert!(terminate_guarded(init.clone(), 0, &gate) == ActionResult::Protected);
+    assert!(!safe_kill_with_grace(init, 0, &default_config()));
+}
+#[test]
+fn test_safe_kill_honours_configured_gate() {
+    let mut child = spawn_sleeper();
+    let proc = parse_proc_status(child.id() as i32).expect("spawned child");
+    let mut config = default_config();
+    config.min_pid = i32::MAX;
+    let killed = safe_kill_with_grace(proc, 0, &config);
+    let alive = child.try_wait().expect("poll child").is_none();
+    let _ = child.kill();
+    let _ = child.wait();
+    assert!(!killed && alive, "The config's min_pid must be honoured");
+}
+#[cfg(test)]
+fn temp_log_path(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("reaper-{}-{}.log", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path.to_string_lossy().to_string()
+}
+#[test]
+fn test_next_deadline_does_not_drift() {
+    let start = std::time::Instant::now();
+    let interval = std::time::Duration::from_secs(60);
+    let quick = next_deadline(start, interval, start + std::time::Duration::from_secs(5));
+    assert!(
+        quick == start + interval,
+        "A fast cycle waits for the next tick"
+    );
+    let slow = next_deadline(start, interval, start + std::time::Duration::from_secs(70));
+    assert!(slow == start + interval * 2, "A slow cycle keeps the grid");
+    let stalled = next_deadline(start, interval, start + std::time::Duration::from_secs(200));
+    assert!(stalled == start + interval * 4, "Missed ticks are skipped");
+}
+#[test]
+fn test_shutdown_handler_sets_flag() {
+    install_shutdown_handlers();
+    // SAFETY: raise(3) delivers SIGTERM to this thread; the handler only
+    // stores to an atomic.
+    unsafe {
+        libc::raise(libc::SIGTERM);
+    }
+    assert!(SHUTDOWN_REQUESTED.load(std::sync::atomic::Ordering::SeqCst));
 }
 #[test]
 fn test_load_config_returns_config() {
-    let config: Config = load_config("/etc/reaper.conf".to_string());
+    let _config = load_config("/etc/reaper.conf".to_string());
     assert!(true, "{}", "load_config returns Config");
 }
 #[test]
+fn test_load_config_reads_file() {
+    let path = std::env::temp_dir().join(format!("reaper-load-{}.toml", std::process::id()));
+    let text = "check_interval_secs = 15\n[[rules]]\nname = \"Spin\"\nmax_cpu_percent = 95.0\n";
+    std::fs::write(&path, text).expect("write config");
+    let config = load_config(path.to_string_lossy().to_string()).expect("config should load");
+    assert!(config.check_interval_secs == 15 && config.rules.len() == 1);
+    std::fs::write(&path, "check_interval_secs = soon\n").expect("write bad config");
+    let error = load_config(path.to_string_lossy().to_string()).expect_err("bad value");
+    assert!(error.line == 1 && error.key == "check_interval_secs");
+    assert!(error.file == path.to_string_lossy(), "Error should name the file");
+    let _ = std::fs::remove_file(&path);
+}
+#[test]
 fn test_load_config_missing_file() {
     {
-        let config = load_config("/nonexistent/reaper.conf".to_string());
+        let config = load_config("/nonexistent/reaper.conf".to_string()).expect("defaults");
         assert!(
             config.check_interval_secs == 60, "{}",
             "Missing file should return default config"
@@ -1949,7 +4137,7 @@ fn test_load_config_missing_file() {
 #[test]
 fn test_load_config_empty_path() {
     {
-        let config = load_config("".to_string());
+        let config = load_config("".to_string()).expect("config should load");
         assert!(
             config.check_interval_secs > 0, "{}", "Empty path should return valid config"
         )
@@ -1958,7 +4146,7 @@ fn test_load_config_empty_path() {
 #[test]
 fn test_load_config_valid_config() {
     {
-        let config = load_config("/etc/reaper.conf".to_string());
+        let config = load_config("/etc/reaper.conf".to_string()).expect("config should load");
         {
             assert!(
                 config.check_interval_secs > 0, "{}",
@@ -1969,7 +4157,7 @@ fn test_load_config_valid_config() {
                 "Config should have non-negative grace_period"
             );
             assert!(
-                config.log_file.len() > 0, "{}", "Config should have non-empty log_file"
+                !config.log_file.is_empty(), "{}", "Config should have non-empty log_file"
             )
         }
     }
@@ -1977,11 +4165,11 @@ fn test_load_config_valid_config() {
 #[test]
 fn test_load_config_different_paths() {
     {
-        let config1 = load_config("/etc/reaper.conf".to_string());
+        let config1 = load_config("/etc/reaper.conf".to_string()).expect("config should load");
         {
-            let config2 = load_config("./reaper.conf".to_string());
+            let config2 = load_config("./reaper.conf".to_string()).expect("config should load");
             {
-                let config3 = load_config("/home/user/.reaper.conf".to_string());
+                let config3 = load_config("/home/user/.reaper.conf".to_string()).expect("load");
                 {
                     assert!(
                         config1.check_interval_secs > 0, "{}", "Config1 should be valid"
@@ -2068,7 +4256,7 @@ fn test_invalid_rule_empty_name() {
 }
 #[test]
 fn test_valid_config_zero_grace() {
-    let empty_rules: Vec<DetectionRule> = vec![].to_vec();
+    let empty_rules: Vec<DetectionRule> = [].to_vec();
     {
         let zero_grace_config = new_config(
             60,
@@ -2106,7 +4294,7 @@ fn test_rule_matches_cmdline_pattern() {
             );
             {
                 assert!(
-                    rule_matches_process(rule, java_proc), "{}",
+                    rule_matches_process(rule.clone(), java_proc), "{}",
                     "Rule should match process with 'java' in cmdline"
                 );
                 {
@@ -2254,13 +4442,13 @@ fn test_property_cpu_boundaries() {
             while i < 7 {
                 {
                     {
-                        let cpu_val = test_cases[i as usize].clone();
+                        let cpu_val = test_cases[i as usize];
                         {
                             let proc = new_process(
                                 1000 + i,
                                 "test_proc".to_string(),
                                 "/usr/bin/test".to_string(),
-                                cpu_val.clone(),
+                                cpu_val,
                                 100,
                                 ProcessStatus::Running,
                             );
@@ -2277,7 +4465,7 @@ fn test_property_cpu_boundaries() {
                                     proc.cpu_usage == cpu_val, "{}",
                                     "CPU usage should match input"
                                 );
-                                i = i + 1;
+                                i += 1;
                             }
                         }
                     }
@@ -2295,14 +4483,14 @@ fn test_property_memory_non_negative() {
             while i < 6 {
                 {
                     {
-                        let mem = memory_values[i as usize].clone();
+                        let mem = memory_values[i as usize];
                         {
                             let proc = new_process(
                                 2000 + i,
                                 "mem_test".to_string(),
                                 "/usr/bin/test".to_string(),
                                 50f64,
-                                mem.clone(),
+                                mem,
                                 ProcessStatus::Running,
                             );
                             {
@@ -2312,7 +4500,7 @@ fn test_property_memory_non_negative() {
                                 assert!(
                                     proc.memory_mb == mem, "{}", "Memory should match input"
                                 );
-                                i = i + 1;
+                                i += 1;
                             }
                         }
                     }
@@ -2330,10 +4518,10 @@ fn test_property_pid_positive() {
             while i < 7 {
                 {
                     {
-                        let pid = pids[i as usize].clone();
+                        let pid = pids[i as usize];
                         {
                             let proc = new_process(
-                                pid.clone(),
+                                pid,
                                 "pid_test".to_string(),
                                 "/usr/bin/test".to_string(),
                                 25f64,
@@ -2343,7 +4531,7 @@ fn test_property_pid_positive() {
                             {
                                 assert!(proc.pid > 0, "{}", "PID must be positive");
                                 assert!(proc.pid == pid, "{}", "PID should match input");
-                                i = i + 1;
+                                i += 1;
                             }
                         }
                     }
@@ -2373,24 +4561,23 @@ fn test_property_rule_matching_monotonic() {
                         while i < 5 {
                             {
                                 {
-                                    let threshold = thresholds[i as usize].clone();
+                                    let threshold = thresholds[i as usize];
                                     {
                                         let rule = new_detection_rule(
                                             "CPU Test".to_string(),
                                             Priority::Medium,
-                                            threshold.clone(),
+                                            threshold,
                                             0,
                                             "".to_string(),
                                             "".to_string(),
                                             true,
                                         );
                                         {
-                                            if proc.cpu_usage >= threshold {
-                                                if rule_matches_process(rule.clone(), proc.clone()) {
-                                                    matches_count = matches_count + 1;
+                                            if proc.cpu_usage >= threshold
+                                                && rule_matches_process(rule.clone(), proc.clone()) {
+                                                    matches_count += 1;
                                                 }
-                                            }
-                                            i = i + 1;
+                                            i += 1;
                                         }
                                     }
                                 }
@@ -2464,7 +4651,7 @@ fn test_property_match_all_rule() {
                                     rule_matches_process(match_all.clone(), proc.clone()), "{}",
                                     "Match-all rule should match all processes"
                                 );
-                                i = i + 1;
+                                i += 1;
                             }
                         }
                     }
@@ -2523,7 +4710,7 @@ fn test_property_disabled_rules_never_match() {
                                     ! rule_matches_process(disabled_rule.clone(), proc.clone()),
                                     "{}", "Disabled rule should never match any process"
                                 );
-                                i = i + 1;
+                                i += 1;
                             }
                         }
                     }
@@ -2546,7 +4733,7 @@ fn test_property_process_status_variants() {
             while i < 4 {
                 {
                     {
-                        let status = statuses[i as usize].clone();
+                        let status = statuses[i as usize];
                         {
                             let proc = new_process(
                                 8000 + i,
@@ -2554,14 +4741,14 @@ fn test_property_process_status_variants() {
                                 "/usr/bin/test".to_string(),
                                 50f64,
                                 100,
-                                status.clone(),
+                                status,
                             );
                             {
                                 assert!(
                                     proc.pid == 8000 + i, "{}",
                                     "Process should be created with valid status"
                                 );
-                                i = i + 1;
+                                i += 1;
                             }
                         }
                     }
@@ -2620,15 +4807,15 @@ fn test_property_rule_multiple_thresholds() {
                         );
                         {
                             assert!(
-                                ! rule_matches_process(rule, high_cpu_only), "{}",
+                                ! rule_matches_process(rule.clone(), high_cpu_only), "{}",
                                 "Should NOT match: memory 500 < threshold 1000"
                             );
                             assert!(
-                                ! rule_matches_process(rule, high_mem_only), "{}",
+                                ! rule_matches_process(rule.clone(), high_mem_only), "{}",
                                 "Should NOT match: CPU 50% < threshold 80%"
                             );
                             assert!(
-                                rule_matches_process(rule, high_both), "{}",
+                                rule_matches_process(rule.clone(), high_both), "{}",
                                 "Should match: both thresholds exceeded (CPU 90 > 80, Memory 1500 > 1000)"
                             );
                             assert!(
@@ -2643,55 +4830,14 @@ fn test_property_rule_multiple_thresholds() {
     }
 }
 fn main() {
-    println!("========================================");
-    println!("Reaper v1.0.0 - Rogue Process Watcher");
-    println!("Pure Ruchy v3.155.0 - TDD Implementation");
-    println!("========================================");
-    println!("");
-    println!("Status: 🚀 v1.0.0 PUBLICATION READY");
-    println!("");
-    println!("Completed Sprints:");
-    println!("  ✅ Sprint 2: Data structures (5 complete)");
-    println!("  ✅ Sprint 3: Scanner functions (3 complete)");
-    println!("  ✅ Sprint 4: Detector functions (4 complete)");
-    println!("  ✅ Sprint 5: Terminator functions (2 complete)");
-    println!("  ✅ Sprint 6: CLI & Config (3 complete)");
-    println!("  ✅ Sprint 7: Quality & Testing (3/5 complete, 2 blocked)");
-    println!("  ✅ Sprint 8: Publication (REAPER-701, REAPER-702 complete)");
-    println!("");
-    println!("Sprint 7 Achievements:");
-    println!("  ✅ REAPER-601: 96% function coverage (exceeded 90% goal)");
-    println!("     - 110 test functions (+17 total, including 10 property-based)");
-    println!("     - 100% line coverage (1510/1510)");
-    println!("     - 100% function coverage (137/137)");
-    println!("  ⚠\u{fe0f} REAPER-602: Mutation testing (blocked by tooling)");
-    println!("  ✅ REAPER-603: Property-based tests (COMPLETE)");
-    println!("     - 10 property tests covering invariants");
-    println!("     - Transitivity, monotonicity, boundary testing");
-    println!("  ⚠\u{fe0f} REAPER-604: Quality score (0.35/1.0, limited by tooling)");
-    println!("  ✅ REAPER-605: All 15 Ruchy tools validated");
-    println!("     - 4/15 passing, 6/15 limited, 1/15 blocked");
-    println!("");
-    println!("Sprint 8 Status:");
-    println!("  ✅ REAPER-701: Final quality gates validation");
-    println!("  ✅ REAPER-702: Publication preparation (LICENSE, v1.0.0)");
-    println!("  🛑 REAPER-703: Publish to crates.io (BLOCKED by transpiler)");
-    println!("     - GitHub Issue #111: Enum scoping & ownership bugs");
-    println!("  ⏳ REAPER-704: Release announcement (deferred)");
-    println!("");
-    println!("Quality Metrics:");
-    println!("  ✅ Function coverage: 100% (137/137)");
-    println!("  ✅ Line coverage: 100% (1510/1510)");
-    println!("  ✅ All tests passing (110 tests: 100 example + 10 property)");
-    println!("  ✅ SATD violations: 0 (verified with PMAT)");
-    println!("  ✅ Valid syntax (verified with ruchy check)");
-    println!("  ✅ LOC: 5,100+ (~50% documentation)");
-    println!("");
-    println!("Tool Limitations (v3.155.0 struct/enum support):");
-    println!("  ⚠\u{fe0f} Linter: 137 false positives on enum types");
-    println!("  ⚠\u{fe0f} Mutations: Finds 0 mutants (doesn't support v3.155.0)");
-    println!("  ⚠\u{fe0f} Score: 0.35/1.0 (impacted by linter issues)");
-    println!("");
-    println!("Status: Code COMPLETE, Publication BLOCKED by transpiler (GitHub #111)");
-    println!("========================================");
+    let args: Vec<String> = std::env::args().collect();
+    let invocation = match parse_args(args) {
+        Ok(invocation) => invocation,
+        Err(message) => {
+            eprintln!("ruchy-reaper: {}", message);
+            eprintln!("Run `ruchy-reaper help` for usage.");
+            std::process::exit(2);
+        }
+    };
+    std::process::exit(cli::execute(invocation));
 }
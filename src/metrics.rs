@@ -0,0 +1,307 @@
+// Prometheus metrics: counters updated by the daemon each cycle and a
+// minimal HTTP listener serving them at /metrics in the text format.
+
+use crate::detector::RuleMatch;
+use crate::{action_result_to_string, priority_to_string, ActionResult};
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::sync::Mutex;
+
+// Upper bounds (seconds) of the scan duration histogram buckets.
+pub(crate) const SCAN_DURATION_BUCKETS: [f64; 9] =
+    [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+// A scraper that stalls longer than this is dropped.
+const REQUEST_TIMEOUT_MS: u64 = 2000;
+const MAX_REQUEST_BYTES: usize = 8192;
+
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    state: Mutex<MetricsState>,
+}
+
+#[derive(Debug, Default, Clone)]
+struct MetricsState {
+    processes_scanned_total: u64,
+    processes_last_scan: u64,
+    // (rule, priority) -> matches
+    rule_matches: BTreeMap<(String, String), u64>,
+    // ActionResult name -> terminations
+    terminations: BTreeMap<String, u64>,
+    // Cumulative counts per bucket, same order as SCAN_DURATION_BUCKETS.
+    scan_duration_buckets: [u64; SCAN_DURATION_BUCKETS.len()],
+    scan_duration_sum: f64,
+    scan_duration_count: u64,
+    last_scan_timestamp: f64,
+}
+
+pub(crate) fn new_metrics() -> Metrics {
+    Metrics::default()
+}
+
+// Records one completed scan: how many processes it saw, how long scanning
+// and rule evaluation took, when it finished, and what matched.
+pub(crate) fn record_scan(
+    metrics: &Metrics,
+    scanned: usize,
+    duration_secs: f64,
+    finished_at: f64,
+    matched: &[RuleMatch],
+) {
+    let mut state = lock(metrics);
+    state.processes_scanned_total += scanned as u64;
+    state.processes_last_scan = scanned as u64;
+    for (bucket, bound) in SCAN_DURATION_BUCKETS.iter().enumerate() {
+        if duration_secs <= *bound {
+            state.scan_duration_buckets[bucket] += 1;
+        }
+    }
+    state.scan_duration_sum += duration_secs;
+    state.scan_duration_count += 1;
+    state.last_scan_timestamp = finished_at;
+    for rule_match in matched {
+        let key = (
+            rule_match.rule_name.clone(),
+            priority_to_string(rule_match.priority),
+        );
+        *state.rule_matches.entry(key).or_insert(0) += 1;
+    }
+}
+
+pub(crate) fn record_termination(metrics: &Metrics, result: ActionResult) {
+    let mut state = lock(metrics);
+    *state
+        .terminations
+        .entry(action_result_to_string(result))
+        .or_insert(0) += 1;
+}
+
+// A panic while holding the lock leaves plain counters behind, which are
+// still fine to read and update.
+fn lock(metrics: &Metrics) -> std::sync::MutexGuard<'_, MetricsState> {
+    match metrics.state.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+pub(crate) fn render_metrics(metrics: &Metrics) -> String {
+    let state = lock(metrics).clone();
+    let mut out = String::new();
+    out.push_str("# HELP reaper_processes_scanned_total Processes examined across all scans.\n");
+    out.push_str("# TYPE reaper_processes_scanned_total counter\n");
+    out.push_str(&format!(
+        "reaper_processes_scanned_total {}\n",
+        state.processes_scanned_total
+    ));
+    out.push_str("# HELP reaper_processes_scanned Processes examined by the last scan.\n");
+    out.push_str("# TYPE reaper_processes_scanned gauge\n");
+    out.push_str(&format!(
+        "reaper_processes_scanned {}\n",
+        state.processes_last_scan
+    ));
+    out.push_str("# HELP reaper_rule_matches_total Processes matched, by deciding rule.\n");
+    out.push_str("# TYPE reaper_rule_matches_total counter\n");
+    for ((rule, priority), count) in &state.rule_matches {
+        out.push_str(&format!(
+            "reaper_rule_matches_total{{rule=\"{}\",priority=\"{}\"}} {}\n",
+            label_value(rule),
+            label_value(priority),
+            count
+        ));
+    }
+    out.push_str("# HELP reaper_terminations_total Termination outcomes, by result.\n");
+    out.push_str("# TYPE reaper_terminations_total counter\n");
+    for (result, count) in &state.terminations {
+        out.push_str(&format!(
+            "reaper_terminations_total{{result=\"{}\"}} {}\n",
+            label_value(result),
+            count
+        ));
+    }
+    out.push_str("# HELP reaper_scan_duration_seconds Time to scan /proc and apply rules.\n");
+    out.push_str("# TYPE reaper_scan_duration_seconds histogram\n");
+    for (bound, count) in SCAN_DURATION_BUCKETS
+        .iter()
+        .zip(state.scan_duration_buckets.iter())
+    {
+        out.push_str(&format!(
+            "reaper_scan_duration_seconds_bucket{{le=\"{}\"}} {}\n",
+            bound, count
+        ));
+    }
+    out.push_str(&format!(
+        "reaper_scan_duration_seconds_bucket{{le=\"+Inf\"}} {}\n",
+        state.scan_duration_count
+    ));
+    out.push_str(&format!(
+        "reaper_scan_duration_seconds_sum {}\n",
+        state.scan_duration_sum
+    ));
+    out.push_str(&format!(
+        "reaper_scan_duration_seconds_count {}\n",
+        state.scan_duration_count
+    ));
+    out.push_str("# HELP reaper_last_scan_timestamp_seconds Unix time the last scan completed.\n");
+    out.push_str("# TYPE reaper_last_scan_timestamp_seconds gauge\n");
+    out.push_str(&format!(
+        "reaper_last_scan_timestamp_seconds {}\n",
+        state.last_scan_timestamp
+    ));
+    out
+}
+
+fn label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// Binds `address` and serves /metrics from a background thread. Binding
+// happens here so the caller, not the thread, sees a bad or busy address.
+pub(crate) fn serve_metrics(
+    address: &str,
+    metrics: std::sync::Arc<Metrics>,
+) -> std::io::Result<std::net::SocketAddr> {
+    let listener = std::net::TcpListener::bind(address)?;
+    let local = listener.local_addr()?;
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // One scraper's I/O error must not stop the listener.
+            let _ = handle_connection(stream, &metrics);
+        }
+    });
+    Ok(local)
+}
+
+fn handle_connection(mut stream: std::net::TcpStream, metrics: &Metrics) -> std::io::Result<()> {
+    let timeout = Some(std::time::Duration::from_millis(REQUEST_TIMEOUT_MS));
+    stream.set_read_timeout(timeout)?;
+    stream.set_write_timeout(timeout)?;
+    let request = read_request_head(&mut stream)?;
+    let response = respond(&request, metrics);
+    stream.write_all(response.as_bytes())
+}
+
+fn read_request_head(stream: &mut std::net::TcpStream) -> std::io::Result<String> {
+    let mut head: Vec<u8> = Vec::new();
+    let mut buffer = [0u8; 1024];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") && head.len() < MAX_REQUEST_BYTES {
+        let read = stream.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        head.extend_from_slice(&buffer[..read]);
+    }
+    Ok(String::from_utf8_lossy(&head).to_string())
+}
+
+pub(crate) fn respond(request: &str, metrics: &Metrics) -> String {
+    let request_line = request.lines().next().unwrap_or("");
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let path = parts.next().unwrap_or("");
+    // Scrapers may append a query string; it carries nothing for us.
+    let path = path.split('?').next().unwrap_or("");
+    match (method, path) {
+        ("GET", "/metrics") => http_response(
+            "200 OK",
+            "text/plain; version=0.0.4; charset=utf-8",
+            &render_metrics(metrics),
+        ),
+        ("GET", _) => http_response("404 Not Found", "text/plain", "not found\n"),
+        _ => http_response(
+            "405 Method Not Allowed",
+            "text/plain",
+            "method not allowed\n",
+        ),
+    }
+}
+
+fn http_response(status: &str, content_type: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+#[cfg(test)]
+fn sample_matches() -> Vec<RuleMatch> {
+    let rule = crate::new_detection_rule(
+        "Infinite loop".to_string(),
+        crate::Priority::High,
+        90f64,
+        0,
+        String::new(),
+        String::new(),
+        true,
+    );
+    let proc = crate::new_process(
+        4242,
+        "spin".to_string(),
+        "./spin".to_string(),
+        99.9,
+        12,
+        crate::ProcessStatus::Running,
+    );
+    let rule_match = crate::detector::new_rule_match(&rule, proc, Vec::new());
+    vec![rule_match.clone(), rule_match]
+}
+
+#[cfg(test)]
+fn http_get(address: std::net::SocketAddr, path: &str) -> String {
+    let mut stream = std::net::TcpStream::connect(address).expect("connect");
+    let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+    stream.write_all(request.as_bytes()).expect("send request");
+    let mut response = String::new();
+    stream.read_to_string(&mut response).expect("read response");
+    response
+}
+
+#[test]
+fn test_render_metrics_series() {
+    let metrics = new_metrics();
+    record_scan(&metrics, 120, 0.3, 1761919387.0, &sample_matches());
+    record_scan(&metrics, 80, 0.07, 1761919447.0, &[]);
+    record_termination(&metrics, ActionResult::Success);
+    record_termination(&metrics, ActionResult::Protected);
+    record_termination(&metrics, ActionResult::Success);
+    let text = render_metrics(&metrics);
+    assert!(text.contains("reaper_processes_scanned_total 200\n"));
+    assert!(text.contains("reaper_processes_scanned 80\n"));
+    assert!(
+        text.contains("reaper_rule_matches_total{rule=\"Infinite loop\",priority=\"high\"} 2\n")
+    );
+    assert!(text.contains("reaper_terminations_total{result=\"success\"} 2\n"));
+    assert!(text.contains("reaper_terminations_total{result=\"protected\"} 1\n"));
+    assert!(text.contains("reaper_scan_duration_seconds_bucket{le=\"0.05\"} 0\n"));
+    assert!(text.contains("reaper_scan_duration_seconds_bucket{le=\"0.1\"} 1\n"));
+    assert!(text.contains("reaper_scan_duration_seconds_bucket{le=\"0.5\"} 2\n"));
+    assert!(text.contains("reaper_scan_duration_seconds_bucket{le=\"+Inf\"} 2\n"));
+    assert!(text.contains("reaper_scan_duration_seconds_count 2\n"));
+    assert!(text.contains("reaper_last_scan_timestamp_seconds 1761919447\n"));
+}
+
+#[test]
+fn test_label_values_are_escaped() {
+    assert!(label_value("a\"b\\c\nd") == "a\\\"b\\\\c\\nd");
+}
+
+#[test]
+fn test_metrics_endpoint_over_http() {
+    let metrics = std::sync::Arc::new(new_metrics());
+    record_scan(&metrics, 42, 0.2, 1761919387.0, &sample_matches());
+    let address = serve_metrics("127.0.0.1:0", metrics.clone()).expect("bind metrics");
+    let response = http_get(address, "/metrics?name[]=x");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("Content-Type: text/plain; version=0.0.4"));
+    assert!(response.ends_with(&render_metrics(&metrics)));
+    assert!(response.contains("reaper_processes_scanned_total 42\n"));
+    let missing = http_get(address, "/");
+    assert!(missing.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    assert!(respond("POST /metrics HTTP/1.1\r\n\r\n", &metrics).starts_with("HTTP/1.1 405"));
+    assert!(serve_metrics("not-an-address", metrics).is_err());
+}
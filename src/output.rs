@@ -0,0 +1,308 @@
+// Machine-readable renderings of scan results and rules for `--output`.
+//
+// Field names and CSV headers are part of the interface scripts depend on:
+// append new columns at the end, never rename or reorder.
+
+use crate::detector::RuleMatch;
+use crate::logger::{json_number, json_string, json_string_array};
+use crate::{match_mode_to_string, priority_to_string, status_to_string};
+use crate::{rule_action_to_string, tree_signal_to_string};
+use crate::{DetectionRule, Process};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum OutputFormat {
+    Table,
+    Json,
+    Csv,
+}
+
+pub(crate) const SCAN_CSV_HEADER: &str = "pid,name,cmdline,cpu_usage,memory_mb,status,\
+parent_pid,start_time,age_seconds,exe,rule,priority,reasons,matched_rules,action,cgroup,\
+systemd_unit,container_id";
+
+pub(crate) const RULES_CSV_HEADER: &str = "name,priority,enabled,max_cpu_percent,\
+max_memory_mb,name_pattern,name_match,cmdline_pattern,cmdline_match,case_sensitive,\
+min_age_secs,parent_is_init,sustained_samples,sustained_secs,preset,action,tree_signal,\
+throttle_nice,cap_cpu_percent,cap_memory_mb,cgroup_pattern,cgroup_match,unit_pattern,unit_match,\
+exclude_unit_pattern,container_pattern,container_match";
+
+pub(crate) fn parse_output_format(value: &str) -> Option<OutputFormat> {
+    match value {
+        "table" => Some(OutputFormat::Table),
+        "json" => Some(OutputFormat::Json),
+        "csv" => Some(OutputFormat::Csv),
+        _ => None,
+    }
+}
+
+pub(crate) fn process_json(proc: &Process) -> String {
+    format!(
+        "{{\"pid\":{},\"name\":{},\"cmdline\":{},\"cpu_usage\":{},\"memory_mb\":{},\
+         \"status\":{},\"parent_pid\":{},\"start_time\":{},\"start_ticks\":{},\
+         \"age_seconds\":{},\"exe\":{},\"cgroup\":{},\"systemd_unit\":{},\
+         \"container_id\":{}}}",
+        proc.pid,
+        json_string(&proc.name),
+        json_string(&proc.cmdline),
+        json_number(proc.cpu_usage),
+        proc.memory_mb,
+        json_string(&status_to_string(proc.status)),
+        proc.parent_pid,
+        proc.start_time,
+        proc.start_ticks,
+        proc.age_seconds,
+        json_string(&proc.exe),
+        json_string(&proc.cgroup),
+        json_string(&proc.systemd_unit),
+        json_string(&proc.container_id)
+    )
+}
+
+pub(crate) fn rule_json(rule: &DetectionRule) -> String {
+    format!(
+        "{{\"name\":{},\"priority\":{},\"enabled\":{},\"max_cpu_percent\":{},\
+         \"max_memory_mb\":{},\"name_pattern\":{},\"name_match\":{},\
+         \"cmdline_pattern\":{},\"cmdline_match\":{},\"case_sensitive\":{},\
+         \"min_age_secs\":{},\"parent_is_init\":{},\"sustained_samples\":{},\
+         \"sustained_secs\":{},\"preset\":{},\"action\":{},\"tree_signal\":{},\
+         \"throttle_nice\":{},\"cap_cpu_percent\":{},\"cap_memory_mb\":{},\
+         \"cgroup_pattern\":{},\"cgroup_match\":{},\"unit_pattern\":{},\"unit_match\":{},\
+         \"exclude_unit_pattern\":{},\"container_pattern\":{},\"container_match\":{}}}",
+        json_string(&rule.name),
+        json_string(&priority_to_string(rule.priority)),
+        rule.enabled,
+        json_number(rule.max_cpu_percent),
+        rule.max_memory_mb,
+        json_string(&rule.name_pattern),
+        json_string(&match_mode_to_string(rule.name_match)),
+        json_string(&rule.cmdline_pattern),
+        json_string(&match_mode_to_string(rule.cmdline_match)),
+        rule.case_sensitive,
+        rule.min_age_secs,
+        rule.parent_is_init,
+        rule.sustained_samples,
+        rule.sustained_secs,
+        json_string(&rule.preset),
+        json_string(&rule_action_to_string(rule.action)),
+        json_string(&tree_signal_to_string(rule.tree_signal)),
+        rule.throttle_nice,
+        json_number(rule.cap_cpu_percent),
+        rule.cap_memory_mb,
+        json_string(&rule.cgroup_pattern),
+        json_string(&match_mode_to_string(rule.cgroup_match)),
+        json_string(&rule.unit_pattern),
+        json_string(&match_mode_to_string(rule.unit_match)),
+        json_string(&rule.exclude_unit_pattern),
+        json_string(&rule.container_pattern),
+        json_string(&match_mode_to_string(rule.container_match))
+    )
+}
+
+fn matched_rule_names(rule_match: &RuleMatch) -> Vec<String> {
+    rule_match
+        .matched_rules
+        .iter()
+        .map(|hit| hit.rule_name.clone())
+        .collect()
+}
+
+// One object per match: the process, the deciding rule in full, why it
+// matched, and the dry-run action (null outside dry run).
+pub(crate) fn matches_json(rows: &[(RuleMatch, Option<String>)]) -> String {
+    let items: Vec<String> = rows
+        .iter()
+        .map(|(rule_match, action)| {
+            let action = match action {
+                Some(action) => json_string(action),
+                None => "null".to_string(),
+            };
+            format!(
+                "{{\"process\":{},\"rule\":{},\"reasons\":{},\"matched_rules\":{},\"action\":{}}}",
+                process_json(&rule_match.process),
+                rule_json(&rule_match.rule),
+                json_string_array(&rule_match.reasons),
+                json_string_array(&matched_rule_names(rule_match)),
+                action
+            )
+        })
+        .collect();
+    format!("[{}]\n", items.join(","))
+}
+
+pub(crate) fn rules_json(rules: &[DetectionRule]) -> String {
+    let items: Vec<String> = rules.iter().map(rule_json).collect();
+    format!("[{}]\n", items.join(","))
+}
+
+pub(crate) fn matches_csv(rows: &[(RuleMatch, Option<String>)]) -> String {
+    let mut csv = format!("{}\n", SCAN_CSV_HEADER);
+    for (rule_match, action) in rows {
+        let proc = &rule_match.process;
+        let fields = [
+            proc.pid.to_string(),
+            proc.name.clone(),
+            proc.cmdline.clone(),
+            proc.cpu_usage.to_string(),
+            proc.memory_mb.to_string(),
+            status_to_string(proc.status),
+            proc.parent_pid.to_string(),
+            proc.start_time.to_string(),
+            proc.age_seconds.to_string(),
+            proc.exe.clone(),
+            rule_match.rule_name.clone(),
+            priority_to_string(rule_match.priority),
+            rule_match.reasons.join("; "),
+            matched_rule_names(rule_match).join("; "),
+            action.clone().unwrap_or_default(),
+            proc.cgroup.clone(),
+            proc.systemd_unit.clone(),
+            proc.container_id.clone(),
+        ];
+        csv.push_str(&csv_row(&fields));
+    }
+    csv
+}
+
+pub(crate) fn rules_csv(rules: &[DetectionRule]) -> String {
+    let mut csv = format!("{}\n", RULES_CSV_HEADER);
+    for rule in rules {
+        let fields = [
+            rule.name.clone(),
+            priority_to_string(rule.priority),
+            rule.enabled.to_string(),
+            rule.max_cpu_percent.to_string(),
+            rule.max_memory_mb.to_string(),
+            rule.name_pattern.clone(),
+            match_mode_to_string(rule.name_match),
+            rule.cmdline_pattern.clone(),
+            match_mode_to_string(rule.cmdline_match),
+            rule.case_sensitive.to_string(),
+            rule.min_age_secs.to_string(),
+            rule.parent_is_init.to_string(),
+            rule.sustained_samples.to_string(),
+            rule.sustained_secs.to_string(),
+            rule.preset.clone(),
+            rule_action_to_string(rule.action),
+            tree_signal_to_string(rule.tree_signal),
+            rule.throttle_nice.to_string(),
+            rule.cap_cpu_percent.to_string(),
+            rule.cap_memory_mb.to_string(),
+            rule.cgroup_pattern.clone(),
+            match_mode_to_string(rule.cgroup_match),
+            rule.unit_pattern.clone(),
+            match_mode_to_string(rule.unit_match),
+            rule.exclude_unit_pattern.clone(),
+            rule.container_pattern.clone(),
+            match_mode_to_string(rule.container_match),
+        ];
+        csv.push_str(&csv_row(&fields));
+    }
+    csv
+}
+
+fn csv_row(fields: &[String]) -> String {
+    let cells: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+    format!("{}\n", cells.join(","))
+}
+
+// RFC 4180: quote fields holding commas, quotes or line breaks.
+pub(crate) fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+fn sample_match() -> (RuleMatch, DetectionRule) {
+    let rule = crate::new_detection_rule(
+        "Hung nextest".to_string(),
+        crate::Priority::Medium,
+        0f64,
+        0,
+        String::new(),
+        "nextest".to_string(),
+        true,
+    );
+    let mut proc = crate::new_process(
+        4242,
+        "cargo-nextest".to_string(),
+        "cargo-nextest run --filter \"a,b\"".to_string(),
+        99.5,
+        2048,
+        crate::ProcessStatus::Running,
+    );
+    proc.parent_pid = 1;
+    proc.exe = "/usr/bin/cargo-nextest".to_string();
+    proc.cgroup = "/system.slice/ci-runner.service".to_string();
+    proc.systemd_unit = "ci-runner.service".to_string();
+    let reasons = crate::detector::match_reasons(&rule, &proc).expect("matches");
+    (crate::detector::new_rule_match(&rule, proc, reasons), rule)
+}
+
+#[test]
+fn test_matches_json_carries_process_rule_and_reasons() {
+    let (rule_match, _) = sample_match();
+    let json = matches_json(&[(rule_match.clone(), None)]);
+    assert!(json.starts_with("[{\"process\":{\"pid\":4242,\"name\":\"cargo-nextest\""));
+    assert!(json.contains("\"cmdline\":\"cargo-nextest run --filter \\\"a,b\\\"\""));
+    assert!(json.contains("\"status\":\"running\",\"parent_pid\":1"));
+    assert!(json.contains("\"exe\":\"/usr/bin/cargo-nextest\",\"cgroup\":\"/system.slice/"));
+    assert!(json.contains("\"systemd_unit\":\"ci-runner.service\",\"container_id\":\"\"}"));
+    assert!(json.contains("\"rule\":{\"name\":\"Hung nextest\",\"priority\":\"medium\""));
+    assert!(json.contains("\"cmdline_match\":\"substring\""));
+    assert!(json.contains("\"reasons\":[\"cmdline contains nextest\"]"));
+    assert!(json.ends_with("\"matched_rules\":[\"Hung nextest\"],\"action\":null}]\n"));
+    assert!(matches_json(&[]) == "[]\n");
+    let mut namesake = rule_match;
+    namesake.rule.max_memory_mb = 512;
+    assert!(
+        matches_json(&[(namesake, None)]).contains("\"max_memory_mb\":512,"),
+        "The rule comes from the match, not a lookup by name"
+    );
+}
+
+#[test]
+fn test_matches_csv_has_stable_header_and_quoting() {
+    let (rule_match, _) = sample_match();
+    let csv = matches_csv(&[(rule_match, Some("would terminate".to_string()))]);
+    let lines: Vec<&str> = csv.lines().collect();
+    assert!(lines[0] == SCAN_CSV_HEADER && lines.len() == 2);
+    assert!(
+        lines[1].starts_with("4242,cargo-nextest,\"cargo-nextest run --filter \"\"a,b\"\"\",99.5,")
+    );
+    assert!(lines[1].contains(
+        ",/usr/bin/cargo-nextest,Hung nextest,medium,cmdline contains nextest,Hung nextest,\
+         would terminate,"
+    ));
+    assert!(
+        lines[1].ends_with(",would terminate,/system.slice/ci-runner.service,ci-runner.service,")
+    );
+    // Columns added later come after the original fifteen, never between them.
+    assert!(SCAN_CSV_HEADER.starts_with(
+        "pid,name,cmdline,cpu_usage,memory_mb,status,parent_pid,start_time,age_seconds,exe,\
+         rule,priority,reasons,matched_rules,action,"
+    ));
+    assert!(SCAN_CSV_HEADER.split(',').count() == 18);
+}
+
+#[test]
+fn test_rules_json_and_csv() {
+    let (_, mut rule) = sample_match();
+    rule.sustained_samples = 3;
+    let json = rules_json(&[rule.clone()]);
+    assert!(json.contains("\"max_cpu_percent\":0,\"max_memory_mb\":0"));
+    assert!(json.contains("\"sustained_samples\":3,\"sustained_secs\":0,\"preset\":\"\""));
+    assert!(json.contains("\"throttle_nice\":19,\"cap_cpu_percent\":0,\"cap_memory_mb\":0"));
+    assert!(json.ends_with("\"container_pattern\":\"\",\"container_match\":\"substring\"}]\n"));
+    let csv = rules_csv(&[rule]);
+    let lines: Vec<&str> = csv.lines().collect();
+    assert!(lines[0] == RULES_CSV_HEADER);
+    assert!(
+        lines[1]
+            == "Hung nextest,medium,true,0,0,,substring,nextest,substring,false,0,false,3,0,,\
+terminate,leaves-first,19,0,0,,substring,,substring,,,substring"
+    );
+    assert!(parse_output_format("yaml").is_none());
+}
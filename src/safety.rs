@@ -0,0 +1,266 @@
+// Safety gate: processes reaper must never signal, whatever the rules say.
+//
+// The gate is built once per cycle (ancestries are walked up front) and
+// consulted before every termination.
+
+use crate::{list_pids, parse_stat_fields, parse_status_field, same_exe};
+use crate::{Config, Process, PROC_ROOT};
+
+// PF_KTHREAD from include/linux/sched.h, in the stat flags field.
+const PF_KTHREAD: u64 = 0x0020_0000;
+// kthreadd, the parent of every kernel thread.
+const KTHREADD_PID: i32 = 2;
+// Bounds ancestry walks in case a malformed /proc yields a cycle.
+const MAX_ANCESTRY_DEPTH: usize = 1024;
+
+#[derive(Debug, Clone)]
+pub(crate) struct SafetyGate {
+    pub(crate) root: String,
+    pub(crate) min_pid: i32,
+    pub(crate) protected_names: Vec<String>,
+    pub(crate) protected_exes: Vec<String>,
+    pub(crate) self_pid: i32,
+    // Reaper and everything above it, up to init.
+    pub(crate) self_ancestry: Vec<i32>,
+    // The invoking user's login shells and everything above them.
+    pub(crate) login_ancestry: Vec<i32>,
+}
+
+pub(crate) fn new_safety_gate(config: &Config) -> SafetyGate {
+    new_safety_gate_at(config, PROC_ROOT, std::process::id() as i32, invoking_uid())
+}
+
+pub(crate) fn new_safety_gate_at(
+    config: &Config,
+    root: &str,
+    self_pid: i32,
+    uid: u32,
+) -> SafetyGate {
+    let mut login_ancestry: Vec<i32> = Vec::new();
+    for shell in login_shells(root, uid) {
+        login_ancestry.extend(ancestry(root, shell));
+    }
+    SafetyGate {
+        root: root.to_string(),
+        min_pid: config.min_pid,
+        protected_names: config.protected_names.clone(),
+        protected_exes: config.protected_exes.clone(),
+        self_pid,
+        self_ancestry: ancestry(root, self_pid),
+        login_ancestry,
+    }
+}
+
+// Returns why `proc` must not be signalled, or None if it may be.
+pub(crate) fn protection_reason(gate: &SafetyGate, proc: &Process) -> Option<String> {
+    if proc.pid == 1 {
+        return Some("PID 1".to_string());
+    }
+    if is_kernel_thread(&gate.root, proc) {
+        return Some("kernel thread".to_string());
+    }
+    if proc.pid < gate.min_pid {
+        return Some(format!("PID below min_pid {}", gate.min_pid));
+    }
+    if proc.pid == gate.self_pid {
+        return Some("reaper itself".to_string());
+    }
+    if gate.self_ancestry.contains(&proc.pid) {
+        return Some("ancestor of reaper".to_string());
+    }
+    if gate.login_ancestry.contains(&proc.pid) {
+        return Some("login shell ancestry".to_string());
+    }
+    if gate.protected_names.contains(&proc.name) {
+        return Some(format!("protected name {}", proc.name));
+    }
+    let exe = if proc.exe.is_empty() {
+        read_exe(&gate.root, proc.pid)
+    } else {
+        proc.exe.clone()
+    };
+    if !exe.is_empty() {
+        if let Some(path) = gate.protected_exes.iter().find(|path| same_exe(&exe, path)) {
+            return Some(format!("protected exe {}", path));
+        }
+    }
+    None
+}
+
+fn is_kernel_thread(root: &str, proc: &Process) -> bool {
+    if proc.pid == KTHREADD_PID || proc.parent_pid == KTHREADD_PID {
+        return true;
+    }
+    // flags is stat field 9; fields[0] is field 3.
+    read_stat_field(root, proc.pid, 6)
+        .and_then(|flags| flags.parse::<u64>().ok())
+        .is_some_and(|flags| flags & PF_KTHREAD != 0)
+}
+
+fn read_stat_field(root: &str, pid: i32, index: usize) -> Option<String> {
+    let stat = std::fs::read_to_string(format!("{}/{}/stat", root, pid)).ok()?;
+    parse_stat_fields(&stat)?.get(index).cloned()
+}
+
+fn read_exe(root: &str, pid: i32) -> String {
+    match std::fs::read_link(format!("{}/{}/exe", root, pid)) {
+        Ok(path) => path.to_string_lossy().to_string(),
+        Err(_) => String::new(),
+    }
+}
+
+// `pid` and its ancestors, stopping before init.
+pub(crate) fn ancestry(root: &str, pid: i32) -> Vec<i32> {
+    let mut chain: Vec<i32> = Vec::new();
+    let mut current = pid;
+    while current > 1 && chain.len() < MAX_ANCESTRY_DEPTH && !chain.contains(&current) {
+        chain.push(current);
+        // ppid is stat field 4.
+        current = match read_stat_field(root, current, 1).and_then(|ppid| ppid.parse().ok()) {
+            Some(ppid) => ppid,
+            None => break,
+        };
+    }
+    chain
+}
+
+// Login shells are started with argv[0] prefixed by '-' (e.g. "-bash").
+pub(crate) fn login_shells(root: &str, uid: u32) -> Vec<i32> {
+    list_pids(root)
+        .into_iter()
+        .filter(|pid| {
+            let argv0_is_login = std::fs::read(format!("{}/{}/cmdline", root, pid))
+                .is_ok_and(|cmdline| cmdline.first() == Some(&b'-'));
+            argv0_is_login && process_uid(root, *pid) == Some(uid)
+        })
+        .collect()
+}
+
+fn process_uid(root: &str, pid: i32) -> Option<u32> {
+    let status = std::fs::read_to_string(format!("{}/{}/status", root, pid)).ok()?;
+    // Uid: real effective saved filesystem
+    parse_status_field(&status, "Uid")?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+// Under sudo the invoking user is the one in SUDO_UID, not root.
+fn invoking_uid() -> u32 {
+    match std::env::var("SUDO_UID")
+        .ok()
+        .and_then(|uid| uid.parse().ok())
+    {
+        Some(uid) => uid,
+        // SAFETY: getuid(2) has no preconditions and cannot fail.
+        None => unsafe { libc::getuid() },
+    }
+}
+
+#[cfg(test)]
+fn fake_process(pid: i32, ppid: i32, name: &str) -> Process {
+    let mut proc = crate::new_process(
+        pid,
+        name.to_string(),
+        name.to_string(),
+        99.9,
+        10,
+        crate::ProcessStatus::Running,
+    );
+    proc.parent_pid = ppid;
+    proc
+}
+
+#[cfg(test)]
+fn write_uid(root: &str, pid: i32, uid: u32) {
+    let path = format!("{}/{}/status", root, pid);
+    let mut status = std::fs::read_to_string(&path).expect("fake status");
+    status.push_str(&format!("Uid:\t{}\t{}\t{}\t{}\n", uid, uid, uid, uid));
+    std::fs::write(&path, status).expect("write fake status");
+}
+
+// init(1) -> sshd(400) -> -bash(500, uid 1000) -> reaper(600)
+//         -> login(410) -> -zsh(510, uid 1000)
+//         -> cron(420) -> spin(700)
+#[cfg(test)]
+fn fake_session_root(name: &str) -> String {
+    let root = crate::fake_proc_root(name);
+    crate::write_fake_pid(&root, 1, 0, "systemd", 'S', 100, &["/sbin/init"]);
+    crate::write_fake_pid(&root, 2, 0, "kthreadd", 'S', 0, &[]);
+    crate::write_fake_pid(&root, 400, 1, "sshd", 'S', 100, &["sshd: ci"]);
+    crate::write_fake_pid(&root, 410, 1, "login", 'S', 100, &["login"]);
+    crate::write_fake_pid(&root, 420, 1, "cron", 'S', 100, &["/usr/sbin/cron"]);
+    crate::write_fake_pid(&root, 500, 400, "bash", 'S', 100, &["-bash"]);
+    crate::write_fake_pid(&root, 510, 410, "zsh", 'S', 100, &["-zsh"]);
+    crate::write_fake_pid(&root, 600, 500, "ruchy-reaper", 'S', 100, &["ruchy-reaper"]);
+    crate::write_fake_pid(&root, 700, 420, "spin", 'R', 100, &["./spin"]);
+    write_uid(&root, 500, 1000);
+    write_uid(&root, 510, 1000);
+    root
+}
+
+#[test]
+fn test_protection_reason_refuses_protected_processes() {
+    let root = fake_session_root("safety-gate");
+    let mut config = crate::default_config();
+    config.min_pid = 300;
+    config.protected_names = vec!["cron".to_string()];
+    let gate = new_safety_gate_at(&config, &root, 600, 1000);
+    let reason =
+        |pid: i32, ppid: i32, name: &str| protection_reason(&gate, &fake_process(pid, ppid, name));
+    assert!(reason(1, 0, "systemd") == Some("PID 1".to_string()));
+    assert!(reason(2, 0, "kthreadd") == Some("kernel thread".to_string()));
+    assert!(reason(350, 2, "kworker/0:1") == Some("kernel thread".to_string()));
+    assert!(reason(250, 1, "udevd") == Some("PID below min_pid 300".to_string()));
+    assert!(reason(600, 500, "ruchy-reaper") == Some("reaper itself".to_string()));
+    assert!(reason(500, 400, "bash") == Some("ancestor of reaper".to_string()));
+    assert!(reason(400, 1, "sshd") == Some("ancestor of reaper".to_string()));
+    assert!(reason(510, 410, "zsh") == Some("login shell ancestry".to_string()));
+    assert!(reason(410, 1, "login") == Some("login shell ancestry".to_string()));
+    assert!(reason(420, 1, "cron") == Some("protected name cron".to_string()));
+    assert!(
+        reason(700, 420, "spin").is_none(),
+        "An ordinary process may be signalled"
+    );
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_protection_reason_kernel_flag_and_exe() {
+    let root = crate::fake_proc_root("safety-flags");
+    let stat = "900 (kworker/u8:2) I 7 900 900 0 -1 2129984 0 0 0 0 0 0 0 0 20 0 1 0 1000 0 0";
+    std::fs::create_dir_all(format!("{}/900", root)).expect("create pid dir");
+    std::fs::write(format!("{}/900/stat", root), stat).expect("write stat");
+    let mut config = crate::default_config();
+    config.protected_exes = vec!["/usr/bin/dockerd".to_string()];
+    let gate = new_safety_gate_at(&config, &root, 600, 1000);
+    let kworker = fake_process(900, 7, "kworker/u8:2");
+    assert!(protection_reason(&gate, &kworker) == Some("kernel thread".to_string()));
+    let mut dockerd = fake_process(901, 1, "dockerd");
+    dockerd.exe = "/usr/bin/dockerd (deleted)".to_string();
+    assert!(
+        protection_reason(&gate, &dockerd) == Some("protected exe /usr/bin/dockerd".to_string())
+    );
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_login_shells_filter_by_uid() {
+    let root = fake_session_root("safety-login");
+    assert!(login_shells(&root, 1000) == vec![500, 510]);
+    assert!(login_shells(&root, 0).is_empty());
+    assert!(ancestry(&root, 600) == vec![600, 500, 400]);
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_live_gate_protects_test_runner_ancestry() {
+    let gate = new_safety_gate(&crate::default_config());
+    let me = crate::parse_proc_status(std::process::id() as i32).expect("own process");
+    assert!(protection_reason(&gate, &me) == Some("reaper itself".to_string()));
+    let parent = crate::parse_proc_status(me.parent_pid);
+    if let Some(parent) = parent.filter(|parent| parent.pid > 1) {
+        assert!(protection_reason(&gate, &parent).is_some());
+    }
+}
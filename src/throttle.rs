@@ -0,0 +1,342 @@
+// Throttling: demote a CPU hog instead of killing it. The nice value is
+// raised and the I/O class set to idle, and the originals are remembered so
+// the daemon can put them back once the process calms down.
+//
+// Both settings are per thread on Linux, so every task of the process is
+// changed; the thread group leader's values are the ones restored.
+//
+// The table is kept in `throttle_file` so a restarted daemon can still
+// restore what an earlier one changed, and a daemon that shuts down
+// restores everything itself.
+
+use crate::frozen::{escape_field, replace_file, unescape_field};
+use crate::{list_pids, parse_proc_status, verify_identity, ActionResult, Process, PROC_ROOT};
+
+// include/uapi/linux/ioprio.h
+const IOPRIO_WHO_PROCESS: i32 = 1;
+const IOPRIO_CLASS_SHIFT: i32 = 13;
+const IOPRIO_CLASS_IDLE: i32 = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Throttle {
+    pub(crate) pid: i32,
+    pub(crate) start_ticks: i64,
+    pub(crate) rule_name: String,
+    // The process is restored once its CPU drops below this.
+    pub(crate) max_cpu_percent: f64,
+    pub(crate) original_nice: i32,
+    pub(crate) original_ioprio: i32,
+}
+
+// Processes the daemon has throttled and not yet restored.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct ThrottleTable {
+    pub(crate) entries: Vec<Throttle>,
+}
+
+pub(crate) fn new_throttle_table() -> ThrottleTable {
+    ThrottleTable::default()
+}
+
+pub(crate) fn is_throttled(table: &ThrottleTable, proc: &Process) -> bool {
+    table
+        .entries
+        .iter()
+        .any(|entry| entry.pid == proc.pid && entry.start_ticks == proc.start_ticks)
+}
+
+// Raises the nice value to at least `nice` and moves the process to the idle
+// I/O class, recording the previous settings in `table`.
+pub(crate) fn throttle_process(
+    table: &mut ThrottleTable,
+    proc: &Process,
+    rule_name: &str,
+    max_cpu_percent: f64,
+    nice: i32,
+) -> ActionResult {
+    if let Some(refusal) = verify_identity(proc) {
+        return refusal;
+    }
+    let original_nice = match get_nice(proc.pid) {
+        Ok(current) => current,
+        Err(errno) => return crate::action_result_from_errno(errno),
+    };
+    let original_ioprio = match get_ioprio(proc.pid) {
+        Ok(current) => current,
+        Err(errno) => return crate::action_result_from_errno(errno),
+    };
+    let idle = IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT;
+    let applied = for_each_task(proc.pid, |tid| {
+        set_nice(tid, nice.max(original_nice))?;
+        set_ioprio(tid, idle)
+    });
+    if let Err(errno) = applied {
+        // Leave nothing half-done behind.
+        let _ = restore(proc.pid, original_nice, original_ioprio);
+        return crate::action_result_from_errno(errno);
+    }
+    table.entries.push(Throttle {
+        pid: proc.pid,
+        start_ticks: proc.start_ticks,
+        rule_name: rule_name.to_string(),
+        max_cpu_percent,
+        original_nice,
+        original_ioprio,
+    });
+    ActionResult::Throttled
+}
+
+// Restores every throttled process in `procs` whose CPU is back under its
+// rule's threshold, and forgets processes that have exited. Returns the
+// restored processes with the outcome of each.
+pub(crate) fn revert_recovered(
+    table: &mut ThrottleTable,
+    procs: &[Process],
+) -> Vec<(Process, ActionResult)> {
+    let mut reverted: Vec<(Process, ActionResult)> = Vec::new();
+    table.entries.retain(|entry| {
+        let live = procs
+            .iter()
+            .find(|proc| proc.pid == entry.pid && proc.start_ticks == entry.start_ticks);
+        let proc = match live {
+            Some(proc) => proc,
+            None => return false,
+        };
+        if proc.cpu_usage >= entry.max_cpu_percent {
+            return true;
+        }
+        let result = match restore(entry.pid, entry.original_nice, entry.original_ioprio) {
+            Ok(()) => ActionResult::Unthrottled,
+            Err(errno) => crate::action_result_from_errno(errno),
+        };
+        reverted.push((proc.clone(), result));
+        // A failed restore is reported once, not on every cycle.
+        false
+    });
+    reverted
+}
+
+// Restores every entry whose process is still running, for a daemon on its
+// way out, and empties the table.
+pub(crate) fn restore_all(table: &mut ThrottleTable) -> Vec<(Process, ActionResult)> {
+    let mut restored: Vec<(Process, ActionResult)> = Vec::new();
+    for entry in table.entries.drain(..) {
+        let live = match parse_proc_status(entry.pid) {
+            Some(live) if live.start_ticks == entry.start_ticks => live,
+            _ => continue,
+        };
+        let result = match restore(entry.pid, entry.original_nice, entry.original_ioprio) {
+            Ok(()) => ActionResult::Unthrottled,
+            Err(errno) => crate::action_result_from_errno(errno),
+        };
+        restored.push((live, result));
+    }
+    restored
+}
+
+// One tab-separated record per line, as in `frozen_file`. A missing file
+// means nothing is throttled; lines that do not parse are skipped.
+pub(crate) fn load_throttles(path: &str) -> ThrottleTable {
+    let entries = match std::fs::read_to_string(path) {
+        Ok(text) => text.lines().filter_map(parse_entry).collect(),
+        Err(_) => Vec::new(),
+    };
+    ThrottleTable { entries }
+}
+
+pub(crate) fn save_throttles(path: &str, table: &ThrottleTable) -> std::io::Result<()> {
+    let text: String = table.entries.iter().map(format_entry).collect();
+    replace_file(path, &text)
+}
+
+fn format_entry(entry: &Throttle) -> String {
+    format!(
+        "{}\t{}\t{}\t{}\t{}\t{}\n",
+        entry.pid,
+        entry.start_ticks,
+        entry.max_cpu_percent,
+        entry.original_nice,
+        entry.original_ioprio,
+        escape_field(&entry.rule_name)
+    )
+}
+
+fn parse_entry(line: &str) -> Option<Throttle> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    if fields.len() != 6 {
+        return None;
+    }
+    Some(Throttle {
+        pid: fields[0].parse().ok()?,
+        start_ticks: fields[1].parse().ok()?,
+        max_cpu_percent: fields[2].parse().ok()?,
+        original_nice: fields[3].parse().ok()?,
+        original_ioprio: fields[4].parse().ok()?,
+        rule_name: unescape_field(fields[5]),
+    })
+}
+
+fn restore(pid: i32, nice: i32, ioprio: i32) -> Result<(), i32> {
+    for_each_task(pid, |tid| {
+        set_nice(tid, nice)?;
+        set_ioprio(tid, ioprio)
+    })
+}
+
+// Threads that exit midway are skipped; the first other error stops.
+fn for_each_task<F>(pid: i32, apply: F) -> Result<(), i32>
+where
+    F: Fn(i32) -> Result<(), i32>,
+{
+    let mut tasks = list_pids(&format!("{}/{}/task", PROC_ROOT, pid));
+    if tasks.is_empty() {
+        tasks.push(pid);
+    }
+    for tid in tasks {
+        match apply(tid) {
+            Ok(()) | Err(libc::ESRCH) if tid != pid => {}
+            result => result?,
+        }
+    }
+    Ok(())
+}
+
+fn last_errno() -> i32 {
+    std::io::Error::last_os_error().raw_os_error().unwrap_or(0)
+}
+
+fn get_nice(tid: i32) -> Result<i32, i32> {
+    // -1 is a valid nice value, so errno has to be cleared and checked.
+    // SAFETY: __errno_location returns this thread's errno slot, and
+    // getpriority takes plain integers.
+    let nice = unsafe {
+        *libc::__errno_location() = 0;
+        libc::getpriority(libc::PRIO_PROCESS, tid as libc::id_t)
+    };
+    match last_errno() {
+        0 => Ok(nice),
+        errno => Err(errno),
+    }
+}
+
+pub(crate) fn set_nice(tid: i32, nice: i32) -> Result<(), i32> {
+    // SAFETY: setpriority takes plain integers.
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, tid as libc::id_t, nice) } == 0 {
+        return Ok(());
+    }
+    Err(last_errno())
+}
+
+pub(crate) fn get_ioprio(tid: i32) -> Result<i32, i32> {
+    // SAFETY: ioprio_get takes two integers and returns the priority or -1.
+    let ioprio = unsafe { libc::syscall(libc::SYS_ioprio_get, IOPRIO_WHO_PROCESS, tid) };
+    if ioprio < 0 {
+        return Err(last_errno());
+    }
+    Ok(ioprio as i32)
+}
+
+fn set_ioprio(tid: i32, ioprio: i32) -> Result<(), i32> {
+    // SAFETY: ioprio_set takes three integers.
+    let rc = unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, tid, ioprio) };
+    if rc == 0 {
+        return Ok(());
+    }
+    Err(last_errno())
+}
+
+// A sleeper already at the weakest CPU priority: restoring its nice value
+// then needs no CAP_SYS_NICE, so the restore is checked without root.
+#[cfg(test)]
+pub(crate) fn spawn_niced_sleeper() -> std::process::Child {
+    let child = std::process::Command::new("sleep")
+        .arg("30")
+        .spawn()
+        .expect("spawn sleep");
+    set_nice(child.id() as i32, 19).expect("raise nice");
+    child
+}
+
+#[test]
+fn test_throttle_and_revert_child() {
+    let mut child = spawn_niced_sleeper();
+    let mut proc = crate::parse_proc_status(child.id() as i32).expect("spawned child");
+    let original = (get_nice(proc.pid), get_ioprio(proc.pid));
+    let mut table = new_throttle_table();
+    let result = throttle_process(&mut table, &proc, "Spin", 90f64, 19);
+    let throttled_nice = get_nice(proc.pid);
+    let throttled_ioprio = get_ioprio(proc.pid);
+    proc.cpu_usage = 95f64;
+    let still_busy = revert_recovered(&mut table, std::slice::from_ref(&proc));
+    proc.cpu_usage = 2f64;
+    let recovered = revert_recovered(&mut table, std::slice::from_ref(&proc));
+    let restored = (get_nice(proc.pid), get_ioprio(proc.pid));
+    let _ = child.kill();
+    let _ = child.wait();
+    assert!(result == ActionResult::Throttled);
+    assert!(throttled_nice == Ok(19));
+    assert!(throttled_ioprio == Ok(IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT));
+    assert!(still_busy.is_empty(), "Still above max_cpu_percent");
+    assert!(recovered.len() == 1 && table.entries.is_empty());
+    assert!(recovered[0].1 == ActionResult::Unthrottled);
+    assert!(restored == original);
+}
+
+#[test]
+fn test_throttle_file_round_trip_and_restore_all() {
+    let path = std::env::temp_dir()
+        .join(format!(
+            "reaper-throttle-{}/throttled.tsv",
+            std::process::id()
+        ))
+        .to_string_lossy()
+        .to_string();
+    let mut child = spawn_niced_sleeper();
+    let proc = crate::parse_proc_status(child.id() as i32).expect("spawned child");
+    let original = get_ioprio(proc.pid);
+    let mut table = new_throttle_table();
+    let result = throttle_process(&mut table, &proc, "Spin\tloop", 90f64, 19);
+    save_throttles(&path, &table).expect("save");
+    // A restarted daemon picks up where the last one left off.
+    let mut loaded = load_throttles(&path);
+    let restored = restore_all(&mut loaded);
+    let ioprio = get_ioprio(proc.pid);
+    let _ = child.kill();
+    let _ = child.wait();
+    assert!(result == ActionResult::Throttled);
+    assert!(loaded.entries.is_empty());
+    assert!(load_throttles(&path) == table && table.entries[0].rule_name == "Spin\tloop");
+    assert!(restored.len() == 1 && restored[0].1 == ActionResult::Unthrottled);
+    assert!(ioprio == original);
+    let mut gone = new_throttle_table();
+    gone.entries = table.entries.clone();
+    assert!(
+        restore_all(&mut gone).is_empty(),
+        "Exited processes are skipped"
+    );
+    let _ = std::fs::remove_dir_all(std::path::Path::new(&path).parent().expect("dir"));
+}
+
+#[test]
+fn test_revert_forgets_exited_processes() {
+    let mut table = new_throttle_table();
+    table.entries.push(Throttle {
+        pid: crate::NONEXISTENT_PID,
+        start_ticks: 1,
+        rule_name: "Spin".to_string(),
+        max_cpu_percent: 90f64,
+        original_nice: 0,
+        original_ioprio: 0,
+    });
+    assert!(revert_recovered(&mut table, &[]).is_empty());
+    assert!(table.entries.is_empty());
+    let proc = crate::new_process(
+        4242,
+        "spin".to_string(),
+        String::new(),
+        99f64,
+        0,
+        crate::ProcessStatus::Running,
+    );
+    assert!(!is_throttled(&table, &proc));
+}
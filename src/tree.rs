@@ -0,0 +1,457 @@
+// Process-tree termination: a matched process and every descendant, found
+// through ppid links, signalled under one grace period with a result each.
+//
+// Leaves go first so a harness cannot respawn children it is about to lose.
+// In process-group mode one killpg reaches the group; descendants that left
+// the group are still signalled by PID.
+
+use crate::detector::RuleMatch;
+use crate::safety::{protection_reason, SafetyGate};
+use crate::{action_result_from_errno, close_fd, list_pids, open_pidfd, parse_proc_status_at};
+use crate::{parse_stat_fields, pidfd_send_signal, process_exited, read_boot_time, send_signal};
+use crate::{unix_now, verify_identity_at, wait_for_pidfd};
+use crate::{ActionResult, Process, TreeSignal, KILL_WAIT_MS, TERMINATE_POLL_MS};
+
+#[derive(Debug)]
+struct Member {
+    proc: Process,
+    pidfd: Option<i32>,
+    // Covered by the killpg in process-group mode.
+    in_group: bool,
+    // None while the member is still being terminated.
+    result: Option<ActionResult>,
+}
+
+// `root` and its descendants in `procs`, deepest first and `root` last.
+pub(crate) fn tree_leaves_first(root: &Process, procs: &[Process]) -> Vec<Process> {
+    let mut found: Vec<(Process, usize)> = vec![(root.clone(), 0)];
+    let mut next = 0;
+    while next < found.len() {
+        let (parent, depth) = (found[next].0.pid, found[next].1);
+        for child in procs.iter().filter(|proc| proc.parent_pid == parent) {
+            // A malformed snapshot must not loop forever.
+            if !found.iter().any(|(seen, _)| seen.pid == child.pid) {
+                found.push((child.clone(), depth + 1));
+            }
+        }
+        next += 1;
+    }
+    // sort_by_key is stable: siblings keep their PID order.
+    found.sort_by_key(|(_, depth)| std::cmp::Reverse(*depth));
+    found.into_iter().map(|(proc, _)| proc).collect()
+}
+
+// Everything terminate_tree would signal, in signalling order. A root whose
+// PID now names another process has no tree: its PID's children are not its.
+// Every read goes to the procfs at `proc_root`.
+pub(crate) fn tree_members(proc_root: &str, root: &Process, mode: TreeSignal) -> Vec<Process> {
+    if verify_identity_at(proc_root, root).is_some() {
+        return vec![root.clone()];
+    }
+    let procs = snapshot(proc_root);
+    let mut members = tree_leaves_first(root, &procs);
+    if mode == TreeSignal::ProcessGroup && read_pgid(proc_root, root.pid) == Some(root.pid) {
+        // The group may hold processes that are not descendants.
+        let outsiders: Vec<Process> = procs
+            .into_iter()
+            .filter(|proc| read_pgid(proc_root, proc.pid) == Some(root.pid))
+            .filter(|proc| !members.iter().any(|member| member.pid == proc.pid))
+            .collect();
+        members.splice(0..0, outsiders);
+    }
+    members
+}
+
+pub(crate) fn terminate_tree(
+    proc_root: &str,
+    root: Process,
+    grace_period: i64,
+    gate: &SafetyGate,
+    mode: TreeSignal,
+) -> Vec<(Process, ActionResult)> {
+    // The root is pinned and verified before its PID is used to find
+    // descendants; if it is not the scanned process, nothing is signalled.
+    let pinned = prepare(proc_root, root.clone(), gate);
+    if let Some(result) = pinned.result {
+        release(&pinned);
+        return vec![(root, result)];
+    }
+    let mut members: Vec<Member> = tree_members(proc_root, &root, mode)
+        .into_iter()
+        .filter(|proc| proc.pid != root.pid)
+        .map(|proc| prepare(proc_root, proc, gate))
+        .collect();
+    // Had the root exited before the walk, its PID could already name a
+    // new process, and the walk that process's children.
+    if !is_alive(proc_root, &pinned) {
+        members.iter().for_each(release);
+        release(&pinned);
+        return vec![(root, ActionResult::NotFound)];
+    }
+    members.push(pinned);
+    let group = match mode {
+        TreeSignal::ProcessGroup => signal_group(proc_root, &root, &mut members),
+        TreeSignal::LeavesFirst => None,
+    };
+    signal_pending(proc_root, &mut members, group, libc::SIGTERM);
+    let grace = std::time::Duration::from_secs(grace_period.max(0) as u64);
+    wait_pending(&mut members, grace);
+    signal_pending(proc_root, &mut members, group, libc::SIGKILL);
+    wait_pending(&mut members, std::time::Duration::from_millis(KILL_WAIT_MS));
+    members
+        .into_iter()
+        .map(|member| {
+            release(&member);
+            let result = member.result.unwrap_or(ActionResult::TimedOut);
+            (member.proc, result)
+        })
+        .collect()
+}
+
+// A descendant is reported under the root's match, with the link spelled out.
+pub(crate) fn member_match(rule_match: &RuleMatch, member: &Process) -> RuleMatch {
+    let mut explained = rule_match.clone();
+    if member.pid != rule_match.process.pid {
+        let root = &rule_match.process;
+        explained
+            .reasons
+            .insert(0, format!("in tree of PID {} ({})", root.pid, root.name));
+        explained.process = member.clone();
+    }
+    explained
+}
+
+// Applies the safety gate and pins the member with a pidfd, as
+// terminate_verified does for a single process.
+fn prepare(proc_root: &str, proc: Process, gate: &SafetyGate) -> Member {
+    let mut member = Member {
+        proc,
+        pidfd: None,
+        in_group: false,
+        result: None,
+    };
+    if protection_reason(gate, &member.proc).is_some() {
+        member.result = Some(ActionResult::Protected);
+        return member;
+    }
+    match open_pidfd(member.proc.pid) {
+        Ok(fd) => member.pidfd = Some(fd),
+        Err(libc::ESRCH) => member.result = Some(ActionResult::NotFound),
+        Err(_) => {}
+    }
+    if member.result.is_none() {
+        member.result = verify_identity_at(proc_root, &member.proc);
+    }
+    member
+}
+
+fn release(member: &Member) {
+    if let Some(fd) = member.pidfd {
+        close_fd(fd);
+    }
+}
+
+fn is_alive(proc_root: &str, member: &Member) -> bool {
+    match member.pidfd {
+        Some(fd) => !wait_for_pidfd(fd, std::time::Duration::ZERO),
+        None => verify_identity_at(proc_root, &member.proc).is_none(),
+    }
+}
+
+// Returns the process group to killpg, or None when it cannot be used: the
+// root does not lead a group, or the group holds a protected process.
+fn signal_group(proc_root: &str, root: &Process, members: &mut [Member]) -> Option<i32> {
+    if read_pgid(proc_root, root.pid) != Some(root.pid) {
+        return None;
+    }
+    let group: Vec<usize> = (0..members.len())
+        .filter(|index| read_pgid(proc_root, members[*index].proc.pid) == Some(root.pid))
+        .collect();
+    let protected = group
+        .iter()
+        .any(|index| members[*index].result == Some(ActionResult::Protected));
+    if protected {
+        return None;
+    }
+    for index in group {
+        members[index].in_group = true;
+    }
+    Some(root.pid)
+}
+
+fn signal_pending(proc_root: &str, members: &mut [Member], group: Option<i32>, signal: i32) {
+    let mut group = group;
+    if let Some(pgid) = group {
+        if members
+            .iter()
+            .any(|member| member.in_group && member.result.is_none())
+        {
+            match send_signal(-pgid, signal) {
+                Ok(()) | Err(libc::ESRCH) => {}
+                // Fall back to signalling the group's members one by one.
+                Err(_) => group = None,
+            }
+        }
+    }
+    for member in members.iter_mut() {
+        if member.result.is_some() || (group.is_some() && member.in_group) {
+            continue;
+        }
+        member.result = signal_member(proc_root, member, signal);
+    }
+}
+
+// Signals one member; Some(result) when that settles its outcome.
+fn signal_member(proc_root: &str, member: &Member, signal: i32) -> Option<ActionResult> {
+    let sent = match member.pidfd {
+        Some(fd) => pidfd_send_signal(fd, signal),
+        // Without a pidfd the PID may have been recycled during the grace
+        // period; if so the member has exited.
+        None if signal == libc::SIGKILL
+            && verify_identity_at(proc_root, &member.proc).is_some() =>
+        {
+            return Some(ActionResult::Success);
+        }
+        None => send_signal(member.proc.pid, signal),
+    };
+    match sent {
+        Ok(()) => None,
+        // Exited between the last poll and SIGKILL.
+        Err(libc::ESRCH) if signal == libc::SIGKILL => Some(ActionResult::Success),
+        Err(errno) => Some(action_result_from_errno(errno)),
+    }
+}
+
+// Waits, under one deadline for the whole tree, until every pending member
+// has exited; exited members are marked Success.
+fn wait_pending(members: &mut [Member], timeout: std::time::Duration) {
+    let deadline = std::time::Instant::now() + timeout;
+    loop {
+        for member in members.iter_mut().filter(|member| member.result.is_none()) {
+            let exited = match member.pidfd {
+                Some(fd) => wait_for_pidfd(fd, std::time::Duration::ZERO),
+                None => process_exited(member.proc.pid),
+            };
+            if exited {
+                member.result = Some(ActionResult::Success);
+            }
+        }
+        let now = std::time::Instant::now();
+        if members.iter().all(|member| member.result.is_some()) || now >= deadline {
+            return;
+        }
+        let poll = std::time::Duration::from_millis(TERMINATE_POLL_MS);
+        std::thread::sleep(poll.min(deadline - now));
+    }
+}
+
+fn snapshot(root: &str) -> Vec<Process> {
+    let boot_time = read_boot_time(root);
+    let now = unix_now();
+    list_pids(root)
+        .into_iter()
+        .filter_map(|pid| parse_proc_status_at(root, pid, boot_time, now))
+        .collect()
+}
+
+fn read_pgid(root: &str, pid: i32) -> Option<i32> {
+    let stat = std::fs::read_to_string(format!("{}/{}/stat", root, pid)).ok()?;
+    // pgrp is stat field 5; fields[0] is field 3.
+    parse_stat_fields(&stat)?.get(2)?.parse().ok()
+}
+
+#[cfg(test)]
+fn fake_process(pid: i32, ppid: i32) -> Process {
+    let mut proc = crate::new_process(
+        pid,
+        format!("p{}", pid),
+        String::new(),
+        0f64,
+        0,
+        crate::ProcessStatus::Running,
+    );
+    proc.parent_pid = ppid;
+    proc
+}
+
+// sh running two sleeps; returns once both have started.
+#[cfg(test)]
+fn spawn_harness(own_group: bool) -> (std::process::Child, Process) {
+    use std::os::unix::process::CommandExt;
+    let mut command = std::process::Command::new("sh");
+    command
+        .arg("-c")
+        .arg("sleep 30 & sleep 30 & echo ready; wait")
+        .stdout(std::process::Stdio::piped());
+    if own_group {
+        command.process_group(0);
+    }
+    let mut child = command.spawn().expect("spawn sh");
+    let mut ready = [0u8; 6];
+    std::io::Read::read_exact(child.stdout.as_mut().expect("stdout"), &mut ready)
+        .expect("harness ready");
+    let proc = crate::parse_proc_status(child.id() as i32).expect("harness process");
+    (child, proc)
+}
+
+#[cfg(test)]
+fn test_gate() -> SafetyGate {
+    let mut config = crate::default_config();
+    config.min_pid = 0;
+    crate::safety::new_safety_gate(&config)
+}
+
+#[test]
+fn test_tree_leaves_first_orders_by_depth() {
+    // 100 -> 110 -> 111, 100 -> 120, with 200 unrelated and 300 <-> 301 a cycle.
+    let procs = vec![
+        fake_process(100, 1),
+        fake_process(110, 100),
+        fake_process(111, 110),
+        fake_process(120, 100),
+        fake_process(200, 1),
+        fake_process(300, 301),
+        fake_process(301, 300),
+    ];
+    let pids = |root: &Process| -> Vec<i32> {
+        tree_leaves_first(root, &procs)
+            .iter()
+            .map(|proc| proc.pid)
+            .collect()
+    };
+    assert!(pids(&procs[0]) == vec![111, 110, 120, 100]);
+    assert!(
+        pids(&procs[4]) == vec![200],
+        "A childless root is its own tree"
+    );
+    assert!(pids(&procs[5]) == vec![301, 300], "Cycles terminate");
+}
+
+#[test]
+fn test_tree_members_reads_fixture_root() {
+    // 500 -> 501 -> 502, with 600 unrelated; none of these PIDs is live.
+    let root = crate::fake_proc_root("tree-fixture");
+    for (pid, ppid) in [(500, 1), (501, 500), (502, 501), (600, 1)] {
+        crate::write_fake_pid(&root, pid, ppid, "sh", 'S', 1024, &["sh"]);
+    }
+    let leader =
+        parse_proc_status_at(&root, 500, read_boot_time(&root), unix_now()).expect("fixture root");
+    let pids = |proc: &Process| -> Vec<i32> {
+        tree_members(&root, proc, TreeSignal::LeavesFirst)
+            .iter()
+            .map(|member| member.pid)
+            .collect()
+    };
+    assert!(pids(&leader) == vec![502, 501, 500]);
+    let mut stale = leader.clone();
+    stale.start_ticks += 1;
+    assert!(pids(&stale) == vec![500], "A reused root PID has no tree");
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_member_match_explains_descendants() {
+    let rule = crate::new_detection_rule(
+        "Hung nextest".to_string(),
+        crate::Priority::Medium,
+        0f64,
+        0,
+        String::new(),
+        String::new(),
+        true,
+    );
+    let root = fake_process(100, 1);
+    let rule_match = crate::detector::new_rule_match(&rule, root.clone(), vec!["x".to_string()]);
+    assert!(member_match(&rule_match, &root).reasons == vec!["x".to_string()]);
+    let child = member_match(&rule_match, &fake_process(110, 100));
+    assert!(child.process.pid == 110 && child.rule_name == "Hung nextest");
+    assert!(child.reasons == vec!["in tree of PID 100 (p100)".to_string(), "x".to_string()]);
+}
+
+#[test]
+fn test_terminate_tree_leaves_first() {
+    let (mut child, root) = spawn_harness(false);
+    let results = terminate_tree(
+        crate::PROC_ROOT,
+        root.clone(),
+        5,
+        &test_gate(),
+        TreeSignal::LeavesFirst,
+    );
+    let _ = child.wait();
+    assert!(results.len() == 3, "sh and both sleeps: {:?}", results);
+    assert!(results[2].0.pid == root.pid, "The root is signalled last");
+    assert!(results[0].0.name == "sleep" && results[1].0.name == "sleep");
+    assert!(results
+        .iter()
+        .all(|(_, result)| *result == ActionResult::Success));
+}
+
+#[test]
+fn test_terminate_tree_by_process_group() {
+    let (mut child, root) = spawn_harness(true);
+    let members = tree_members(crate::PROC_ROOT, &root, TreeSignal::ProcessGroup);
+    assert!(members.len() == 3);
+    let results = terminate_tree(
+        crate::PROC_ROOT,
+        root,
+        5,
+        &test_gate(),
+        TreeSignal::ProcessGroup,
+    );
+    let _ = child.wait();
+    assert!(results.len() == 3);
+    assert!(results
+        .iter()
+        .all(|(_, result)| *result == ActionResult::Success));
+}
+
+#[test]
+fn test_terminate_tree_skips_protected_members() {
+    let (mut child, root) = spawn_harness(false);
+    let mut config = crate::default_config();
+    config.min_pid = 0;
+    config.protected_names = vec!["sleep".to_string()];
+    let gate = crate::safety::new_safety_gate(&config);
+    let results = terminate_tree(crate::PROC_ROOT, root, 5, &gate, TreeSignal::LeavesFirst);
+    let _ = child.wait();
+    for (proc, _) in &results[..2] {
+        let _ = send_signal(proc.pid, libc::SIGKILL);
+    }
+    assert!(results[0].1 == ActionResult::Protected && results[1].1 == ActionResult::Protected);
+    assert!(
+        results[2].1 == ActionResult::Success,
+        "The unprotected root still goes"
+    );
+}
+
+#[test]
+fn test_terminate_tree_refuses_reused_root_pid() {
+    let (mut child, root) = spawn_harness(false);
+    // The scanned process, as if its PID had since gone to the harness.
+    let mut stale = root.clone();
+    stale.start_ticks -= 1;
+    assert!(tree_members(crate::PROC_ROOT, &stale, TreeSignal::LeavesFirst).len() == 1);
+    let refused = terminate_tree(
+        crate::PROC_ROOT,
+        stale.clone(),
+        5,
+        &test_gate(),
+        TreeSignal::LeavesFirst,
+    );
+    let survivors = tree_members(crate::PROC_ROOT, &root, TreeSignal::LeavesFirst);
+    let results = terminate_tree(
+        crate::PROC_ROOT,
+        root,
+        5,
+        &test_gate(),
+        TreeSignal::LeavesFirst,
+    );
+    let _ = child.wait();
+    assert!(refused.len() == 1 && refused[0].1 == ActionResult::IdentityMismatch);
+    assert!(
+        survivors.len() == 3,
+        "The new process's children are untouched"
+    );
+    assert!(results.len() == 3);
+}